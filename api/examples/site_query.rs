@@ -0,0 +1,302 @@
+/*
+ * examples/site_query.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Benchmark for reading a whole site's attribution from DynamoDB.
+//!
+//! This compares the read capacity consumed by the previous implementation,
+//! a filtered Scan of the whole table, with a Query on the site's partition.
+//! It also checks that the storage backend returns the same pages as the Scan,
+//! both sequentially and with parallel split queries.
+//!
+//! It is intended to be run against [DynamoDB Local], never a real table:
+//! ```text
+//! java -Djava.library.path=./DynamoDBLocal_lib -jar DynamoDBLocal.jar -inMemory
+//! cargo run --example site_query
+//! ```
+//!
+//! The endpoint can be changed with `DYNAMODB_ENDPOINT` (default
+//! `http://localhost:8000`), and the amount of data with `BENCH_SITES`
+//! and `BENCH_PAGES` (pages per site).
+//!
+//! [DynamoDB Local]: https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/DynamoDBLocal.html
+
+use attribution_metadata::storage::{DynamoStorage, Storage};
+use aws_sdk_dynamodb::config::{Builder as ConfigBuilder, Credentials, Region};
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, AttributeValue, KeySchemaElement, KeyType,
+    ProvisionedThroughput, PutRequest, ReturnConsumedCapacity, ScalarAttributeType,
+    WriteRequest,
+};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use std::collections::HashMap;
+use std::env;
+use std::time::Instant;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const TABLE: &str = "attribution_metadata";
+const TARGET_SITE: &str = "site-0";
+
+fn env_number(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let endpoint =
+        env::var("DYNAMODB_ENDPOINT").unwrap_or_else(|_| "http://localhost:8000".into());
+    let sites = env_number("BENCH_SITES", 20);
+    let pages = env_number("BENCH_PAGES", 500);
+
+    let config = ConfigBuilder::new()
+        .endpoint_url(&endpoint)
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("local", "local", None, None, "local"))
+        .build();
+
+    let dynamo = DynamoClient::from_conf(config);
+
+    println!("Seeding {sites} sites with {pages} pages each at {endpoint}");
+    create_table(&dynamo).await?;
+    seed(&dynamo, sites, pages).await?;
+
+    // Previous implementation, reading every site's items
+    let (scan_slugs, scan_capacity) = scan_site(&dynamo).await?;
+    println!(
+        "Scan:  {} pages, {scan_capacity:.1} capacity units",
+        scan_slugs.len(),
+    );
+
+    // Current implementation, reading only the site's partition
+    let (query_slugs, query_capacity) = query_site(&dynamo).await?;
+    println!(
+        "Query: {} pages, {query_capacity:.1} capacity units ({:.1}x less)",
+        query_slugs.len(),
+        scan_capacity / query_capacity,
+    );
+
+    assert_eq!(
+        scan_slugs, query_slugs,
+        "Scan and Query returned different pages"
+    );
+
+    // Storage backend, sequentially and with parallel ranges
+    let splits = vec![
+        format!("page-{:05}", pages / 4),
+        format!("page-{:05}", pages / 2),
+        format!("page-{:05}", pages * 3 / 4),
+    ];
+
+    for splits in [vec![], splits] {
+        let label = if splits.is_empty() {
+            "sequential"
+        } else {
+            "parallel"
+        };
+
+        let storage = DynamoStorage::new(dynamo.clone(), splits);
+        let start = Instant::now();
+        let attributions = storage.get_site_attribution(TARGET_SITE).await?;
+        let elapsed = start.elapsed();
+
+        let slugs = attributions
+            .into_iter()
+            .map(|attribution| attribution.page_slug)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            scan_slugs, slugs,
+            "Storage ({label}) returned different pages"
+        );
+        println!("Storage ({label}): {} pages in {elapsed:?}", slugs.len());
+    }
+
+    Ok(())
+}
+
+async fn create_table(dynamo: &DynamoClient) -> Result<(), Error> {
+    let tables = dynamo.list_tables().send().await?;
+    if let Some(names) = tables.table_names() {
+        if names.iter().any(|name| name == TABLE) {
+            println!("Deleting existing table {TABLE}");
+            dynamo.delete_table().table_name(TABLE).send().await?;
+        }
+    }
+
+    let key = |name: &str, key_type| {
+        KeySchemaElement::builder()
+            .attribute_name(name)
+            .key_type(key_type)
+            .build()
+    };
+
+    let attribute = |name: &str| {
+        AttributeDefinition::builder()
+            .attribute_name(name)
+            .attribute_type(ScalarAttributeType::S)
+            .build()
+    };
+
+    dynamo
+        .create_table()
+        .table_name(TABLE)
+        .key_schema(key("site_slug", KeyType::Hash))
+        .key_schema(key("page_slug", KeyType::Range))
+        .attribute_definitions(attribute("site_slug"))
+        .attribute_definitions(attribute("page_slug"))
+        .provisioned_throughput(
+            ProvisionedThroughput::builder()
+                .read_capacity_units(1000)
+                .write_capacity_units(1000)
+                .build(),
+        )
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+async fn seed(dynamo: &DynamoClient, sites: usize, pages: usize) -> Result<(), Error> {
+    let mut requests = Vec::new();
+
+    for site in 0..sites {
+        for page in 0..pages {
+            let entry = AttributeValue::M(hashmap_of([
+                ("type", AttributeValue::S("author".into())),
+                ("user_name", AttributeValue::S(format!("user-{page}"))),
+                ("user_id", AttributeValue::N(page.to_string())),
+                ("date", AttributeValue::S("2020-01-01".into())),
+            ]));
+
+            let item = hashmap_of([
+                ("site_slug", AttributeValue::S(format!("site-{site}"))),
+                ("page_slug", AttributeValue::S(format!("page-{page:05}"))),
+                ("attribution", AttributeValue::L(vec![entry])),
+            ]);
+
+            let put = PutRequest::builder().set_item(Some(item)).build();
+            requests.push(WriteRequest::builder().put_request(put).build());
+        }
+    }
+
+    // BatchWriteItem accepts at most 25 items per request
+    for chunk in requests.chunks(25) {
+        let mut items = HashMap::from([(String::from(TABLE), chunk.to_vec())]);
+
+        while !items.is_empty() {
+            let result = dynamo
+                .batch_write_item()
+                .set_request_items(Some(items))
+                .send()
+                .await?;
+
+            items = result.unprocessed_items.unwrap_or_default();
+        }
+    }
+
+    Ok(())
+}
+
+async fn scan_site(dynamo: &DynamoClient) -> Result<(Vec<String>, f64), Error> {
+    let mut slugs = Vec::new();
+    let mut capacity = 0.0;
+    let mut exclusive_start_key = None;
+
+    loop {
+        let result = dynamo
+            .scan()
+            .table_name(TABLE)
+            .limit(1000)
+            .set_exclusive_start_key(exclusive_start_key)
+            .filter_expression("site_slug = :site_slug")
+            .expression_attribute_values(
+                ":site_slug",
+                AttributeValue::S(TARGET_SITE.into()),
+            )
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await?;
+
+        capacity += consumed(result.consumed_capacity());
+        slugs.extend(page_slugs(result.items()));
+
+        match result.last_evaluated_key {
+            None => break,
+            Some(key) => exclusive_start_key = Some(key),
+        }
+    }
+
+    // Scans are not ordered, unlike queries
+    slugs.sort();
+    Ok((slugs, capacity))
+}
+
+async fn query_site(dynamo: &DynamoClient) -> Result<(Vec<String>, f64), Error> {
+    let mut slugs = Vec::new();
+    let mut capacity = 0.0;
+    let mut exclusive_start_key = None;
+
+    loop {
+        let result = dynamo
+            .query()
+            .table_name(TABLE)
+            .set_exclusive_start_key(exclusive_start_key)
+            .key_condition_expression("site_slug = :site_slug")
+            .expression_attribute_values(
+                ":site_slug",
+                AttributeValue::S(TARGET_SITE.into()),
+            )
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await?;
+
+        capacity += consumed(result.consumed_capacity());
+        slugs.extend(page_slugs(result.items()));
+
+        match result.last_evaluated_key {
+            None => break,
+            Some(key) => exclusive_start_key = Some(key),
+        }
+    }
+
+    Ok((slugs, capacity))
+}
+
+fn consumed(capacity: Option<&aws_sdk_dynamodb::types::ConsumedCapacity>) -> f64 {
+    capacity
+        .and_then(|capacity| capacity.capacity_units())
+        .unwrap_or(0.0)
+}
+
+fn page_slugs(
+    items: Option<&[HashMap<String, AttributeValue>]>,
+) -> impl Iterator<Item = String> + '_ {
+    items.unwrap_or_default().iter().map(|item| {
+        item["page_slug"]
+            .as_s()
+            .expect("Field 'page_slug' not string")
+            .clone()
+    })
+}
+
+fn hashmap_of<const N: usize>(
+    fields: [(&str, AttributeValue); N],
+) -> HashMap<String, AttributeValue> {
+    fields
+        .into_iter()
+        .map(|(key, value)| (String::from(key), value))
+        .collect()
+}
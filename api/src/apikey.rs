@@ -0,0 +1,348 @@
+/*
+ * apikey.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Named per-site API keys, for use by bots and scripts.
+//!
+//! A key is `amk.<key id>.<secret>`, passed in an `Authorization: Bearer` header.
+//! Only a SHA-256 hash of the secret is stored, since it is long and random
+//! there is no need for a slow password hash.
+
+use crate::password::PasswordType;
+use crate::storage::Storage;
+use crate::utils::unix_time;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use lambda_http::Error;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use subtle::ConstantTimeEq;
+
+/// The prefix which distinguishes API keys from session tokens.
+pub const API_KEY_PREFIX: &str = "amk.";
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    /// Identifies the caller, but grants no additional permissions.
+    ReadOnly,
+
+    /// Grants the same permissions as the site's regular password.
+    Write,
+
+    /// Grants the same permissions as the site's admin password.
+    Admin,
+}
+
+impl ApiKeyScope {
+    #[inline]
+    pub fn field_name(self) -> &'static str {
+        match self {
+            ApiKeyScope::ReadOnly => "read_only",
+            ApiKeyScope::Write => "write",
+            ApiKeyScope::Admin => "admin",
+        }
+    }
+
+    /// Determines if this scope grants the permissions of the given password type.
+    pub fn allows(self, password_type: PasswordType) -> bool {
+        match password_type {
+            PasswordType::Regular => {
+                matches!(self, ApiKeyScope::Write | ApiKeyScope::Admin)
+            }
+            PasswordType::Admin => self == ApiKeyScope::Admin,
+        }
+    }
+}
+
+impl TryFrom<&'_ str> for ApiKeyScope {
+    type Error = ApiKeyScopeConversionError;
+
+    fn try_from(value: &str) -> Result<ApiKeyScope, Self::Error> {
+        match value {
+            "read_only" => Ok(ApiKeyScope::ReadOnly),
+            "write" => Ok(ApiKeyScope::Write),
+            "admin" => Ok(ApiKeyScope::Admin),
+            _ => Err(ApiKeyScopeConversionError),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiKeyScopeConversionError;
+
+/// A stored API key.
+#[derive(Serialize, Debug, Clone)]
+pub struct ApiKey {
+    #[serde(rename = "id")]
+    pub key_id: String,
+    pub name: String,
+    pub scope: ApiKeyScope,
+
+    #[serde(skip_serializing)]
+    pub secret_hash: String,
+
+    /// When this key was created, in seconds since the UNIX epoch.
+    pub created: u64,
+
+    /// When this key expires, in seconds since the UNIX epoch, if ever.
+    pub expires: Option<u64>,
+}
+
+impl ApiKey {
+    #[inline]
+    pub fn is_expired(&self) -> bool {
+        match self.expires {
+            Some(expires) => expires <= unix_time(),
+            None => false,
+        }
+    }
+}
+
+/// A newly-created API key, the only time the full key is available.
+#[derive(Serialize, Debug)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub info: ApiKey,
+    pub key: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateApiKeyInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+    pub admin_password: Option<String>,
+    pub name: String,
+    pub scope: ApiKeyScope,
+    pub expires: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListApiKeysInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+    pub admin_password: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RevokeApiKeyInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+    pub admin_password: Option<String>,
+
+    #[serde(rename = "id")]
+    pub key_id: String,
+}
+
+/// Generates and stores a new API key for a site.
+pub async fn create_api_key(
+    storage: &dyn Storage,
+    site_slug: String,
+    name: String,
+    scope: ApiKeyScope,
+    expires: Option<u64>,
+) -> Result<CreatedApiKey, Error> {
+    let key_id = {
+        let mut bytes = [0; 8];
+        OsRng.fill_bytes(&mut bytes);
+        bytes.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    };
+
+    let secret = {
+        let mut bytes = [0; 32];
+        OsRng.fill_bytes(&mut bytes);
+        URL_SAFE_NO_PAD.encode(bytes)
+    };
+
+    let key = format!("{API_KEY_PREFIX}{key_id}.{secret}");
+    let info = ApiKey {
+        key_id,
+        name,
+        scope,
+        secret_hash: hash_secret(&secret),
+        created: unix_time(),
+        expires,
+    };
+
+    storage.create_api_key(site_slug, info.clone()).await?;
+    Ok(CreatedApiKey { info, key })
+}
+
+/// Checks the API key against those stored for the site.
+///
+/// Returns the key if it is valid and unexpired. Whether its scope grants
+/// the permissions needed is left to the caller.
+pub async fn check_api_key(
+    storage: &dyn Storage,
+    key: &str,
+    site_slug: &str,
+) -> Result<Option<ApiKey>, Error> {
+    let (key_id, secret) = match parse_api_key(key) {
+        Some(parts) => parts,
+        None => {
+            warn!("API key is malformed");
+            return Ok(None);
+        }
+    };
+
+    let api_key = match storage.get_api_key(site_slug, key_id).await? {
+        Some(api_key) => api_key,
+        None => {
+            warn!("No such API key for this site");
+            return Ok(None);
+        }
+    };
+
+    let valid: bool = hash_secret(secret)
+        .as_bytes()
+        .ct_eq(api_key.secret_hash.as_bytes())
+        .into();
+
+    if !valid {
+        warn!("API key secret is invalid");
+        return Ok(None);
+    }
+
+    if api_key.is_expired() {
+        info!("API key has expired");
+        return Ok(None);
+    }
+
+    Ok(Some(api_key))
+}
+
+/// Splits an API key into its key ID and secret.
+fn parse_api_key(key: &str) -> Option<(&str, &str)> {
+    let (key_id, secret) = key.strip_prefix(API_KEY_PREFIX)?.split_once('.')?;
+    if key_id.is_empty() || secret.is_empty() {
+        return None;
+    }
+
+    Some((key_id, secret))
+}
+
+fn hash_secret(secret: &str) -> String {
+    let hash = Sha256::digest(secret.as_bytes());
+    URL_SAFE_NO_PAD.encode(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_api_key("amk.0123abcd.c2VjcmV0"),
+            Some(("0123abcd", "c2VjcmV0"))
+        );
+        assert_eq!(parse_api_key("amk.0123abcd.a.b"), Some(("0123abcd", "a.b")));
+        assert_eq!(parse_api_key("0123abcd.c2VjcmV0"), None);
+        assert_eq!(parse_api_key("amk.0123abcd"), None);
+        assert_eq!(parse_api_key("amk..c2VjcmV0"), None);
+        assert_eq!(parse_api_key("amk.0123abcd."), None);
+    }
+
+    #[test]
+    fn scope_names() {
+        for scope in [
+            ApiKeyScope::ReadOnly,
+            ApiKeyScope::Write,
+            ApiKeyScope::Admin,
+        ] {
+            assert_eq!(ApiKeyScope::try_from(scope.field_name()).ok(), Some(scope));
+        }
+
+        assert!(ApiKeyScope::try_from("readonly").is_err());
+    }
+
+    #[test]
+    fn hash() {
+        // SHA-256 of the empty string
+        assert_eq!(
+            hash_secret(""),
+            "47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU",
+        );
+        assert_eq!(hash_secret("secret"), hash_secret("secret"));
+        assert_ne!(hash_secret("secret"), hash_secret("Secret"));
+    }
+
+    #[tokio::test]
+    async fn check() {
+        let storage = MemoryStorage::new();
+        let created = create_api_key(
+            &storage,
+            str!("scp-wiki"),
+            str!("bot"),
+            ApiKeyScope::Write,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert!(created.key.starts_with(API_KEY_PREFIX));
+        assert_ne!(created.info.secret_hash, created.key);
+
+        let checked = check_api_key(&storage, &created.key, "scp-wiki")
+            .await
+            .unwrap();
+        assert_eq!(
+            checked.map(|key| key.key_id),
+            Some(created.info.key_id.clone())
+        );
+
+        // Wrong site
+        let checked = check_api_key(&storage, &created.key, "scp-jp")
+            .await
+            .unwrap();
+        assert!(checked.is_none());
+
+        // Wrong secret
+        let wrong = format!("{API_KEY_PREFIX}{}.wrong", created.info.key_id);
+        let checked = check_api_key(&storage, &wrong, "scp-wiki").await.unwrap();
+        assert!(checked.is_none());
+    }
+
+    #[tokio::test]
+    async fn expired() {
+        let storage = MemoryStorage::new();
+        let created = create_api_key(
+            &storage,
+            str!("scp-wiki"),
+            str!("bot"),
+            ApiKeyScope::Admin,
+            Some(1),
+        )
+        .await
+        .unwrap();
+
+        let checked = check_api_key(&storage, &created.key, "scp-wiki")
+            .await
+            .unwrap();
+        assert!(checked.is_none());
+    }
+
+    #[test]
+    fn scopes() {
+        assert!(!ApiKeyScope::ReadOnly.allows(PasswordType::Regular));
+        assert!(!ApiKeyScope::ReadOnly.allows(PasswordType::Admin));
+        assert!(ApiKeyScope::Write.allows(PasswordType::Regular));
+        assert!(!ApiKeyScope::Write.allows(PasswordType::Admin));
+        assert!(ApiKeyScope::Admin.allows(PasswordType::Regular));
+        assert!(ApiKeyScope::Admin.allows(PasswordType::Admin));
+    }
+}
@@ -11,11 +11,8 @@
  *
  */
 
-use crate::object::{Attribution, FullAttribution};
-use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoClient};
-use lambda_http::Error;
-
-const TABLE: &str = "attribution_metadata";
+use crate::object::Attribution;
+use crate::patch::PatchOperation;
 
 #[derive(Deserialize, Debug)]
 pub struct UpdatePageAttributionInput {
@@ -24,120 +21,80 @@ pub struct UpdatePageAttributionInput {
 
     #[serde(rename = "page")]
     pub page_slug: String,
-    pub password: String,
+
+    /// The site's regular password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub password: Option<String>,
     pub attributions: Attribution,
-}
 
-pub async fn update_page_attribution(
-    dynamo: &DynamoClient,
-    site_slug: String,
-    page_slug: String,
-    attributions: AttributeValue,
-) -> Result<(), Error> {
-    dynamo
-        .update_item()
-        .table_name(TABLE)
-        .key("site_slug", AttributeValue::S(site_slug))
-        .key("page_slug", AttributeValue::S(page_slug))
-        .update_expression("SET attribution = :attribution")
-        .expression_attribute_values(":attribution", attributions)
-        .send()
-        .await?;
-
-    Ok(())
+    /// An optional description of the change, recorded in the page's history.
+    pub summary: Option<String>,
 }
 
-pub async fn delete_page_attribution(
-    dynamo: &DynamoClient,
-    site_slug: String,
-    page_slug: String,
-) -> Result<(), Error> {
-    dynamo
-        .delete_item()
-        .table_name(TABLE)
-        .key("site_slug", AttributeValue::S(site_slug))
-        .key("page_slug", AttributeValue::S(page_slug))
-        .send()
-        .await?;
-
-    Ok(())
+#[derive(Deserialize, Debug)]
+pub struct PatchPageAttributionInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    #[serde(rename = "page")]
+    pub page_slug: String,
+
+    /// The site's regular password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub password: Option<String>,
+
+    /// The changes to make, which are applied in order.
+    pub operations: Vec<PatchOperation>,
+
+    /// An optional description of the change, recorded in the page's history.
+    pub summary: Option<String>,
 }
 
-pub async fn get_page_attribution(
-    dynamo: &DynamoClient,
-    site_slug: &str,
-    page_slug: &str,
-) -> Result<Option<Attribution>, Error> {
-    let result = dynamo
-        .get_item()
-        .table_name(TABLE)
-        .key("site_slug", AttributeValue::S(str!(site_slug)))
-        .key("page_slug", AttributeValue::S(str!(page_slug)))
-        .projection_expression("attribution")
-        .send()
-        .await?;
-
-    match result.item() {
-        None => Ok(None),
-        Some(item) => {
-            let object = &item["attribution"];
-            Ok(Some(object.into()))
-        }
-    }
+#[derive(Deserialize, Debug)]
+pub struct RenamePageInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    /// The current slug of the page.
+    #[serde(rename = "page")]
+    pub page_slug: String,
+
+    /// The slug to move the page to.
+    #[serde(rename = "new_page")]
+    pub new_page_slug: String,
+
+    /// The site's regular password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub password: Option<String>,
+
+    /// An optional description of the change, recorded in the page's history.
+    pub summary: Option<String>,
 }
 
-pub async fn get_site_attribution(
-    dynamo: &DynamoClient,
-    site_slug: &str,
-) -> Result<Vec<FullAttribution>, Error> {
-    let mut attributions = Vec::new();
-    let mut exclusive_start_key = None;
-
-    // Maximum body size from DynamoDB is 1 MB, so we may need to fetch repeatedly
-    loop {
-        info!("Running full-site scan (start {exclusive_start_key:?})");
-
-        let result = dynamo
-            .scan()
-            .table_name(TABLE)
-            .limit(1000)
-            .set_exclusive_start_key(exclusive_start_key)
-            .filter_expression("site_slug = :site_slug")
-            .expression_attribute_values(":site_slug", AttributeValue::S(str!(site_slug)))
-            .send()
-            .await?;
-
-        match result.items {
-            None => break,
-            Some(items) => {
-                for item in items {
-                    // Extract page_slug
-                    let page_slug = item["page_slug"]
-                        .as_s()
-                        .expect("Field 'page_slug' not string")
-                        .clone();
-
-                    // Convert each from DynamoDB to AttributionEntry
-                    let attribution_raw = &item["attribution"];
-                    let attribution = attribution_raw.into();
-
-                    // Create and push FullAttribution object
-                    attributions.push(FullAttribution {
-                        page_slug,
-                        attribution,
-                    });
-                }
-
-                // Set flag for last item received to continue pagination
-                match result.last_evaluated_key {
-                    None => break,
-                    Some(last_evaluated_key) => {
-                        exclusive_start_key = Some(last_evaluated_key);
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(attributions)
+#[derive(Deserialize, Debug)]
+pub struct RevertPageAttributionInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    #[serde(rename = "page")]
+    pub page_slug: String,
+
+    /// The site's regular password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub password: Option<String>,
+
+    /// The revision to restore the page to.
+    pub revision: Option<u64>,
+
+    /// The point in time to restore the page to, in seconds since the UNIX epoch.
+    ///
+    /// Exactly one of this and `revision` must be given.
+    pub timestamp: Option<u64>,
+
+    /// An optional description of the change, recorded in the page's history.
+    pub summary: Option<String>,
 }
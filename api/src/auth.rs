@@ -0,0 +1,577 @@
+/*
+ * auth.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+use crate::apikey::{check_api_key, API_KEY_PREFIX};
+use crate::password::{check_password, PasswordType};
+use crate::ratelimit::{begin_attempt, RateLimitKeys};
+use crate::session::check_session;
+use crate::storage::Storage;
+use lambda_http::http::header::AUTHORIZATION;
+use lambda_http::request::RequestContext;
+use lambda_http::{Error, Request, RequestExt};
+use std::fmt::{self, Display};
+use std::net::IpAddr;
+
+/// The address of the connected client.
+///
+/// This is added as a request extension by the standalone server,
+/// since it has no Lambda request context.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClientAddress(pub IpAddr);
+
+/// Who made an authorized request, so changes can be attributed to them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Actor {
+    Password(PasswordType),
+    Session(PasswordType),
+    ApiKey { key_id: String, name: String },
+}
+
+impl Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Actor::Password(password_type) => {
+                write!(f, "password:{}", password_type.field_name())
+            }
+            Actor::Session(password_type) => {
+                write!(f, "session:{}", password_type.field_name())
+            }
+            Actor::ApiKey { key_id, name } => write!(f, "api_key:{key_id}:{name}"),
+        }
+    }
+}
+
+/// The result of checking a request's credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// The credentials are valid, and belong to this actor.
+    Granted(Actor),
+
+    /// The credentials for this password type are missing or invalid.
+    Denied(PasswordType),
+
+    /// The credentials are a valid API key, but its scope does
+    /// not grant the permissions of this password type.
+    Forbidden(PasswordType),
+
+    /// Too many invalid credentials have been passed recently,
+    /// so no check was made. Contains the number of seconds
+    /// until another attempt is permitted.
+    RateLimited(u64),
+}
+
+/// Gets an identifier for the client making the request, if one is available.
+pub fn client_address(req: &Request) -> Option<String> {
+    if let Some(ClientAddress(address)) = req.extensions().get() {
+        return Some(address.to_string());
+    }
+
+    match req.request_context_ref()? {
+        RequestContext::ApiGatewayV2(context) => context.http.source_ip.clone(),
+        RequestContext::ApiGatewayV1(context) => context.identity.source_ip.clone(),
+        RequestContext::Alb(_) => {
+            // The load balancer appends the client's address to this header
+            let header = req.headers().get("X-Forwarded-For")?.to_str().ok()?;
+            header
+                .rsplit(',')
+                .next()
+                .map(|address| str!(address.trim()))
+        }
+        _ => None,
+    }
+}
+
+/// Gets the token from the request's `Authorization: Bearer` header, if present.
+pub fn bearer_token(req: &Request) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Checks that a request is authorized for the given site and password types.
+///
+/// Each credential is a password for its password type. If a password was
+/// passed in the request body it is checked directly. Otherwise, the request
+/// must have a session token or API key in its `Authorization` header.
+///
+/// All of the credentials must be valid, and the actor for the first is returned.
+/// The request counts as a single attempt, however many credentials it needs.
+///
+/// Failed checks are recorded, and once there are too many for the client
+/// or site, checks are refused for a time without being made.
+pub async fn check_credentials(
+    storage: &dyn Storage,
+    req: &Request,
+    site_slug: &str,
+    credentials: &[(Option<&str>, PasswordType)],
+) -> Result<Authorization, Error> {
+    let client = client_address(req);
+    let keys = RateLimitKeys::new(site_slug, client.as_deref());
+    let checks_password = credentials.iter().any(|(password, _)| password.is_some());
+    let attempt = begin_attempt(storage, &keys, checks_password).await?;
+
+    if let Some(retry_after) = attempt.retry_after() {
+        attempt.release(storage).await?;
+        return Ok(Authorization::RateLimited(retry_after));
+    }
+
+    let mut actors = Vec::with_capacity(credentials.len());
+    for &(password, password_type) in credentials {
+        match check_actor(storage, req, site_slug, password, password_type).await? {
+            ActorCheck::Valid(actor) => actors.push(actor),
+            ActorCheck::Invalid => {
+                attempt.fail();
+                return Ok(Authorization::Denied(password_type));
+            }
+            ActorCheck::OutOfScope => {
+                // Not a guess at credentials, so not counted as a failure
+                attempt.release(storage).await?;
+                return Ok(Authorization::Forbidden(password_type));
+            }
+        }
+    }
+
+    attempt.succeed(storage).await?;
+    let actor = actors
+        .into_iter()
+        .next()
+        .expect("No credentials given to check");
+
+    Ok(Authorization::Granted(actor))
+}
+
+async fn check_actor(
+    storage: &dyn Storage,
+    req: &Request,
+    site_slug: &str,
+    password: Option<&str>,
+    password_type: PasswordType,
+) -> Result<ActorCheck, Error> {
+    if let Some(password) = password {
+        let valid = check_password(storage, site_slug, password, password_type).await?;
+        return Ok(ActorCheck::valid_if(valid, Actor::Password(password_type)));
+    }
+
+    match bearer_token(req) {
+        None => Ok(ActorCheck::Invalid),
+        Some(token) if token.starts_with(API_KEY_PREFIX) => {
+            let api_key = match check_api_key(storage, token, site_slug).await? {
+                Some(api_key) => api_key,
+                None => return Ok(ActorCheck::Invalid),
+            };
+
+            if !api_key.scope.allows(password_type) {
+                warn!(
+                    "API key scope {} does not grant {} permissions",
+                    api_key.scope.field_name(),
+                    password_type.field_name(),
+                );
+                return Ok(ActorCheck::OutOfScope);
+            }
+
+            Ok(ActorCheck::Valid(Actor::ApiKey {
+                key_id: api_key.key_id,
+                name: api_key.name,
+            }))
+        }
+        Some(token) => {
+            let valid = check_session(storage, token, site_slug, password_type).await?;
+            Ok(ActorCheck::valid_if(valid, Actor::Session(password_type)))
+        }
+    }
+}
+
+/// The result of checking a single credential.
+#[derive(Debug, PartialEq, Eq)]
+enum ActorCheck {
+    /// The credentials are valid, and were passed by this actor.
+    Valid(Actor),
+
+    /// The credentials are missing or invalid.
+    Invalid,
+
+    /// The credentials are a valid API key, but its scope does
+    /// not grant the permissions of this password type.
+    OutOfScope,
+}
+
+impl ActorCheck {
+    #[inline]
+    fn valid_if(valid: bool, actor: Actor) -> Self {
+        if valid {
+            ActorCheck::Valid(actor)
+        } else {
+            ActorCheck::Invalid
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apikey::{create_api_key, ApiKeyScope};
+    use crate::password::update_password;
+    use crate::session::issue_session;
+    use crate::storage::MemoryStorage;
+    use crate::utils::unix_time;
+    use lambda_http::Body;
+    use std::env;
+
+    async fn storage() -> MemoryStorage {
+        let storage = MemoryStorage::new();
+        update_password(
+            &storage,
+            str!("scp-wiki"),
+            str!("hunter2"),
+            PasswordType::Admin,
+        )
+        .await
+        .unwrap();
+        storage
+    }
+
+    fn request(token: Option<&str>) -> Request {
+        let mut builder = lambda_http::http::Request::builder();
+        if let Some(token) = token {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        builder.body(Body::Empty).unwrap()
+    }
+
+    fn client_request(token: Option<&str>) -> Request {
+        let mut req = request(token);
+        let address = IpAddr::from([192, 0, 2, 1]);
+        req.extensions_mut().insert(ClientAddress(address));
+        req
+    }
+
+    async fn authorize(
+        storage: &dyn Storage,
+        req: &Request,
+        credentials: &[(Option<&str>, PasswordType)],
+    ) -> Authorization {
+        check_credentials(storage, req, "scp-wiki", credentials)
+            .await
+            .unwrap()
+    }
+
+    async fn failures(storage: &dyn Storage, key: &str) -> Option<u32> {
+        let attempts = storage.get_login_attempts(key).await.unwrap();
+        attempts.map(|attempts| attempts.failures)
+    }
+
+    #[tokio::test]
+    async fn password() {
+        let storage = storage().await;
+        let req = request(None);
+        let check = |password| {
+            check_actor(&storage, &req, "scp-wiki", password, PasswordType::Admin)
+        };
+
+        assert_eq!(
+            check(Some("hunter2")).await.unwrap(),
+            ActorCheck::Valid(Actor::Password(PasswordType::Admin)),
+        );
+        assert_eq!(check(Some("hunter3")).await.unwrap(), ActorCheck::Invalid);
+        assert_eq!(check(None).await.unwrap(), ActorCheck::Invalid);
+    }
+
+    #[tokio::test]
+    async fn session() {
+        env::set_var("ATTRIBUTION_SESSION_SECRET", "test secret");
+        let storage = storage().await;
+        let session = issue_session(&storage, "scp-wiki", PasswordType::Admin)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let req = request(Some(&session.token));
+        let check = |site_slug, password_type| {
+            check_actor(&storage, &req, site_slug, None, password_type)
+        };
+
+        assert_eq!(
+            check("scp-wiki", PasswordType::Admin).await.unwrap(),
+            ActorCheck::Valid(Actor::Session(PasswordType::Admin)),
+        );
+        assert_eq!(
+            check("scp-wiki", PasswordType::Regular).await.unwrap(),
+            ActorCheck::Invalid,
+        );
+        assert_eq!(
+            check("scp-jp", PasswordType::Admin).await.unwrap(),
+            ActorCheck::Invalid,
+        );
+
+        // A password in the body takes precedence over the token
+        let result = check_actor(
+            &storage,
+            &req,
+            "scp-wiki",
+            Some("hunter3"),
+            PasswordType::Admin,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, ActorCheck::Invalid);
+
+        let req = request(Some("not a token"));
+        let result = check_actor(&storage, &req, "scp-wiki", None, PasswordType::Admin)
+            .await
+            .unwrap();
+        assert_eq!(result, ActorCheck::Invalid);
+    }
+
+    #[tokio::test]
+    async fn api_key() {
+        let storage = storage().await;
+        let check = |key: String, site_slug, password_type| {
+            let storage = &storage;
+            async move {
+                let req = request(Some(&key));
+                check_actor(storage, &req, site_slug, None, password_type)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let create =
+            |scope| create_api_key(&storage, str!("scp-wiki"), str!("bot"), scope, None);
+
+        let write = create(ApiKeyScope::Write).await.unwrap();
+        assert_eq!(
+            check(write.key.clone(), "scp-wiki", PasswordType::Regular).await,
+            ActorCheck::Valid(Actor::ApiKey {
+                key_id: write.info.key_id.clone(),
+                name: str!("bot"),
+            }),
+        );
+        assert_eq!(
+            check(write.key.clone(), "scp-wiki", PasswordType::Admin).await,
+            ActorCheck::OutOfScope,
+        );
+        assert_eq!(
+            check(write.key, "scp-jp", PasswordType::Regular).await,
+            ActorCheck::Invalid,
+        );
+
+        let read_only = create(ApiKeyScope::ReadOnly).await.unwrap();
+        for password_type in [PasswordType::Regular, PasswordType::Admin] {
+            assert_eq!(
+                check(read_only.key.clone(), "scp-wiki", password_type).await,
+                ActorCheck::OutOfScope,
+            );
+        }
+
+        let forged = format!("{API_KEY_PREFIX}{}.wrong", read_only.info.key_id);
+        assert_eq!(
+            check(forged, "scp-wiki", PasswordType::Regular).await,
+            ActorCheck::Invalid,
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited() {
+        const CLIENT_KEY: &str = "client:scp-wiki:192.0.2.1";
+
+        let storage = storage().await;
+        let req = client_request(None);
+        let check = |password| {
+            let (storage, req) = (&storage, &req);
+            async move {
+                let credentials = [(Some(password), PasswordType::Admin)];
+                authorize(storage, req, &credentials).await
+            }
+        };
+
+        for _ in 0..5 {
+            assert_eq!(
+                check("hunter3").await,
+                Authorization::Denied(PasswordType::Admin),
+            );
+        }
+        assert_eq!(failures(&storage, CLIENT_KEY).await, Some(5));
+        assert_eq!(failures(&storage, "site:scp-wiki").await, Some(5));
+
+        // Enough failures that the lockout cannot run out mid-test
+        let now = unix_time();
+        for _ in 0..5 {
+            storage
+                .record_login_failure(str!(CLIENT_KEY), now)
+                .await
+                .unwrap();
+        }
+
+        // Even the correct password is not checked, and the refusal is not counted
+        assert!(matches!(
+            check("hunter2").await,
+            Authorization::RateLimited(_),
+        ));
+        assert_eq!(failures(&storage, CLIENT_KEY).await, Some(10));
+
+        // Other clients are unaffected, and a success resets only their own count
+        let req = request(None);
+        let result = check_credentials(
+            &storage,
+            &req,
+            "scp-wiki",
+            &[(Some("hunter2"), PasswordType::Admin)],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            result,
+            Authorization::Granted(Actor::Password(PasswordType::Admin))
+        );
+        assert_eq!(failures(&storage, CLIENT_KEY).await, Some(10));
+    }
+
+    #[tokio::test]
+    async fn site_rate_limited() {
+        let storage = storage().await;
+        let created = create_api_key(
+            &storage,
+            str!("scp-wiki"),
+            str!("bot"),
+            ApiKeyScope::Admin,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let now = unix_time();
+        for _ in 0..60 {
+            storage
+                .record_login_failure(str!("site:scp-wiki"), now)
+                .await
+                .unwrap();
+        }
+
+        // Passwords are refused for every client
+        let req = client_request(None);
+        let result = check_credentials(
+            &storage,
+            &req,
+            "scp-wiki",
+            &[(Some("hunter2"), PasswordType::Admin)],
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, Authorization::RateLimited(_)));
+
+        // But API keys still work
+        let req = client_request(Some(&created.key));
+        let result =
+            check_credentials(&storage, &req, "scp-wiki", &[(None, PasswordType::Admin)])
+                .await
+                .unwrap();
+        assert!(matches!(
+            result,
+            Authorization::Granted(Actor::ApiKey { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn counted_once() {
+        const CLIENT_KEY: &str = "client:scp-wiki:192.0.2.1";
+
+        let storage = storage().await;
+        update_password(
+            &storage,
+            str!("scp-wiki"),
+            str!("letmein"),
+            PasswordType::Regular,
+        )
+        .await
+        .unwrap();
+
+        let req = client_request(None);
+        let check = |admin_password, old_password| {
+            let (storage, req) = (&storage, &req);
+            async move {
+                let credentials = [
+                    (Some(admin_password), PasswordType::Admin),
+                    (Some(old_password), PasswordType::Regular),
+                ];
+                authorize(storage, req, &credentials).await
+            }
+        };
+
+        assert_eq!(
+            check("hunter2", "wrong").await,
+            Authorization::Denied(PasswordType::Regular),
+        );
+        assert_eq!(failures(&storage, CLIENT_KEY).await, Some(1));
+
+        assert_eq!(
+            check("wrong", "wrong").await,
+            Authorization::Denied(PasswordType::Admin),
+        );
+        assert_eq!(failures(&storage, CLIENT_KEY).await, Some(2));
+
+        assert_eq!(
+            check("hunter2", "letmein").await,
+            Authorization::Granted(Actor::Password(PasswordType::Admin)),
+        );
+        assert_eq!(failures(&storage, CLIENT_KEY).await, None);
+    }
+
+    #[tokio::test]
+    async fn forbidden_not_counted() {
+        let storage = storage().await;
+        let created = create_api_key(
+            &storage,
+            str!("scp-wiki"),
+            str!("bot"),
+            ApiKeyScope::ReadOnly,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let req = client_request(Some(&created.key));
+        let result = check_credentials(
+            &storage,
+            &req,
+            "scp-wiki",
+            &[(None, PasswordType::Regular)],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, Authorization::Forbidden(PasswordType::Regular));
+        assert_eq!(failures(&storage, "client:scp-wiki:192.0.2.1").await, None);
+        assert_eq!(failures(&storage, "site:scp-wiki").await, None);
+    }
+
+    #[tokio::test]
+    async fn unknown_client() {
+        let storage = storage().await;
+        let req = request(None);
+        let result = check_credentials(
+            &storage,
+            &req,
+            "scp-wiki",
+            &[(Some("hunter3"), PasswordType::Admin)],
+        )
+        .await
+        .unwrap();
+
+        // Only the site-wide count is kept
+        assert_eq!(result, Authorization::Denied(PasswordType::Admin));
+        assert_eq!(failures(&storage, "site:scp-wiki").await, Some(1));
+    }
+}
@@ -0,0 +1,228 @@
+/*
+ * batch.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Reads and writes of several pages of a site at once.
+//!
+//! Writes are for changes such as splitting or merging articles. Every page is
+//! validated before anything is written, and then all the writes are made in a
+//! single transaction, so either every page changes or none do.
+
+use crate::object::Attribution;
+use std::collections::HashSet;
+
+/// The most pages which can be written in a single batch.
+pub const MAX_BATCH_PAGES: usize = 25;
+
+/// The most pages which can be read in a single request.
+pub const MAX_BATCH_READ_PAGES: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct BatchWriteInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    /// The site's regular password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub password: Option<String>,
+
+    pub pages: Vec<BatchPageInput>,
+
+    /// An optional description of the change, recorded in the history of each page.
+    pub summary: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BatchPageInput {
+    #[serde(rename = "page")]
+    pub page_slug: String,
+
+    /// The page's new attribution. If empty, the page's attribution is removed.
+    pub attributions: Attribution,
+
+    /// If set, nothing is written unless this is still the page's latest revision.
+    pub revision: Option<u64>,
+}
+
+/// A validated write to a single page in a batch.
+#[derive(Debug, Clone)]
+pub struct PageWrite {
+    pub page_slug: String,
+
+    /// The page's new attribution, or `None` to remove it.
+    pub attribution: Option<Attribution>,
+    pub expected_revision: Option<u64>,
+}
+
+/// Why a page in a batch was rejected.
+#[derive(Serialize, Debug)]
+pub struct PageError {
+    #[serde(rename = "page")]
+    pub page_slug: String,
+    pub message: String,
+}
+
+/// A page's revision, after a batch was written or as of a conflict.
+#[derive(Serialize, Debug)]
+pub struct PageRevision {
+    #[serde(rename = "page")]
+    pub page_slug: String,
+    pub revision: u64,
+}
+
+/// Normalizes and validates every page in a batch, in the same way as a regular update.
+///
+/// If any page is invalid, the errors for every invalid page are returned instead.
+pub fn check_batch(pages: Vec<BatchPageInput>) -> Result<Vec<PageWrite>, Vec<PageError>> {
+    let mut seen = HashSet::new();
+    let mut writes = Vec::new();
+    let mut errors = Vec::new();
+
+    for BatchPageInput {
+        page_slug,
+        mut attributions,
+        revision,
+    } in pages
+    {
+        let checked = if page_slug.is_empty() {
+            Err(str!("Page slug cannot be empty"))
+        } else if !seen.insert(page_slug.clone()) {
+            Err(str!("Page appears more than once in the batch"))
+        } else if attributions.0.is_empty() {
+            Ok(None)
+        } else {
+            attributions.sort();
+            attributions.validate().map(|()| Some(attributions))
+        };
+
+        match checked {
+            Ok(attribution) => writes.push(PageWrite {
+                page_slug,
+                attribution,
+                expected_revision: revision,
+            }),
+            Err(message) => errors.push(PageError { page_slug, message }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(writes)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{AttributionEntry, AttributionType};
+
+    fn entry(user_name: &str, date: Option<&str>) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            attribution_type: AttributionType::Author,
+            user_name: str!(user_name),
+            user_id: None,
+            date: date.map(String::from),
+        }
+    }
+
+    fn page(page_slug: &str, entries: Vec<AttributionEntry>) -> BatchPageInput {
+        BatchPageInput {
+            page_slug: str!(page_slug),
+            attributions: Attribution(entries),
+            revision: None,
+        }
+    }
+
+    fn errors(pages: Vec<BatchPageInput>) -> Vec<(String, String)> {
+        check_batch(pages)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| (error.page_slug, error.message))
+            .collect()
+    }
+
+    #[test]
+    fn normalizes() {
+        let mut revised = page(
+            "scp-173",
+            vec![
+                entry(" Moto42 ", Some("2008-07-25")),
+                entry("Someone", None),
+            ],
+        );
+        revised.revision = Some(3);
+
+        let writes = check_batch(vec![revised, page("scp-001", vec![])]).unwrap();
+        assert_eq!(writes.len(), 2);
+
+        assert_eq!(writes[0].page_slug, "scp-173");
+        assert_eq!(writes[0].expected_revision, Some(3));
+        assert_eq!(
+            writes[0].attribution,
+            Some(Attribution(vec![
+                entry("Someone", None),
+                entry("Moto42", Some("2008-07-25")),
+            ])),
+        );
+
+        // An empty list removes the page's attribution
+        assert_eq!(writes[1].page_slug, "scp-001");
+        assert_eq!(writes[1].attribution, None);
+        assert_eq!(writes[1].expected_revision, None);
+    }
+
+    #[test]
+    fn duplicates() {
+        let pages = vec![
+            page("scp-173", vec![entry("Moto42", None)]),
+            page("scp-001", vec![]),
+            page("scp-173", vec![entry("Someone", None)]),
+        ];
+
+        assert_eq!(
+            errors(pages),
+            [(
+                str!("scp-173"),
+                str!("Page appears more than once in the batch"),
+            )],
+        );
+    }
+
+    #[test]
+    fn every_error() {
+        let pages = vec![
+            page("", vec![entry("Moto42", None)]),
+            page("scp-173", vec![entry("   ", None)]),
+            page("scp-001", vec![entry("Dr Gears", None)]),
+            page("scp-002", vec![entry("Someone", Some("July 2008"))]),
+        ];
+
+        // Every invalid page is reported, not just the first
+        assert_eq!(
+            errors(pages),
+            [
+                (str!(""), str!("Page slug cannot be empty")),
+                (
+                    str!("scp-173"),
+                    str!("Username cannot be an empty string or only whitespace"),
+                ),
+                (
+                    str!("scp-002"),
+                    str!("Date value 'July 2008' is invalid (must be YYYY-MM-DD)"),
+                ),
+            ],
+        );
+    }
+}
@@ -0,0 +1,46 @@
+/*
+ * bin/backfill.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Fills in data derived from existing pages in DynamoDB.
+//!
+//! The SQLite backend does this in its schema migrations, but DynamoDB has no
+//! migrations, so this should be run once after upgrading. It is safe to run
+//! again, since only missing data is added.
+
+#[macro_use]
+extern crate tracing;
+
+use attribution_metadata::storage::DynamoStorage;
+use lambda_http::Error;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    color_backtrace::install();
+
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false) // disable printing the name of the module in every log line
+        .init();
+
+    let storage = DynamoStorage::connect().await;
+
+    info!("Backfilling attribution entry IDs");
+    let updated = storage.backfill_entry_ids().await?;
+    info!(updated, "Finished backfilling attribution entry IDs");
+
+    info!("Backfilling user index");
+    let added = storage.backfill_user_index().await?;
+    info!(added, "Finished backfilling user index");
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+/*
+ * bin/server.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+#[macro_use]
+extern crate tracing;
+
+use attribution_metadata::{server, storage};
+use lambda_http::Error;
+use std::env;
+use std::net::SocketAddr;
+
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:8080";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    color_backtrace::install();
+
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false) // disable printing the name of the module in every log line
+        .init();
+
+    let address: SocketAddr = env::var("ATTRIBUTION_BIND_ADDRESS")
+        .as_deref()
+        .unwrap_or(DEFAULT_BIND_ADDRESS)
+        .parse()?;
+
+    info!("Starting AttributionMetadataService standalone server");
+    let storage = storage::connect().await?;
+    server::run(storage, address).await
+}
@@ -0,0 +1,53 @@
+/*
+ * etag.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Entity tags for pages, for detecting conflicting edits.
+//!
+//! A page's entity tag is the number of its latest revision. Clients send it back
+//! in `If-Match` when writing, so the write is refused if someone else changed
+//! the page in the meantime, instead of silently overwriting their change.
+
+use crate::object::Attribution;
+use lambda_http::http::header::IF_MATCH;
+use lambda_http::Request;
+
+/// Formats a page revision as the value of an `ETag` header.
+#[inline]
+pub fn format_etag(revision: u64) -> String {
+    format!("\"{revision}\"")
+}
+
+/// Gets the revision a write expects the page to be at, from the `If-Match` header.
+///
+/// Returns `None` if the header is not present, so the write is unconditional.
+pub fn expected_revision(req: &Request) -> Result<Option<u64>, &'static str> {
+    let value = match req.headers().get(IF_MATCH) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().strip_prefix('"')?.strip_suffix('"'))
+        .and_then(|value| value.parse().ok())
+        .map(Some)
+        .ok_or("header 'If-Match' must be a single entity tag returned for this page")
+}
+
+/// The current state of a page, returned when a write conflicts with it.
+#[derive(Serialize, Debug)]
+pub struct PageState {
+    pub revision: u64,
+    pub attribution: Option<Attribution>,
+}
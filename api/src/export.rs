@@ -0,0 +1,409 @@
+/*
+ * export.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Export of a site's attribution, as newline-delimited JSON, CSV, or Wikidot markup.
+//!
+//! Pages are read from storage a batch at a time and written out as they are read,
+//! so when the response is streamed, the whole site is never held in memory.
+
+use crate::object::FullAttribution;
+use crate::pagination::MAX_PAGE_LIMIT;
+use crate::storage::Storage;
+use crate::wikidot::WikidotTemplate;
+use hyper::body::Sender;
+use lambda_http::http::header::ACCEPT;
+use lambda_http::{Error, Request, RequestExt};
+
+pub const JSON_CONTENT_TYPE: &str = "text/json";
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+pub const CSV_CONTENT_TYPE: &str = "text/csv";
+pub const WIKIDOT_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+const CSV_HEADER: [&str; 6] = ["page_slug", "type", "user_name", "user_id", "date", "id"];
+
+/// The format to output attribution in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    Wikidot,
+}
+
+impl OutputFormat {
+    /// Determines the format requested by the client.
+    ///
+    /// This is given by the `format` URL parameter if present,
+    /// or otherwise by a `text/csv` entry in the `Accept` header.
+    pub fn from_request(req: &Request) -> Result<Self, String> {
+        let format = req
+            .query_string_parameters_ref()
+            .and_then(|params| params.first("format"));
+
+        match format {
+            Some("json") => return Ok(OutputFormat::Json),
+            Some("csv") => return Ok(OutputFormat::Csv),
+            Some("wikidot") => return Ok(OutputFormat::Wikidot),
+            Some(format) => {
+                return Err(format!(
+                    "URL parameter 'format' must be 'json', 'csv', or 'wikidot', not '{format}'",
+                ))
+            }
+            None => (),
+        }
+
+        let accepts_csv = req
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|media_type| media_type.split(';').next())
+            .any(|media_type| media_type.trim().eq_ignore_ascii_case(CSV_CONTENT_TYPE));
+
+        match accepts_csv {
+            true => Ok(OutputFormat::Csv),
+            false => Ok(OutputFormat::Json),
+        }
+    }
+
+    /// The content type of a response in this format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            OutputFormat::Json => JSON_CONTENT_TYPE,
+            OutputFormat::Csv => CSV_CONTENT_TYPE,
+            OutputFormat::Wikidot => WIKIDOT_CONTENT_TYPE,
+        }
+    }
+
+    /// The content type of a site export in this format.
+    pub fn export_content_type(self) -> &'static str {
+        match self {
+            OutputFormat::Json => NDJSON_CONTENT_TYPE,
+            _ => self.content_type(),
+        }
+    }
+}
+
+/// Renders attributions as CSV, with one row per attribution entry.
+///
+/// The header row is included if `header` is true.
+pub fn write_csv(
+    out: &mut Vec<u8>,
+    attributions: &[FullAttribution],
+    header: bool,
+) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    if header {
+        writer.write_record(CSV_HEADER)?;
+    }
+
+    for FullAttribution {
+        page_slug,
+        attribution,
+    } in attributions
+    {
+        for entry in &attribution.0 {
+            let user_id = entry.user_id.map(|id| id.to_string()).unwrap_or_default();
+
+            writer.write_record([
+                page_slug,
+                entry.attribution_type.field_name(),
+                &entry.user_name,
+                &user_id,
+                entry.date.as_deref().unwrap_or_default(),
+                entry.id.as_deref().unwrap_or_default(),
+            ])?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Renders attributions as newline-delimited JSON, one `FullAttribution` per line.
+fn write_ndjson(
+    out: &mut Vec<u8>,
+    attributions: &[FullAttribution],
+) -> Result<(), Error> {
+    for attribution in attributions {
+        serde_json::to_writer(&mut *out, attribution)?;
+        out.push(b'\n');
+    }
+
+    Ok(())
+}
+
+/// Writes every page in a site to the body channel in the given format.
+///
+/// If an error occurs partway through, the body is aborted, so the client
+/// sees a failed response rather than one which is silently incomplete.
+pub async fn export_site(
+    storage: &dyn Storage,
+    site_slug: &str,
+    format: OutputFormat,
+    mut sender: Sender,
+) {
+    if let Err(error) = write_site(storage, site_slug, format, &mut sender).await {
+        error!("Error exporting site attribution: {error}");
+        sender.abort();
+    }
+}
+
+async fn write_site(
+    storage: &dyn Storage,
+    site_slug: &str,
+    format: OutputFormat,
+    sender: &mut Sender,
+) -> Result<(), Error> {
+    let mut start_after = None;
+    let mut pages = 0;
+
+    // Only needed for Wikidot, the site hub is rendered with its template
+    let template = match format {
+        OutputFormat::Wikidot => storage
+            .get_wikidot_template(site_slug)
+            .await?
+            .unwrap_or_default(),
+        _ => WikidotTemplate::default(),
+    };
+
+    let (hub_header, hub_footer) = template.hub_parts();
+
+    match format {
+        OutputFormat::Json => (),
+        OutputFormat::Csv => {
+            let mut chunk = Vec::new();
+            write_csv(&mut chunk, &[], true)?;
+            sender.send_data(chunk.into()).await?;
+        }
+        OutputFormat::Wikidot => {
+            sender.send_data(str!(hub_header).into()).await?;
+        }
+    }
+
+    loop {
+        let (attributions, last_page) = storage
+            .get_site_attribution_page(site_slug, MAX_PAGE_LIMIT, start_after.as_deref())
+            .await?;
+
+        if !attributions.is_empty() {
+            let mut chunk = Vec::new();
+            match format {
+                OutputFormat::Json => write_ndjson(&mut chunk, &attributions)?,
+                OutputFormat::Csv => write_csv(&mut chunk, &attributions, false)?,
+                OutputFormat::Wikidot => {
+                    for attribution in &attributions {
+                        chunk.extend(template.render_hub_page(attribution).into_bytes());
+                    }
+                }
+            }
+
+            pages += attributions.len();
+            sender.send_data(chunk.into()).await?;
+        }
+
+        match last_page {
+            None => break,
+            Some(page_slug) => start_after = Some(page_slug),
+        }
+    }
+
+    if format == OutputFormat::Wikidot {
+        sender.send_data(str!(hub_footer).into()).await?;
+    }
+
+    info!(site_slug, pages, "Finished site export");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Actor;
+    use crate::history::Change;
+    use crate::object::{Attribution, AttributionEntry, AttributionType};
+    use crate::password::PasswordType;
+    use crate::storage::MemoryStorage;
+    use lambda_http::aws_lambda_events::query_map::QueryMap;
+    use lambda_http::Body;
+    use std::collections::HashMap;
+    use std::num::NonZeroU32;
+
+    const SITE: &str = "scp-wiki";
+
+    fn entry(attribution_type: AttributionType, user_name: &str) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }
+    }
+
+    fn request(format: Option<&str>, accept: Option<&str>) -> Request {
+        let mut builder = lambda_http::http::Request::builder();
+        if let Some(accept) = accept {
+            builder = builder.header(ACCEPT, accept);
+        }
+
+        let mut params = HashMap::new();
+        if let Some(format) = format {
+            params.insert(str!("format"), str!(format));
+        }
+
+        builder
+            .body(Body::Empty)
+            .unwrap()
+            .with_query_string_parameters(QueryMap::from(params))
+    }
+
+    async fn export_text(storage: &MemoryStorage, format: OutputFormat) -> String {
+        // The body is read while it is written, since the channel only holds one chunk
+        let (sender, body) = hyper::Body::channel();
+        let (_, bytes) = tokio::join!(
+            export_site(storage, SITE, format, sender),
+            hyper::body::to_bytes(body)
+        );
+
+        String::from_utf8(bytes.unwrap().to_vec()).unwrap()
+    }
+
+    async fn export(storage: &MemoryStorage) -> Vec<FullAttribution> {
+        let text = export_text(storage, OutputFormat::Json).await;
+        assert!(text.is_empty() || text.ends_with('\n'));
+
+        text.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn format_from_request() {
+        let check = |format, accept, expected| {
+            assert_eq!(
+                OutputFormat::from_request(&request(format, accept)),
+                expected,
+                "Unexpected format for {format:?}, {accept:?}",
+            );
+        };
+
+        check(None, None, Ok(OutputFormat::Json));
+        check(Some("csv"), None, Ok(OutputFormat::Csv));
+        check(Some("json"), None, Ok(OutputFormat::Json));
+        check(None, Some("text/csv"), Ok(OutputFormat::Csv));
+        check(
+            None,
+            Some("application/json, Text/CSV; q=0.5"),
+            Ok(OutputFormat::Csv),
+        );
+        check(None, Some("application/json"), Ok(OutputFormat::Json));
+
+        // The URL parameter takes precedence over the header
+        check(Some("json"), Some("text/csv"), Ok(OutputFormat::Json));
+
+        assert!(OutputFormat::from_request(&request(Some("xml"), None)).is_err());
+    }
+
+    #[test]
+    fn csv_rows() {
+        let attributions = [
+            FullAttribution {
+                page_slug: str!("scp-173"),
+                attribution: Attribution(vec![
+                    AttributionEntry {
+                        id: Some(str!("0123456789abcdef")),
+                        user_id: NonZeroU32::new(42),
+                        date: Some(str!("2008-07-25")),
+                        ..entry(AttributionType::Author, "Moto42")
+                    },
+                    entry(AttributionType::Rewrite, "Someone, \"quoted\""),
+                ]),
+            },
+            FullAttribution {
+                page_slug: str!("scp-001"),
+                attribution: Attribution(vec![]),
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &attributions, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "page_slug,type,user_name,user_id,date,id\n\
+             scp-173,author,Moto42,42,2008-07-25,0123456789abcdef\n\
+             scp-173,rewrite,\"Someone, \"\"quoted\"\"\",,,\n",
+        );
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &attributions[1..], false).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_site() {
+        let storage = MemoryStorage::new();
+        assert!(export(&storage).await.is_empty());
+
+        // A CSV export still has its header
+        let text = export_text(&storage, OutputFormat::Csv).await;
+        assert_eq!(text, "page_slug,type,user_name,user_id,date,id\n");
+    }
+
+    #[tokio::test]
+    async fn multiple_batches() {
+        let storage = MemoryStorage::new();
+        let change = Change::new(Actor::Password(PasswordType::Regular), None);
+        let attribution = Attribution(vec![entry(AttributionType::Author, "Moto42")]);
+
+        let count = MAX_PAGE_LIMIT as usize + 1;
+        for index in 0..count {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    format!("page-{index:04}"),
+                    attribution.clone(),
+                    &change,
+                )
+                .await
+                .unwrap();
+        }
+
+        // Other sites are not included
+        storage
+            .update_page_attribution(
+                str!("scp-jp"),
+                str!("scp-173"),
+                attribution.clone(),
+                &change,
+            )
+            .await
+            .unwrap();
+
+        let pages = export(&storage).await;
+        assert_eq!(pages.len(), count);
+        assert_eq!(pages[0].page_slug, "page-0000");
+        assert_eq!(pages[count - 1].page_slug, format!("page-{:04}", count - 1));
+        assert!(pages
+            .iter()
+            .all(|page| attribution.same_entries(&page.attribution)));
+
+        // The header is only written once, before the first batch
+        let text = export_text(&storage, OutputFormat::Csv).await;
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), count + 1);
+        assert_eq!(lines[0], "page_slug,type,user_name,user_id,date,id");
+        assert!(lines[1].starts_with("page-0000,author,Moto42,,,"));
+        assert!(lines.iter().all(|line| !line.ends_with(',')));
+    }
+}
@@ -11,17 +11,49 @@
  *
  */
 
+use crate::apikey::{
+    create_api_key, CreateApiKeyInput, ListApiKeysInput, RevokeApiKeyInput,
+};
 use crate::attribution::{
-    delete_page_attribution, get_page_attribution, get_site_attribution,
-    update_page_attribution, UpdatePageAttributionInput,
+    PatchPageAttributionInput, RenamePageInput, RevertPageAttributionInput,
+    UpdatePageAttributionInput,
+};
+use crate::auth::{check_credentials, Authorization};
+use crate::batch::{
+    check_batch, BatchWriteInput, PageRevision, PageWrite, MAX_BATCH_PAGES,
+    MAX_BATCH_READ_PAGES,
+};
+use crate::etag::{expected_revision, PageState};
+use crate::export::{export_site, write_csv, OutputFormat};
+use crate::history::{Change, RevertTarget};
+use crate::import::{parse_csv, plan_import, ImportInput, ImportPlan};
+use crate::license::{
+    build_statement, is_supported_language, page_url, site_language, StatementFormat,
+};
+use crate::object::FullAttribution;
+use crate::pagination::{
+    decode_cursor, encode_cursor, SiteAttributionPage, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
 };
 use crate::password::{
-    check_password, update_password, CheckPasswordInput, PasswordType,
-    UpdatePasswordInput,
+    update_password, CheckPasswordInput, PasswordType, UpdatePasswordInput,
 };
+use crate::patch::apply_patch;
 use crate::result::ServiceResult;
+use crate::session::issue_session;
+use crate::storage::{BatchResult, RenameResult, Storage, WriteResult};
+use crate::translation::{
+    PageAttributionOutput, PageReference, PageTranslationsOutput, UpdateTranslationInput,
+};
 use crate::utils::*;
+use crate::wikidot::UpdateWikidotTemplateInput;
+use hyper::Body as HyperBody;
 use lambda_http::{Body, Error, Request, RequestExt};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+use std::num::NonZeroU32;
+
+/// How many times to apply a patch made without `If-Match` before giving up.
+const PATCH_ATTEMPTS: u32 = 3;
 
 macro_rules! input_error {
     ($message:expr) => {
@@ -29,11 +61,52 @@ macro_rules! input_error {
     };
 }
 
-pub async fn handle_get_page(req: Request) -> Result<(u16, String), Error> {
-    info!("Received page attribution request");
+/// Gets the slug a page is stored under, or returns an error response.
+macro_rules! resolve_page_slug {
+    ($storage:expr, $site_slug:expr, $page_slug:expr $(,)?) => {
+        match resolve_page_slug($storage, &$site_slug, $page_slug).await {
+            Ok(page_slug) => page_slug,
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        }
+    };
+}
+
+/// Gets the slug a page is stored under, resolving the old slug of a renamed page.
+///
+/// Reading an old slug returns the renamed page, so writes to it go to that page too,
+/// rather than creating a new page alongside it.
+async fn resolve_page_slug(
+    storage: &dyn Storage,
+    site_slug: &str,
+    page_slug: String,
+) -> Result<String, Error> {
+    // Left for validation to reject, since it can't be looked up
+    if page_slug.is_empty() {
+        return Ok(page_slug);
+    }
 
-    // Setup
-    let dynamo = connect_dynamo_db().await;
+    match storage.get_alias_target(site_slug, &page_slug).await? {
+        None => Ok(page_slug),
+        Some(target) => {
+            info!(
+                page_slug,
+                target, "Page slug is an alias, using the renamed page"
+            );
+            Ok(target)
+        }
+    }
+}
+
+/// Gets a page's attribution.
+///
+/// On success, `revision` is set to the page's latest revision, to be returned as its ETag.
+pub async fn handle_get_page(
+    storage: &dyn Storage,
+    req: Request,
+    format: OutputFormat,
+    revision: &mut Option<u64>,
+) -> Result<(u16, String), Error> {
+    info!("Received page attribution request");
 
     // We use URL parameters because this is a GET request.
 
@@ -53,58 +126,1105 @@ pub async fn handle_get_page(req: Request) -> Result<(u16, String), Error> {
     };
 
     info!(site_slug, page_slug);
-    json_output!(get_page_attribution(&dynamo, site_slug, page_slug))
+
+    // Read this before the attribution, so that if the page changes in between,
+    // a write with this revision is refused rather than overwriting unseen changes.
+    // Writes to an old slug go to the renamed page, so this is its revision.
+    let stored_slug = resolve_page_slug!(storage, site_slug, str!(page_slug));
+    match storage.get_page_revision(site_slug, &stored_slug).await {
+        Ok(latest) => *revision = Some(latest),
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    }
+
+    if format == OutputFormat::Json {
+        if params.first("translations") != Some("true") {
+            return json_output!(storage.get_page_attribution(site_slug, page_slug));
+        }
+
+        // Also include the page's translation links, which changes the output to an object
+        let (attribution, translations) = tokio::join!(
+            storage.get_page_attribution(site_slug, page_slug),
+            storage.get_page_translations(site_slug, page_slug),
+        );
+
+        let output = match (attribution, translations) {
+            (Ok(attribution), Ok(translations)) => PageAttributionOutput {
+                attribution,
+                translations,
+            },
+            (Err(error), _) | (_, Err(error)) => {
+                return Ok((500, service_error(&*error)?));
+            }
+        };
+
+        return Ok((200, serde_json::to_string(&output)?));
+    }
+
+    let attribution = match storage.get_page_attribution(site_slug, page_slug).await {
+        Ok(attribution) => attribution,
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    if format == OutputFormat::Csv {
+        let attributions = attribution
+            .map(|attribution| FullAttribution {
+                page_slug: str!(page_slug),
+                attribution,
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &attributions, true)?;
+        return Ok((200, String::from_utf8(csv)?));
+    }
+
+    // A page without attribution renders as nothing at all
+    let attribution = match attribution {
+        Some(attribution) => attribution,
+        None => return Ok((200, String::new())),
+    };
+
+    match storage.get_wikidot_template(site_slug).await {
+        Ok(template) => {
+            let template = template.unwrap_or_default();
+            Ok((200, template.render_page(&attribution)))
+        }
+        Err(error) => Ok((500, service_error(&*error)?)),
+    }
 }
 
-pub async fn handle_set_page(req: Request) -> Result<(u16, String), Error> {
+/// Sets a page's attribution.
+///
+/// On success or conflict, `revision` is set to the page's latest revision,
+/// to be returned as its ETag.
+pub async fn handle_set_page(
+    storage: &dyn Storage,
+    req: Request,
+    revision: &mut Option<u64>,
+) -> Result<(u16, String), Error> {
     info!("Received page attribution update request");
 
-    // Setup
-    let dynamo = connect_dynamo_db().await;
+    let expected_revision = match expected_revision(&req) {
+        Ok(expected_revision) => expected_revision,
+        Err(message) => input_error!(message),
+    };
+
     let UpdatePageAttributionInput {
         site_slug,
         page_slug,
         password,
         mut attributions,
+        summary,
     } = parse_body!(&req);
 
     info!(
         site_slug,
         page_slug,
         attributions_len = attributions.0.len(),
+        expected_revision,
     );
 
-    check_password!(dynamo, site_slug, password, PasswordType::Regular);
+    let actor =
+        check_credentials!(storage, req, site_slug, password, PasswordType::Regular);
+    let mut change = Change::new(actor, summary);
+    change.expected_revision = expected_revision;
 
-    if attributions.0.is_empty() {
+    let page_slug = resolve_page_slug!(storage, site_slug, page_slug);
+
+    let result = if attributions.0.is_empty() {
         info!("List of attributions for page is empty, deleting item");
-        success_output!(delete_page_attribution(&dynamo, site_slug, page_slug))
+        storage
+            .delete_page_attribution(site_slug.clone(), page_slug.clone(), &change)
+            .await
     } else {
-        debug!("Converting attributions to be inserted");
+        debug!("Validating attributions to be inserted");
 
         attributions.sort();
 
-        let attributions_object = match attributions.try_into() {
-            Ok(object) => object,
-            Err(message) => return Ok((400, message)),
+        if let Err(message) = attributions.validate() {
+            return Ok((400, message));
+        }
+
+        storage
+            .update_page_attribution(
+                site_slug.clone(),
+                page_slug.clone(),
+                attributions,
+                &change,
+            )
+            .await
+    };
+
+    match result {
+        Ok(WriteResult::Written(latest)) => {
+            *revision = Some(latest);
+            success!()
+        }
+        Ok(WriteResult::Conflict) => {
+            let expected_revision = expected_revision.unwrap_or_default();
+            conflict_output(storage, &site_slug, &page_slug, expected_revision, revision)
+                .await
+        }
+        Err(error) => Ok((500, service_error(&*error)?)),
+    }
+}
+
+/// Applies partial edits to a page's attribution.
+///
+/// Without `If-Match`, the edits are re-applied if the page changes while they are
+/// being made, since they do not depend on the rest of the page. On success or
+/// conflict, `revision` is set to the page's latest revision, to be returned as its ETag.
+pub async fn handle_patch_page(
+    storage: &dyn Storage,
+    req: Request,
+    revision: &mut Option<u64>,
+) -> Result<(u16, String), Error> {
+    info!("Received page attribution patch request");
+
+    let expected_revision = match expected_revision(&req) {
+        Ok(expected_revision) => expected_revision,
+        Err(message) => input_error!(message),
+    };
+
+    let PatchPageAttributionInput {
+        site_slug,
+        page_slug,
+        password,
+        operations,
+        summary,
+    } = parse_body!(&req);
+
+    info!(
+        site_slug,
+        page_slug,
+        operations_len = operations.len(),
+        expected_revision,
+    );
+
+    if operations.is_empty() {
+        input_error!("no operations given");
+    }
+
+    let actor =
+        check_credentials!(storage, req, site_slug, password, PasswordType::Regular);
+    let mut change = Change::new(actor, summary);
+    let mut latest = 0;
+
+    let page_slug = resolve_page_slug!(storage, site_slug, page_slug);
+
+    for attempt in 1..=PATCH_ATTEMPTS {
+        // Read this before the attribution, so the write is refused if the page changes in between
+        latest = match storage.get_page_revision(&site_slug, &page_slug).await {
+            Ok(latest) => latest,
+            Err(error) => return Ok((500, service_error(&*error)?)),
         };
 
-        success_output!(update_page_attribution(
-            &dynamo,
-            site_slug,
-            page_slug,
-            attributions_object,
-        ))
+        if let Some(expected_revision) = expected_revision {
+            if expected_revision != latest {
+                warn!("Page has changed since the expected revision");
+                return conflict_output(
+                    storage,
+                    &site_slug,
+                    &page_slug,
+                    expected_revision,
+                    revision,
+                )
+                .await;
+            }
+        }
+
+        let attribution = match storage.get_page_attribution(&site_slug, &page_slug).await
+        {
+            Ok(attribution) => attribution,
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        };
+
+        let mut attributions = match apply_patch(attribution.clone(), operations.clone())
+        {
+            Ok(attributions) => attributions,
+            Err(message) => input_error!(message),
+        };
+
+        debug!("Validating patched attributions");
+
+        attributions.sort();
+
+        if let Err(message) = attributions.validate() {
+            input_error!(message);
+        }
+
+        // Assign IDs to added entries here, so they can be returned
+        attributions.assign_ids(attribution.as_ref());
+
+        change.expected_revision = Some(latest);
+
+        let (result, attribution) = if attributions.0.is_empty() {
+            info!("Patched list of attributions is empty, deleting item");
+            let result = storage
+                .delete_page_attribution(site_slug.clone(), page_slug.clone(), &change)
+                .await;
+
+            (result, None)
+        } else {
+            let result = storage
+                .update_page_attribution(
+                    site_slug.clone(),
+                    page_slug.clone(),
+                    attributions.clone(),
+                    &change,
+                )
+                .await;
+
+            (result, Some(attributions))
+        };
+
+        match result {
+            Ok(WriteResult::Written(latest)) => {
+                *revision = Some(latest);
+                let state = PageState {
+                    revision: latest,
+                    attribution,
+                };
+
+                return Ok((200, ServiceResult::success(state).to_json()?));
+            }
+            Ok(WriteResult::Conflict) => {
+                warn!(attempt, "Page changed while applying patch");
+
+                if let Some(expected_revision) = expected_revision {
+                    return conflict_output(
+                        storage,
+                        &site_slug,
+                        &page_slug,
+                        expected_revision,
+                        revision,
+                    )
+                    .await;
+                }
+            }
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        }
+    }
+
+    error!("Page kept changing while applying patch, giving up");
+    conflict_output(storage, &site_slug, &page_slug, latest, revision).await
+}
+
+/// Responds to a write which was refused because the page is no longer at the expected revision.
+///
+/// Returns the current state, so the client can merge the changes.
+async fn conflict_output(
+    storage: &dyn Storage,
+    site_slug: &str,
+    page_slug: &str,
+    expected_revision: u64,
+    revision: &mut Option<u64>,
+) -> Result<(u16, String), Error> {
+    let latest = match storage.get_page_revision(site_slug, page_slug).await {
+        Ok(latest) => latest,
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    let attribution = match storage.get_page_attribution(site_slug, page_slug).await {
+        Ok(attribution) => attribution,
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    *revision = Some(latest);
+    let message = format!(
+        "page has been changed since revision {expected_revision}, it is now at revision {latest}",
+    );
+    let state = PageState {
+        revision: latest,
+        attribution,
+    };
+
+    let body = ServiceResult::rejected("conflict", message, state);
+    Ok((412, body.to_json()?))
+}
+
+pub async fn handle_get_pages(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received batch page attribution request");
+
+    let params = match req.query_string_parameters_ref() {
+        Some(params) => params,
+        None => input_error!("missing URL parameters 'site' and 'pages'"),
+    };
+
+    let site_slug = match params.first("site") {
+        Some(slug) => slug,
+        None => input_error!("missing URL parameter 'site'"),
+    };
+
+    // Comma-separated list of page slugs, e.g. "scp-001,scp-002"
+    let page_slugs = match params.first("pages") {
+        Some(slugs) => slugs
+            .split(',')
+            .map(|slug| str!(slug.trim()))
+            .filter(|slug| !slug.is_empty())
+            .collect::<Vec<_>>(),
+        None => input_error!("missing URL parameter 'pages'"),
+    };
+
+    info!(site_slug, pages_len = page_slugs.len());
+
+    if page_slugs.len() > MAX_BATCH_READ_PAGES {
+        input_error!(format!(
+            "at most {MAX_BATCH_READ_PAGES} pages can be read at once, not {}",
+            page_slugs.len(),
+        ));
+    }
+
+    json_output!(storage.get_pages_attribution(site_slug, &page_slugs))
+}
+
+pub async fn handle_batch_write(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received batch page attribution update request");
+
+    let BatchWriteInput {
+        site_slug,
+        password,
+        mut pages,
+        summary,
+    } = parse_body!(&req);
+
+    info!(site_slug, pages_len = pages.len());
+
+    if pages.is_empty() {
+        input_error!("no pages given");
+    }
+
+    if pages.len() > MAX_BATCH_PAGES {
+        input_error!(format!(
+            "at most {MAX_BATCH_PAGES} pages can be written at once, not {}",
+            pages.len(),
+        ));
+    }
+
+    let actor =
+        check_credentials!(storage, req, site_slug, password, PasswordType::Regular);
+    let change = Change::new(actor, summary);
+
+    // Resolve aliases first, so that two slugs for the same page are caught as duplicates
+    for page in &mut pages {
+        let page_slug = mem::take(&mut page.page_slug);
+        page.page_slug = resolve_page_slug!(storage, site_slug, page_slug);
+    }
+
+    let writes = match check_batch(pages) {
+        Ok(writes) => writes,
+        Err(errors) => {
+            let message = str!("Some pages were rejected, so nothing was written");
+
+            let body = ServiceResult::rejected("batch-rejected", message, errors);
+            return Ok((400, body.to_json()?));
+        }
+    };
+
+    let page_slugs = writes
+        .iter()
+        .map(|write| write.page_slug.clone())
+        .collect::<Vec<_>>();
+
+    let result = storage
+        .write_pages(site_slug.clone(), writes, &change)
+        .await;
+
+    match result {
+        Ok(BatchResult::Written(revisions)) => {
+            let revisions = page_slugs
+                .into_iter()
+                .zip(revisions)
+                .map(|(page_slug, revision)| PageRevision {
+                    page_slug,
+                    revision,
+                })
+                .collect::<Vec<_>>();
+
+            Ok((200, ServiceResult::success(revisions).to_json()?))
+        }
+        Ok(BatchResult::Conflict) => {
+            warn!("A page in the batch has changed since its expected revision");
+
+            // Return the current revisions, so the client can find which pages changed
+            let mut revisions = Vec::new();
+            for page_slug in page_slugs {
+                let revision =
+                    match storage.get_page_revision(&site_slug, &page_slug).await {
+                        Ok(revision) => revision,
+                        Err(error) => return Ok((500, service_error(&*error)?)),
+                    };
+
+                revisions.push(PageRevision {
+                    page_slug,
+                    revision,
+                });
+            }
+
+            let message = str!("Some pages have been changed, so nothing was written");
+
+            let body = ServiceResult::rejected("conflict", message, revisions);
+            Ok((412, body.to_json()?))
+        }
+        Ok(BatchResult::TooLarge) => {
+            input_error!("batch changes too much to be written at once, try fewer pages")
+        }
+        Err(error) => Ok((500, service_error(&*error)?)),
     }
 }
 
-pub async fn handle_get_site(req: Request) -> Result<(u16, String), Error> {
+pub async fn handle_get_page_history(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received page attribution history request");
+
+    let params = match req.query_string_parameters_ref() {
+        Some(params) => params,
+        None => input_error!("missing URL parameters 'site' and 'page'"),
+    };
+
+    let site_slug = match params.first("site") {
+        Some(slug) => slug,
+        None => input_error!("missing URL parameter 'site'"),
+    };
+
+    let page_slug = match params.first("page") {
+        Some(slug) => slug,
+        None => input_error!("missing URL parameter 'page'"),
+    };
+
+    info!(site_slug, page_slug);
+
+    let page_slug = resolve_page_slug!(storage, site_slug, str!(page_slug));
+    json_output!(storage.get_page_history(site_slug, &page_slug))
+}
+
+pub async fn handle_get_translations(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received page translations request");
+
+    let params = match req.query_string_parameters_ref() {
+        Some(params) => params,
+        None => input_error!("missing URL parameters 'site' and 'page'"),
+    };
+
+    let site_slug = match params.first("site") {
+        Some(slug) => slug,
+        None => input_error!("missing URL parameter 'site'"),
+    };
+
+    let page_slug = match params.first("page") {
+        Some(slug) => slug,
+        None => input_error!("missing URL parameter 'page'"),
+    };
+
+    info!(site_slug, page_slug);
+
+    let translations = match storage.get_page_translations(site_slug, page_slug).await {
+        Ok(translations) => translations,
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    // Include the original's attribution, so its authors can be credited
+    let original_attribution = match translations.translation_of {
+        None => None,
+        Some(ref original) => match storage
+            .get_page_attribution(&original.site_slug, &original.page_slug)
+            .await
+        {
+            Ok(attribution) => attribution,
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        },
+    };
+
+    let output = PageTranslationsOutput {
+        translations,
+        original_attribution,
+    };
+
+    Ok((200, serde_json::to_string(&output)?))
+}
+
+pub async fn handle_set_translation(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received page translation update request");
+
+    let UpdateTranslationInput {
+        site_slug,
+        page_slug,
+        password,
+        translation_of,
+    } = parse_body!(&req);
+
+    info!(site_slug, page_slug, ?translation_of);
+
+    if let Some(ref original) = translation_of {
+        if original.site_slug.is_empty() || original.page_slug.is_empty() {
+            input_error!("original site and page cannot be empty");
+        }
+
+        if original.site_slug == site_slug && original.page_slug == page_slug {
+            input_error!("a page cannot be a translation of itself");
+        }
+    }
+
+    check_credentials!(storage, req, site_slug, password, PasswordType::Regular);
+
+    // Link the pages themselves, so the links are found from either slug
+    let page_slug = resolve_page_slug!(storage, site_slug, page_slug);
+    let translation_of = match translation_of {
+        None => None,
+        Some(PageReference {
+            site_slug: original_site_slug,
+            page_slug: original_page_slug,
+        }) => {
+            let original_page_slug =
+                resolve_page_slug!(storage, original_site_slug, original_page_slug);
+
+            if original_site_slug == site_slug && original_page_slug == page_slug {
+                input_error!("a page cannot be a translation of itself");
+            }
+
+            Some(PageReference {
+                site_slug: original_site_slug,
+                page_slug: original_page_slug,
+            })
+        }
+    };
+
+    success_output!(storage.set_translation_of(site_slug, page_slug, translation_of))
+}
+
+pub async fn handle_get_user(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received user attribution request");
+
+    let params = match req.query_string_parameters_ref() {
+        Some(params) => params,
+        None => input_error!("missing URL parameter 'user_id'"),
+    };
+
+    let user_id: NonZeroU32 = match params.first("user_id").map(str::parse) {
+        Some(Ok(user_id)) => user_id,
+        Some(Err(_)) => input_error!("URL parameter 'user_id' must be a positive number"),
+        None => input_error!("missing URL parameter 'user_id'"),
+    };
+
+    // Optionally only include entries under a particular name,
+    // such as to distinguish credits from before a rename.
+    let user_name = params.first("user_name");
+
+    info!(user_id, user_name);
+
+    let mut attributions = match storage.get_user_attribution(user_id).await {
+        Ok(attributions) => attributions,
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    if let Some(user_name) = user_name {
+        attributions
+            .retain(|attribution| attribution.user_name.eq_ignore_ascii_case(user_name));
+    }
+
+    Ok((200, serde_json::to_string(&attributions)?))
+}
+
+pub async fn handle_rename_page(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received page rename request");
+
+    let RenamePageInput {
+        site_slug,
+        page_slug,
+        new_page_slug,
+        password,
+        summary,
+    } = parse_body!(&req);
+
+    info!(site_slug, page_slug, new_page_slug);
+
+    if new_page_slug.is_empty() {
+        input_error!("new page slug cannot be empty");
+    }
+
+    if new_page_slug == page_slug {
+        input_error!("new page slug is the same as the current one");
+    }
+
+    let actor =
+        check_credentials!(storage, req, site_slug, password, PasswordType::Regular);
+    let summary =
+        summary.or_else(|| Some(format!("Rename from {page_slug} to {new_page_slug}")));
+    let change = Change::new(actor, summary);
+
+    match storage
+        .rename_page(site_slug, page_slug, new_page_slug, &change)
+        .await
+    {
+        Ok(RenameResult::Renamed) => success!(),
+        Ok(RenameResult::MissingPage) => {
+            input_error!("no attribution exists for this page")
+        }
+        Ok(RenameResult::ExistingPage) => {
+            input_error!("the new page slug already has attribution, or is an alias of another page")
+        }
+        Ok(RenameResult::TooLarge) => {
+            input_error!("page has too many aliases and translations to be renamed")
+        }
+        Err(error) => Ok((500, service_error(&*error)?)),
+    }
+}
+
+pub async fn handle_revert_page(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received page attribution revert request");
+
+    let RevertPageAttributionInput {
+        site_slug,
+        page_slug,
+        password,
+        revision,
+        timestamp,
+        summary,
+    } = parse_body!(&req);
+
+    info!(site_slug, page_slug, revision, timestamp);
+
+    let target = match (revision, timestamp) {
+        (Some(revision), None) => RevertTarget::Revision(revision),
+        (None, Some(timestamp)) => RevertTarget::Timestamp(timestamp),
+        _ => input_error!("exactly one of 'revision' or 'timestamp' must be given"),
+    };
+
+    let actor =
+        check_credentials!(storage, req, site_slug, password, PasswordType::Regular);
+    let change = Change::new(actor, summary.or_else(|| Some(target.summary())));
+    let page_slug = resolve_page_slug!(storage, site_slug, page_slug);
+
+    let history = match storage.get_page_history(&site_slug, &page_slug).await {
+        Ok(history) => history,
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    match target.find(&history) {
+        Ok(Some(attributions)) => {
+            info!("Restoring page attribution from history");
+            success_output!(storage.update_page_attribution(
+                site_slug,
+                page_slug,
+                attributions,
+                &change,
+            ))
+        }
+        Ok(None) => {
+            info!("Page had no attribution at this point, deleting item");
+            success_output!(storage.delete_page_attribution(site_slug, page_slug, &change))
+        }
+        Err(message) => input_error!(message),
+    }
+}
+
+pub async fn handle_get_site(
+    storage: &dyn Storage,
+    req: Request,
+    format: OutputFormat,
+) -> Result<(u16, String), Error> {
     info!("Received site attribution list request");
 
-    // Setup
-    let dynamo = connect_dynamo_db().await;
+    let params = match req.query_string_parameters_ref() {
+        Some(params) => params,
+        None => input_error!("missing URL parameter 'site'"),
+    };
+
+    let site_slug = match params.first("site") {
+        Some(site_slug) => site_slug,
+        None => input_error!("missing URL parameter 'site'"),
+    };
+
+    // Spreadsheets can't follow a cursor, so CSV always has the whole site
+    if format == OutputFormat::Csv {
+        info!(site_slug, "Returning all site attributions as CSV");
+
+        return match storage.get_site_attribution(site_slug).await {
+            Ok(attributions) => {
+                let mut csv = Vec::new();
+                write_csv(&mut csv, &attributions, true)?;
+                Ok((200, String::from_utf8(csv)?))
+            }
+            Err(error) => Ok((500, service_error(&*error)?)),
+        };
+    }
+
+    // The hub needs every page, there is no way to continue it from a cursor
+    if format == OutputFormat::Wikidot {
+        info!(site_slug, "Returning site attribution hub");
+
+        let template = match storage.get_wikidot_template(site_slug).await {
+            Ok(template) => template.unwrap_or_default(),
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        };
+
+        return match storage.get_site_attribution(site_slug).await {
+            Ok(attributions) => {
+                let (header, footer) = template.hub_parts();
+                let mut hub = str!(header);
+                for attribution in &attributions {
+                    hub.push_str(&template.render_hub_page(attribution));
+                }
+
+                hub.push_str(footer);
+                Ok((200, hub))
+            }
+            Err(error) => Ok((500, service_error(&*error)?)),
+        };
+    }
+
+    // The full list of attributions can be requested explicitly, in one response
+    if params.first("all") == Some("true") {
+        info!(site_slug, "Returning all site attributions");
+        return json_output!(storage.get_site_attribution(site_slug));
+    }
+
+    let limit = match params.first("limit").map(str::parse) {
+        None => DEFAULT_PAGE_LIMIT,
+        Some(Ok(limit @ 1..=MAX_PAGE_LIMIT)) => limit,
+        Some(_) => input_error!(format!(
+            "URL parameter 'limit' must be a number from 1 to {MAX_PAGE_LIMIT}",
+        )),
+    };
+
+    let start_after = match params.first("cursor") {
+        None => None,
+        Some(cursor) => match decode_cursor(site_slug, cursor) {
+            Ok(page_slug) => Some(page_slug),
+            Err(message) => input_error!(message),
+        },
+    };
+
+    info!(site_slug, limit, start_after);
+
+    let result = storage
+        .get_site_attribution_page(site_slug, limit, start_after.as_deref())
+        .await;
+
+    match result {
+        Ok((attributions, last_page)) => {
+            let page = SiteAttributionPage {
+                attributions,
+                cursor: last_page.map(|page_slug| encode_cursor(site_slug, page_slug)),
+            };
+
+            Ok((200, serde_json::to_string(&page)?))
+        }
+        Err(error) => Ok((500, service_error(&*error)?)),
+    }
+}
+
+pub async fn handle_export_site(
+    storage: &dyn Storage,
+    req: Request,
+    format: OutputFormat,
+) -> Result<(u16, String), Error> {
+    info!("Received site attribution export request");
+
+    let site_slug = match req
+        .query_string_parameters_ref()
+        .and_then(|params| params.first("site"))
+    {
+        Some(site_slug) => site_slug,
+        None => input_error!("missing URL parameter 'site'"),
+    };
+
+    info!(site_slug);
+
+    // Without a streaming response, collect the whole export into the body
+    let (sender, body) = HyperBody::channel();
+    let ((), result) = tokio::join!(
+        export_site(storage, site_slug, format, sender),
+        hyper::body::to_bytes(body),
+    );
+
+    match result {
+        Ok(bytes) => Ok((200, String::from_utf8(bytes.to_vec())?)),
+        Err(error) => Ok((500, service_error(&error)?)),
+    }
+}
+
+pub async fn handle_get_license(
+    storage: &dyn Storage,
+    req: Request,
+    format: StatementFormat,
+) -> Result<(u16, String), Error> {
+    info!("Received license statement request");
+
+    let params = match req.query_string_parameters_ref() {
+        Some(params) => params,
+        None => input_error!("missing URL parameters 'site' and 'page'"),
+    };
+
+    let site_slug = match params.first("site") {
+        Some(slug) => slug,
+        None => input_error!("missing URL parameter 'site'"),
+    };
+
+    let page_slug = match params.first("page") {
+        Some(slug) => slug,
+        None => input_error!("missing URL parameter 'page'"),
+    };
+
+    // We don't store titles, so the caller may provide one
+    let title = params.first("title").unwrap_or(page_slug);
+    let language = params
+        .first("lang")
+        .unwrap_or_else(|| site_language(site_slug));
+
+    info!(site_slug, page_slug, title, language);
+
+    if !is_supported_language(language) {
+        input_error!(format!("unsupported language '{language}'"));
+    }
+
+    let attribution = match storage.get_page_attribution(site_slug, page_slug).await {
+        Ok(Some(attribution)) => attribution,
+        Ok(None) => input_error!("no attribution exists for this page"),
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    let url = page_url(site_slug, page_slug);
+    let statement = build_statement(&attribution, title, &url, language, format);
+    Ok((200, statement))
+}
+
+pub async fn handle_import(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received bulk import request");
+
+    let ImportInput {
+        site_slug,
+        admin_password,
+        dry_run,
+        summary,
+        pages,
+        csv,
+    } = parse_body!(&req);
+
+    info!(site_slug, dry_run);
+
+    let actor =
+        check_credentials!(storage, req, site_slug, admin_password, PasswordType::Admin);
+
+    let pages = match (pages, csv) {
+        (Some(pages), None) => pages
+            .into_iter()
+            .map(|page| (page.page_slug, Ok(page.attribution)))
+            .collect(),
+        (None, Some(csv)) => match parse_csv(&csv) {
+            Ok(pages) => pages,
+            Err(message) => input_error!(message),
+        },
+        _ => input_error!("exactly one of 'pages' or 'csv' must be given"),
+    };
+
+    // Read these before the attribution, so that pages changed after the import is
+    // checked are refused rather than overwritten. A dry run writes nothing, so doesn't need them.
+    let mut revisions = BTreeMap::new();
+    if !dry_run {
+        let page_slugs = pages
+            .iter()
+            .map(|(page_slug, _)| page_slug.clone())
+            .collect::<Vec<_>>();
+
+        revisions = match storage.get_page_revisions(&site_slug, &page_slugs).await {
+            Ok(revisions) => revisions,
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        };
+    }
+
+    let mut existing = match storage.get_site_attribution(&site_slug).await {
+        Ok(existing) => existing,
+        Err(error) => return Ok((500, service_error(&*error)?)),
+    };
+
+    // Only slugs without a page of their own can be aliases
+    let stored = existing
+        .iter()
+        .map(|page| page.page_slug.clone())
+        .collect::<HashSet<_>>();
+
+    let mut resolved = Vec::with_capacity(pages.len());
+    let mut targets = Vec::new();
+    for (page_slug, attribution) in pages {
+        let page_slug = if stored.contains(&page_slug) {
+            page_slug
+        } else {
+            let target = resolve_page_slug!(storage, site_slug, page_slug.clone());
+            if target != page_slug && !revisions.contains_key(&target) {
+                targets.push(target.clone());
+            }
+
+            target
+        };
+
+        resolved.push((page_slug, attribution));
+    }
+    let pages = resolved;
+
+    // Renamed pages weren't known when the revisions were read, so read them
+    // again, along with their attribution, in the same order
+    if !dry_run && !targets.is_empty() {
+        match storage.get_page_revisions(&site_slug, &targets).await {
+            Ok(target_revisions) => revisions.extend(target_revisions),
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        }
+
+        let attributions = match storage.get_pages_attribution(&site_slug, &targets).await
+        {
+            Ok(attributions) => attributions,
+            Err(error) => return Ok((500, service_error(&*error)?)),
+        };
+
+        existing.retain(|page| !attributions.contains_key(&page.page_slug));
+        existing.extend(attributions.into_iter().filter_map(
+            |(page_slug, attribution)| {
+                attribution.map(|attribution| FullAttribution {
+                    page_slug,
+                    attribution,
+                })
+            },
+        ));
+    }
+
+    let ImportPlan { mut report, writes } = plan_import(pages, existing, dry_run);
+    info!(
+        created = report.created,
+        updated = report.updated,
+        unchanged = report.unchanged,
+        rejected = report.rejected,
+    );
+
+    if dry_run {
+        return Ok((200, ServiceResult::success(report).to_json()?));
+    }
+
+    if report.rejected > 0 {
+        let message = str!("Some pages were rejected, so nothing was imported");
+
+        let body = ServiceResult::rejected("import-rejected", message, report);
+        return Ok((400, body.to_json()?));
+    }
+
+    let writes = writes
+        .into_iter()
+        .map(
+            |FullAttribution {
+                 page_slug,
+                 attribution,
+             }| PageWrite {
+                expected_revision: revisions.get(&page_slug).copied(),
+                page_slug,
+                attribution: Some(attribution),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    // Write in batches, each of which is atomic. If a batch is too large for a
+    // single transaction, it is split until it fits. If a batch can't be written,
+    // the report says which pages were written before it.
+    let change = Change::new(actor, summary.or_else(|| Some(str!("Bulk import"))));
+    let mut remaining = writes.as_slice();
+    let mut batch_size = MAX_BATCH_PAGES;
+    let mut written = HashMap::new();
+
+    while !remaining.is_empty() {
+        let batch = &remaining[..batch_size.min(remaining.len())];
+        let result = storage
+            .write_pages(site_slug.clone(), batch.to_vec(), &change)
+            .await;
+
+        let (status, error_type, message) = match result {
+            Ok(BatchResult::Written(batch_revisions)) => {
+                for (write, revision) in batch.iter().zip(batch_revisions) {
+                    written.insert(write.page_slug.clone(), revision);
+                }
+
+                remaining = &remaining[batch.len()..];
+                continue;
+            }
+            Ok(BatchResult::TooLarge) if batch.len() > 1 => {
+                debug!(batch_size, "Import batch too large, splitting");
+                batch_size = batch.len() / 2;
+                continue;
+            }
+            Ok(BatchResult::TooLarge) => {
+                let page_slug = &batch[0].page_slug;
+                let message = format!(
+                    "Page '{page_slug}' changes too much to be written, {} pages were imported before it",
+                    written.len(),
+                );
+
+                (400, "import-failed", message)
+            }
+            Ok(BatchResult::Conflict) => {
+                warn!(
+                    imported = written.len(),
+                    "A page has changed since the import was checked",
+                );
+                let message = format!(
+                    "Some pages have been changed since the import was checked, {} pages were imported before this",
+                    written.len(),
+                );
+
+                (412, "conflict", message)
+            }
+            Err(error) => {
+                error!(
+                    imported = written.len(),
+                    "Import failed after some pages were written: {error}",
+                );
+                let message = format!(
+                    "{error}, {} pages were imported before this",
+                    written.len(),
+                );
+
+                (500, "backend", message)
+            }
+        };
+
+        report.set_written(&written);
+        let body = ServiceResult::rejected(error_type, message, report);
+        return Ok((status, body.to_json()?));
+    }
+
+    report.set_written(&written);
+    Ok((200, ServiceResult::success(report).to_json()?))
+}
+
+pub async fn handle_get_wikidot_template(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received Wikidot template request");
 
-    // We only need one URL parameter here.
     let site_slug = match req
         .query_string_parameters_ref()
         .and_then(|params| params.first("site"))
@@ -114,13 +1234,39 @@ pub async fn handle_get_site(req: Request) -> Result<(u16, String), Error> {
     };
 
     info!(site_slug);
-    json_output!(get_site_attribution(&dynamo, site_slug))
+
+    match storage.get_wikidot_template(site_slug).await {
+        Ok(template) => {
+            let template = template.unwrap_or_default();
+            Ok((200, serde_json::to_string(&template)?))
+        }
+        Err(error) => Ok((500, service_error(&*error)?)),
+    }
 }
 
-pub async fn handle_password_check(req: Request) -> Result<(u16, String), Error> {
+pub async fn handle_set_wikidot_template(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received Wikidot template update request");
+
+    let UpdateWikidotTemplateInput {
+        site_slug,
+        admin_password,
+        template,
+    } = parse_body!(&req);
+
+    info!(site_slug);
+    check_credentials!(storage, req, site_slug, admin_password, PasswordType::Admin);
+    success_output!(storage.set_wikidot_template(site_slug, template))
+}
+
+pub async fn handle_password_check(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
     info!("Received password check request");
 
-    let dynamo = connect_dynamo_db().await;
     let CheckPasswordInput {
         site_slug,
         password,
@@ -128,14 +1274,16 @@ pub async fn handle_password_check(req: Request) -> Result<(u16, String), Error>
     } = parse_body!(&req);
 
     info!(site_slug, password_type = password_type.field_name());
-    check_password!(dynamo, site_slug, password, password_type);
-    success!()
+    check_credentials!(storage, req, site_slug, Some(password), password_type);
+    data_output!(issue_session(storage, &site_slug, password_type))
 }
 
-pub async fn handle_password_update(req: Request) -> Result<(u16, String), Error> {
+pub async fn handle_password_update(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
     info!("Received password update request");
 
-    let dynamo = connect_dynamo_db().await;
     let UpdatePasswordInput {
         site_slug,
         password_type,
@@ -145,12 +1293,90 @@ pub async fn handle_password_update(req: Request) -> Result<(u16, String), Error
     } = parse_body!(&req);
 
     info!(site_slug, password_type = password_type.field_name());
-    check_password!(dynamo, site_slug, admin_password, PasswordType::Admin);
-    check_password!(dynamo, site_slug, old_password, password_type);
-    update_password(&dynamo, site_slug, new_password, password_type).await?;
+
+    // The old password is always checked as a password, never as a token
+    check_credentials!(
+        storage,
+        req,
+        site_slug,
+        [
+            (admin_password, PasswordType::Admin),
+            (Some(old_password), password_type),
+        ],
+    );
+    update_password(storage, site_slug, new_password, password_type).await?;
     success!()
 }
 
+pub async fn handle_api_key_create(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received API key creation request");
+
+    let CreateApiKeyInput {
+        site_slug,
+        admin_password,
+        name,
+        scope,
+        expires,
+    } = parse_body!(&req);
+
+    info!(site_slug, name, scope = scope.field_name(), expires);
+    check_credentials!(storage, req, site_slug, admin_password, PasswordType::Admin);
+
+    let name = str!(name.trim());
+    if name.is_empty() {
+        input_error!("API key name cannot be empty");
+    }
+
+    if let Some(expires) = expires {
+        if expires <= unix_time() {
+            input_error!("API key expiry must be in the future");
+        }
+    }
+
+    data_output!(create_api_key(storage, site_slug, name, scope, expires))
+}
+
+pub async fn handle_api_key_list(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received API key list request");
+
+    let ListApiKeysInput {
+        site_slug,
+        admin_password,
+    } = parse_body!(&req);
+
+    info!(site_slug);
+    check_credentials!(storage, req, site_slug, admin_password, PasswordType::Admin);
+    data_output!(storage.list_api_keys(&site_slug))
+}
+
+pub async fn handle_api_key_revoke(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<(u16, String), Error> {
+    info!("Received API key revocation request");
+
+    let RevokeApiKeyInput {
+        site_slug,
+        admin_password,
+        key_id,
+    } = parse_body!(&req);
+
+    info!(site_slug, key_id);
+    check_credentials!(storage, req, site_slug, admin_password, PasswordType::Admin);
+
+    match storage.delete_api_key(site_slug, key_id).await {
+        Ok(true) => success!(),
+        Ok(false) => input_error!("No API key with this ID exists for the site"),
+        Err(error) => Ok((500, service_error(&*error)?)),
+    }
+}
+
 pub fn handle_info() -> Result<(u16, String), Error> {
     info!("Received info request");
 
@@ -209,3 +1435,460 @@ pub fn handle_missing_route(method: &str, path: &str) -> Result<(u16, String), E
 
     Ok((400, body))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Actor, ClientAddress};
+    use crate::etag::format_etag;
+    use crate::object::{Attribution, AttributionEntry, AttributionType};
+    use crate::storage::MemoryStorage;
+    use lambda_http::aws_lambda_events::query_map::QueryMap;
+    use lambda_http::http::header::IF_MATCH;
+    use serde_json::{json, Value};
+    use std::collections::{BTreeMap, HashMap};
+    use std::net::IpAddr;
+
+    const SITE: &str = "scp-wiki";
+
+    /// Creates an attribution list whose entry already has an ID, so it is stored as is.
+    fn attribution(user_name: &str) -> Attribution {
+        Attribution(vec![AttributionEntry {
+            id: Some(user_name.to_lowercase()),
+            attribution_type: AttributionType::Author,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }])
+    }
+
+    fn change() -> Change {
+        Change::new(
+            Actor::Password(PasswordType::Regular),
+            Some(str!("Testing")),
+        )
+    }
+
+    /// Creates storage where `scp-173` has been renamed to `scp-173-new`.
+    async fn storage() -> MemoryStorage {
+        let storage = MemoryStorage::new();
+        for (password_type, password) in [
+            (PasswordType::Regular, "hunter2"),
+            (PasswordType::Admin, "hunter3"),
+        ] {
+            storage
+                .set_password(str!(SITE), password_type, str!(password))
+                .await
+                .unwrap();
+        }
+
+        storage
+            .update_page_attribution(
+                str!(SITE),
+                str!("scp-173"),
+                attribution("Alice"),
+                &change(),
+            )
+            .await
+            .unwrap();
+        storage
+            .rename_page(str!(SITE), str!("scp-173"), str!("scp-173-new"), &change())
+            .await
+            .unwrap();
+        storage
+    }
+
+    fn request(body: Value) -> Request {
+        let mut req = lambda_http::http::Request::builder()
+            .body(Body::Text(body.to_string()))
+            .unwrap();
+        let address = IpAddr::from([192, 0, 2, 1]);
+        req.extensions_mut().insert(ClientAddress(address));
+        req
+    }
+
+    fn query(params: &[(&str, &str)]) -> Request {
+        let params = params
+            .iter()
+            .map(|&(key, value)| (str!(key), str!(value)))
+            .collect::<HashMap<_, _>>();
+
+        lambda_http::http::Request::builder()
+            .body(Body::Empty)
+            .unwrap()
+            .with_query_string_parameters(QueryMap::from(params))
+    }
+
+    async fn stored(storage: &MemoryStorage, page_slug: &str) -> Option<Attribution> {
+        assert_eq!(
+            storage
+                .get_alias_target(SITE, "scp-173")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("scp-173-new"),
+            "old slug is no longer an alias",
+        );
+
+        let pages = storage.get_site_attribution(SITE).await.unwrap();
+        pages
+            .into_iter()
+            .find(|page| page.page_slug == page_slug)
+            .map(|page| page.attribution)
+    }
+
+    #[tokio::test]
+    async fn resolve_slug() {
+        let storage = storage().await;
+        let resolve = |page_slug| resolve_page_slug(&storage, SITE, str!(page_slug));
+
+        assert_eq!(resolve("scp-173").await.unwrap(), "scp-173-new");
+        assert_eq!(resolve("scp-173-new").await.unwrap(), "scp-173-new");
+        assert_eq!(resolve("scp-001").await.unwrap(), "scp-001");
+    }
+
+    #[tokio::test]
+    async fn set_page_alias() {
+        let storage = storage().await;
+        let body = json!({
+            "site": SITE,
+            "page": "scp-173",
+            "password": "hunter2",
+            "attributions": attribution("Bob"),
+        });
+
+        let mut revision = None;
+        let (status, _) = handle_set_page(&storage, request(body), &mut revision)
+            .await
+            .unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(stored(&storage, "scp-173").await, None);
+        assert_eq!(
+            stored(&storage, "scp-173-new").await,
+            Some(attribution("Bob"))
+        );
+
+        // The ETag of the old slug is the renamed page's revision
+        let latest = storage
+            .get_page_revision(SITE, "scp-173-new")
+            .await
+            .unwrap();
+        assert_eq!(revision, Some(latest));
+
+        let req = query(&[("site", SITE), ("page", "scp-173")]);
+        let mut revision = None;
+        let (status, _) =
+            handle_get_page(&storage, req, OutputFormat::Json, &mut revision)
+                .await
+                .unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(revision, Some(latest));
+    }
+
+    #[tokio::test]
+    async fn patch_page_alias() {
+        let storage = storage().await;
+        let body = json!({
+            "site": SITE,
+            "page": "scp-173",
+            "password": "hunter2",
+            "operations": [
+                { "op": "add", "entry": { "type": "translator", "user_name": "Bob" } },
+            ],
+        });
+
+        let latest = storage
+            .get_page_revision(SITE, "scp-173-new")
+            .await
+            .unwrap();
+        let patch = |expected_revision: u64| {
+            let mut req = request(body.clone());
+            let etag = format_etag(expected_revision).parse().unwrap();
+            req.headers_mut().insert(IF_MATCH, etag);
+            let storage = &storage;
+            async move {
+                let mut revision = None;
+                let (status, _) = handle_patch_page(storage, req, &mut revision)
+                    .await
+                    .unwrap();
+                (status, revision)
+            }
+        };
+
+        // A stale revision changes nothing
+        assert_eq!(patch(latest - 1).await, (412, Some(latest)));
+        assert_eq!(patch(latest).await, (200, Some(latest + 1)));
+        assert_eq!(stored(&storage, "scp-173").await, None);
+
+        // The added entry is given an ID when stored
+        let mut expected = attribution("Alice");
+        expected.0.push(AttributionEntry {
+            id: None,
+            attribution_type: AttributionType::Translator,
+            user_name: str!("Bob"),
+            user_id: None,
+            date: None,
+        });
+
+        let patched = stored(&storage, "scp-173-new").await.unwrap();
+        assert!(patched.0.iter().all(|entry| entry.id.is_some()));
+        assert!(expected.same_entries(&patched));
+    }
+
+    #[tokio::test]
+    async fn batch_write_alias() {
+        let storage = storage().await;
+        let batch = |pages: Value| {
+            let body = json!({ "site": SITE, "password": "hunter2", "pages": pages });
+            handle_batch_write(&storage, request(body))
+        };
+
+        let (status, body) = batch(json!([
+            { "page": "scp-173", "attributions": attribution("Bob") },
+            { "page": "scp-001", "attributions": attribution("Carol"), "revision": 0 },
+        ]))
+        .await
+        .unwrap();
+        assert_eq!(status, 200);
+        assert!(
+            body.contains(r#"{"page":"scp-173-new","revision":2}"#),
+            "{body}"
+        );
+        assert_eq!(stored(&storage, "scp-173").await, None);
+        assert_eq!(
+            stored(&storage, "scp-173-new").await,
+            Some(attribution("Bob"))
+        );
+        assert_eq!(
+            stored(&storage, "scp-001").await,
+            Some(attribution("Carol"))
+        );
+
+        // Both slugs are the same page
+        let (status, body) = batch(json!([
+            { "page": "scp-173", "attributions": attribution("Dave") },
+            { "page": "scp-173-new", "attributions": [] },
+        ]))
+        .await
+        .unwrap();
+        assert_eq!(status, 400);
+        assert!(body.contains("Page appears more than once in the batch"));
+
+        // A stale revision writes nothing, and returns the current revisions
+        let (status, body) = batch(json!([
+            { "page": "scp-002", "attributions": attribution("Dave") },
+            { "page": "scp-001", "attributions": [], "revision": 0 },
+        ]))
+        .await
+        .unwrap();
+        assert_eq!(status, 412);
+        assert!(
+            body.contains(r#"{"page":"scp-001","revision":1}"#),
+            "{body}"
+        );
+        assert_eq!(stored(&storage, "scp-002").await, None);
+        assert_eq!(
+            stored(&storage, "scp-001").await,
+            Some(attribution("Carol"))
+        );
+    }
+
+    #[tokio::test]
+    async fn batch_write_too_many() {
+        let storage = storage().await;
+        for (count, status) in
+            [(0, 400), (MAX_BATCH_PAGES, 200), (MAX_BATCH_PAGES + 1, 400)]
+        {
+            let pages = (0..count)
+                .map(|index| json!({ "page": format!("page-{index}"), "attributions": [] }))
+                .collect::<Vec<_>>();
+
+            let body = json!({ "site": SITE, "password": "hunter2", "pages": pages });
+            let (actual, _) = handle_batch_write(&storage, request(body)).await.unwrap();
+            assert_eq!(actual, status, "batch of {count} pages");
+        }
+    }
+
+    #[tokio::test]
+    async fn get_pages_too_many() {
+        let storage = storage().await;
+        for (count, status) in
+            [(MAX_BATCH_READ_PAGES, 200), (MAX_BATCH_READ_PAGES + 1, 400)]
+        {
+            let pages = (0..count)
+                .map(|index| format!("page-{index}"))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let req = query(&[("site", SITE), ("pages", &pages)]);
+            let (actual, _) = handle_get_pages(&storage, req).await.unwrap();
+            assert_eq!(actual, status, "batch of {count} pages");
+        }
+
+        // Empty slugs aren't counted
+        let pages = format!("scp-999,{}", ",".repeat(MAX_BATCH_READ_PAGES));
+        let req = query(&[("site", SITE), ("pages", &pages)]);
+        let (status, body) = handle_get_pages(&storage, req).await.unwrap();
+        assert_eq!(status, 200);
+
+        let pages: BTreeMap<String, Option<Attribution>> =
+            serde_json::from_str(&body).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages["scp-999"].is_none());
+    }
+
+    #[tokio::test]
+    async fn get_page_history_alias() {
+        let storage = storage().await;
+        let req = query(&[("site", SITE), ("page", "scp-173")]);
+        let (status, body) = handle_get_page_history(&storage, req).await.unwrap();
+        assert_eq!(status, 200);
+
+        let history = storage.get_page_history(SITE, "scp-173-new").await.unwrap();
+        assert_eq!(body, serde_json::to_string(&history).unwrap());
+    }
+
+    #[tokio::test]
+    async fn revert_page_alias() {
+        let storage = storage().await;
+        storage
+            .update_page_attribution(
+                str!(SITE),
+                str!("scp-173-new"),
+                attribution("Bob"),
+                &change(),
+            )
+            .await
+            .unwrap();
+
+        let body = json!({
+            "site": SITE,
+            "page": "scp-173",
+            "password": "hunter2",
+            "revision": 1,
+        });
+
+        let (status, _) = handle_revert_page(&storage, request(body)).await.unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(stored(&storage, "scp-173").await, None);
+        assert_eq!(
+            stored(&storage, "scp-173-new").await,
+            Some(attribution("Alice")),
+        );
+    }
+
+    #[tokio::test]
+    async fn import_alias() {
+        let storage = storage().await;
+        let body = json!({
+            "site": SITE,
+            "admin_password": "hunter3",
+            "pages": [{ "page_slug": "scp-173", "attribution": attribution("Bob") }],
+        });
+
+        let (status, body) = handle_import(&storage, request(body)).await.unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(stored(&storage, "scp-173").await, None);
+        assert_eq!(
+            stored(&storage, "scp-173-new").await,
+            Some(attribution("Bob"))
+        );
+
+        // The renamed page was at revision 1, and is checked against it
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["data"]["pages"][0]["revision"], 2);
+
+        // Both slugs are the same page
+        let body = json!({
+            "site": SITE,
+            "admin_password": "hunter3",
+            "pages": [
+                { "page_slug": "scp-173", "attribution": attribution("Carol") },
+                { "page_slug": "scp-173-new", "attribution": attribution("Dave") },
+            ],
+        });
+
+        let (status, _) = handle_import(&storage, request(body)).await.unwrap();
+        assert_eq!(status, 400);
+        assert_eq!(
+            stored(&storage, "scp-173-new").await,
+            Some(attribution("Bob"))
+        );
+    }
+
+    #[tokio::test]
+    async fn import_batches() {
+        let storage = storage().await;
+        let mut pages = (0..MAX_BATCH_PAGES * 2 + 1)
+            .map(|index| {
+                json!({
+                    "page_slug": format!("page-{index}"),
+                    "attribution": attribution("Bob"),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        pages.push(
+            json!({ "page_slug": "scp-173-new", "attribution": attribution("Alice") }),
+        );
+
+        let body = json!({ "site": SITE, "admin_password": "hunter3", "pages": pages });
+        let (status, body) = handle_import(&storage, request(body)).await.unwrap();
+        assert_eq!(status, 200);
+
+        // Every page written reports its new revision, while unchanged pages have none
+        let body: Value = serde_json::from_str(&body).unwrap();
+        let report = &body["data"];
+        assert_eq!(report["created"], MAX_BATCH_PAGES * 2 + 1);
+        assert_eq!(report["unchanged"], 1);
+        assert_eq!(report["imported"], MAX_BATCH_PAGES * 2 + 1);
+
+        let pages = report["pages"].as_array().unwrap();
+        let (unchanged, created) = pages.split_last().unwrap();
+        assert!(created.iter().all(|page| page["revision"] == 1));
+        assert!(unchanged.get("revision").is_none());
+
+        let stored = storage
+            .get_page_attribution(SITE, &format!("page-{}", MAX_BATCH_PAGES * 2))
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(attribution("Bob")));
+    }
+
+    #[tokio::test]
+    async fn set_translation_alias() {
+        let storage = storage().await;
+        let set_translation = |page_slug, original_slug| {
+            let body = json!({
+                "site": SITE,
+                "page": page_slug,
+                "password": "hunter2",
+                "translation_of": { "site": SITE, "page": original_slug },
+            });
+
+            handle_set_translation(&storage, request(body))
+        };
+
+        let reference = |page_slug| PageReference {
+            site_slug: str!(SITE),
+            page_slug: str!(page_slug),
+        };
+
+        // Links are made between the pages themselves
+        let (status, _) = set_translation("scp-173", "scp-001").await.unwrap();
+        assert_eq!(status, 200);
+        let (status, _) = set_translation("scp-002", "scp-173").await.unwrap();
+        assert_eq!(status, 200);
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173-new")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(reference("scp-001")));
+        assert_eq!(translations.translations, vec![reference("scp-002")]);
+
+        // An old slug is the same page
+        let (status, _) = set_translation("scp-173", "scp-173-new").await.unwrap();
+        assert_eq!(status, 400);
+    }
+}
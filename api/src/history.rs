@@ -0,0 +1,221 @@
+/*
+ * history.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+use crate::auth::Actor;
+use crate::object::Attribution;
+use crate::utils::unix_time;
+
+/// Information about a write being made, to be recorded in the page's history.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub actor: Actor,
+    pub summary: Option<String>,
+
+    /// If set, the write is only made if this is still the latest revision of the page.
+    ///
+    /// A page which has never been written has revision 0.
+    pub expected_revision: Option<u64>,
+}
+
+impl Change {
+    #[inline]
+    pub fn new(actor: Actor, summary: Option<String>) -> Self {
+        // Treat an empty summary as none at all
+        let summary = summary
+            .map(|summary| str!(summary.trim()))
+            .filter(|summary| !summary.is_empty());
+
+        Change {
+            actor,
+            summary,
+            expected_revision: None,
+        }
+    }
+}
+
+/// An immutable record of a single write to a page's attribution.
+///
+/// Revisions are numbered sequentially per page, starting from 1.
+/// A value of `None` for either attribution means the page had none,
+/// that is, it was created or deleted by this revision.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Revision {
+    pub revision: u64,
+
+    /// When this revision was made, in seconds since the UNIX epoch.
+    pub timestamp: u64,
+    pub actor: String,
+    pub summary: Option<String>,
+    pub previous: Option<Attribution>,
+    pub attribution: Option<Attribution>,
+}
+
+impl Revision {
+    /// Creates a new revision as of now, for the given write.
+    pub fn new(
+        revision: u64,
+        previous: Option<Attribution>,
+        attribution: Option<Attribution>,
+        change: &Change,
+    ) -> Self {
+        Revision {
+            revision,
+            timestamp: unix_time(),
+            actor: str!(change.actor),
+            summary: change.summary.clone(),
+            previous,
+            attribution,
+        }
+    }
+}
+
+/// A point in a page's history to revert it to.
+#[derive(Debug, Copy, Clone)]
+pub enum RevertTarget {
+    /// The state of the page after the given revision.
+    Revision(u64),
+
+    /// The state of the page at the given time.
+    Timestamp(u64),
+}
+
+impl RevertTarget {
+    /// Finds the page's attribution as of this point in its history.
+    ///
+    /// On success, a value of `None` means the page had no attribution at the time.
+    /// The error contains a message describing why the point could not be found.
+    pub fn find(self, history: &[Revision]) -> Result<Option<Attribution>, String> {
+        match self {
+            RevertTarget::Revision(revision) => {
+                match history.iter().find(|rev| rev.revision == revision) {
+                    Some(rev) => Ok(rev.attribution.clone()),
+                    None => Err(format!("no revision {revision} exists for this page")),
+                }
+            }
+            RevertTarget::Timestamp(timestamp) => {
+                // The page may have had attribution from before history was kept,
+                // so the state before the first revision is what that revision replaced.
+                let first = match history.first() {
+                    Some(rev) => rev,
+                    None => return Err(str!("this page has no history")),
+                };
+
+                match history.iter().rev().find(|rev| rev.timestamp <= timestamp) {
+                    Some(rev) => Ok(rev.attribution.clone()),
+                    None => Ok(first.previous.clone()),
+                }
+            }
+        }
+    }
+
+    /// The default summary for a revert made without one.
+    pub fn summary(self) -> String {
+        match self {
+            RevertTarget::Revision(revision) => format!("Revert to revision {revision}"),
+            RevertTarget::Timestamp(timestamp) => format!("Revert to time {timestamp}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{AttributionEntry, AttributionType};
+    use crate::password::PasswordType;
+
+    #[test]
+    fn summary() {
+        let actor = Actor::Password(PasswordType::Regular);
+        let change = |summary: Option<&str>| {
+            Change::new(actor.clone(), summary.map(str::to_owned)).summary
+        };
+
+        assert_eq!(change(Some("  Fix date ")).as_deref(), Some("Fix date"));
+        assert_eq!(change(Some(" \n")), None);
+        assert_eq!(change(None), None);
+    }
+
+    fn attribution(user_name: &str) -> Option<Attribution> {
+        Some(Attribution(vec![AttributionEntry {
+            id: None,
+            attribution_type: AttributionType::Author,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }]))
+    }
+
+    fn revision(
+        revision: u64,
+        timestamp: u64,
+        previous: Option<Attribution>,
+        attribution: Option<Attribution>,
+    ) -> Revision {
+        Revision {
+            revision,
+            timestamp,
+            actor: str!("password:regular"),
+            summary: None,
+            previous,
+            attribution,
+        }
+    }
+
+    /// Attribution from before history was kept, changed twice, then deleted.
+    fn history() -> Vec<Revision> {
+        vec![
+            revision(1, 100, attribution("Alice"), attribution("Bob")),
+            revision(2, 200, attribution("Bob"), attribution("Carol")),
+            revision(3, 300, attribution("Carol"), None),
+        ]
+    }
+
+    #[test]
+    fn by_revision() {
+        let history = history();
+        assert_eq!(
+            RevertTarget::Revision(1).find(&history),
+            Ok(attribution("Bob"))
+        );
+        assert_eq!(
+            RevertTarget::Revision(2).find(&history),
+            Ok(attribution("Carol"))
+        );
+        assert_eq!(RevertTarget::Revision(3).find(&history), Ok(None));
+        assert_eq!(
+            RevertTarget::Revision(4).find(&history),
+            Err(str!("no revision 4 exists for this page")),
+        );
+    }
+
+    #[test]
+    fn by_timestamp() {
+        let history = history();
+        let find = |timestamp| RevertTarget::Timestamp(timestamp).find(&history);
+
+        assert_eq!(find(50), Ok(attribution("Alice")));
+        assert_eq!(find(100), Ok(attribution("Bob")));
+        assert_eq!(find(250), Ok(attribution("Carol")));
+        assert_eq!(find(300), Ok(None));
+        assert_eq!(find(u64::MAX), Ok(None));
+    }
+
+    #[test]
+    fn no_history() {
+        assert_eq!(
+            RevertTarget::Timestamp(100).find(&[]),
+            Err(str!("this page has no history")),
+        );
+        assert!(RevertTarget::Revision(1).find(&[]).is_err());
+    }
+}
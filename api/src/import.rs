@@ -0,0 +1,588 @@
+/*
+ * import.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Bulk import of attribution for many pages in a site at once.
+//!
+//! Every page is validated and compared against what is currently stored,
+//! producing a report of what would happen to each. Nothing is written unless
+//! every page in the import is valid. Pages are then written in batches, so the
+//! report also records which pages have been written, in case a later batch fails.
+
+use crate::object::{Attribution, AttributionEntry, AttributionType, FullAttribution};
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU32;
+
+#[derive(Deserialize, Debug)]
+pub struct ImportInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    /// The site's admin password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub admin_password: Option<String>,
+
+    /// If true, only report what the import would do, without writing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// An optional description of the import, recorded in each page's history.
+    pub summary: Option<String>,
+
+    /// The pages to import, as JSON.
+    pub pages: Option<Vec<FullAttribution>>,
+
+    /// The pages to import, as CSV with the same columns as a CSV export.
+    ///
+    /// Exactly one of this and `pages` must be given.
+    pub csv: Option<String>,
+}
+
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportAction {
+    Create,
+    Update,
+    Unchanged,
+    Reject,
+}
+
+/// What the import does, or would do, for a single page.
+#[derive(Serialize, Debug)]
+pub struct PageReport {
+    pub page_slug: String,
+    pub action: ImportAction,
+
+    /// Why the page was rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// The page's new revision, once it has been written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub rejected: usize,
+
+    /// How many pages have been written.
+    pub imported: usize,
+    pub pages: Vec<PageReport>,
+}
+
+impl ImportReport {
+    /// Records the new revision of each page which has been written.
+    pub fn set_written(&mut self, revisions: &HashMap<String, u64>) {
+        for page in &mut self.pages {
+            if page.action != ImportAction::Reject {
+                page.revision = revisions.get(&page.page_slug).copied();
+            }
+        }
+
+        self.imported = revisions.len();
+    }
+}
+
+/// The result of checking an import, with the writes that need to be made.
+#[derive(Debug)]
+pub struct ImportPlan {
+    pub report: ImportReport,
+    pub writes: Vec<FullAttribution>,
+}
+
+/// A page from the import input, or the reason it couldn't be read.
+pub type ImportPage = (String, Result<Attribution, String>);
+
+#[derive(Deserialize, Debug)]
+struct CsvRow {
+    page_slug: String,
+    #[serde(rename = "type")]
+    attribution_type: String,
+    user_name: String,
+    user_id: String,
+    date: String,
+
+    /// Exports made before entries had IDs don't have this column.
+    #[serde(default)]
+    id: String,
+}
+
+/// Reads pages from CSV, grouping rows by page in the order they first appear.
+///
+/// Problems with individual rows cause that page to be rejected, while an error
+/// in the structure of the file itself is returned as an error message.
+pub fn parse_csv(csv: &str) -> Result<Vec<ImportPage>, String> {
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers = reader.headers().map_err(|error| str!(error))?.clone();
+    let mut pages: Vec<ImportPage> = Vec::new();
+    let mut indices = HashMap::new();
+
+    for record in reader.records() {
+        let record = record.map_err(|error| str!(error))?;
+        let line = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or_default();
+
+        let row: CsvRow = record
+            .deserialize(Some(&headers))
+            .map_err(|error| str!(error))?;
+
+        let entry =
+            convert_csv_row(&row).map_err(|error| format!("line {line}: {error}"));
+
+        let index = *indices.entry(row.page_slug.clone()).or_insert_with(|| {
+            pages.push((row.page_slug, Ok(Attribution(Vec::new()))));
+            pages.len() - 1
+        });
+
+        // Keep only the first error for each page
+        match (&mut pages[index].1, entry) {
+            (Ok(attribution), Ok(entry)) => attribution.0.push(entry),
+            (result @ Ok(_), Err(error)) => *result = Err(error),
+            (Err(_), _) => (),
+        }
+    }
+
+    Ok(pages)
+}
+
+fn convert_csv_row(row: &CsvRow) -> Result<AttributionEntry, String> {
+    let attribution_type = AttributionType::try_from(row.attribution_type.as_str())
+        .map_err(|_| format!("invalid attribution type '{}'", row.attribution_type))?;
+
+    let user_id = match row.user_id.trim() {
+        "" => None,
+        value => match value.parse::<NonZeroU32>() {
+            Ok(user_id) => Some(user_id),
+            Err(_) => return Err(format!("invalid user ID '{value}'")),
+        },
+    };
+
+    let date = match row.date.trim() {
+        "" => None,
+        value => Some(str!(value)),
+    };
+
+    let id = match row.id.trim() {
+        "" => None,
+        value => Some(str!(value)),
+    };
+
+    Ok(AttributionEntry {
+        id,
+        attribution_type,
+        user_name: row.user_name.clone(),
+        user_id,
+        date,
+    })
+}
+
+/// Checks every page in an import against the site's current attribution.
+pub fn plan_import(
+    pages: Vec<ImportPage>,
+    existing: Vec<FullAttribution>,
+    dry_run: bool,
+) -> ImportPlan {
+    let existing = existing
+        .into_iter()
+        .map(
+            |FullAttribution {
+                 page_slug,
+                 attribution,
+             }| (page_slug, attribution),
+        )
+        .collect::<HashMap<_, _>>();
+
+    let mut seen = HashSet::new();
+    let mut reports = Vec::new();
+    let mut writes = Vec::new();
+
+    for (page_slug, result) in pages {
+        let checked = if page_slug.is_empty() {
+            Err(str!("Page slug cannot be empty"))
+        } else if !seen.insert(page_slug.clone()) {
+            Err(str!("Page appears more than once in the import"))
+        } else {
+            result
+                .and_then(|attribution| check_page(attribution, existing.get(&page_slug)))
+        };
+
+        let (action, message) = match checked {
+            Ok((action, attribution)) => {
+                if action != ImportAction::Unchanged {
+                    writes.push(FullAttribution {
+                        page_slug: page_slug.clone(),
+                        attribution,
+                    });
+                }
+
+                (action, None)
+            }
+            Err(message) => (ImportAction::Reject, Some(message)),
+        };
+
+        reports.push(PageReport {
+            page_slug,
+            action,
+            message,
+            revision: None,
+        });
+    }
+
+    let count = |action| {
+        reports
+            .iter()
+            .filter(|report| report.action == action)
+            .count()
+    };
+    let report = ImportReport {
+        dry_run,
+        created: count(ImportAction::Create),
+        updated: count(ImportAction::Update),
+        unchanged: count(ImportAction::Unchanged),
+        rejected: count(ImportAction::Reject),
+        imported: 0,
+        pages: reports,
+    };
+
+    ImportPlan { report, writes }
+}
+
+/// Normalizes and validates a page's attribution, in the same way as a regular update.
+fn check_page(
+    mut attribution: Attribution,
+    existing: Option<&Attribution>,
+) -> Result<(ImportAction, Attribution), String> {
+    if attribution.0.is_empty() {
+        return Err(str!("Page has no attribution entries"));
+    }
+
+    attribution.sort();
+    attribution.validate()?;
+
+    // Match up entries without IDs first, so that importing an unchanged page is a no-op
+    attribution.assign_ids(existing);
+
+    let action = match existing {
+        None => ImportAction::Create,
+        Some(existing) if existing.same_entries(&attribution) => ImportAction::Unchanged,
+        Some(_) => ImportAction::Update,
+    };
+
+    Ok((action, attribution))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::write_csv;
+
+    fn entry(attribution_type: AttributionType, user_name: &str) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }
+    }
+
+    fn attribution(user_name: &str) -> Attribution {
+        Attribution(vec![entry(AttributionType::Author, user_name)])
+    }
+
+    fn full(page_slug: &str, user_name: &str) -> FullAttribution {
+        FullAttribution {
+            page_slug: str!(page_slug),
+            attribution: attribution(user_name),
+        }
+    }
+
+    fn page(page_slug: &str, user_name: &str) -> ImportPage {
+        (str!(page_slug), Ok(attribution(user_name)))
+    }
+
+    fn actions(plan: &ImportPlan) -> Vec<(&str, ImportAction)> {
+        plan.report
+            .pages
+            .iter()
+            .map(|report| (report.page_slug.as_str(), report.action))
+            .collect()
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let attributions = vec![
+            FullAttribution {
+                page_slug: str!("scp-173"),
+                attribution: Attribution(vec![
+                    AttributionEntry {
+                        id: Some(str!("0123456789abcdef")),
+                        user_id: NonZeroU32::new(42),
+                        date: Some(str!("2008-07-25")),
+                        ..entry(AttributionType::Author, "Moto42")
+                    },
+                    entry(AttributionType::Rewrite, "Someone, \"quoted\""),
+                ]),
+            },
+            full("scp-001", "Dr Gears"),
+        ];
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &attributions, true).unwrap();
+        let pages = parse_csv(&String::from_utf8(out).unwrap()).unwrap();
+
+        let expected: Vec<_> = attributions
+            .into_iter()
+            .map(|full| (full.page_slug, Ok(full.attribution)))
+            .collect();
+
+        assert_eq!(pages, expected);
+    }
+
+    #[test]
+    fn csv_bad_row() {
+        let csv = "page_slug,type,user_name,user_id,date\n\
+                   scp-173,author,Moto42,,\n\
+                   scp-173,writer,Moto42,,\n\
+                   scp-173,rewrite,Moto42,zero,\n\
+                   scp-001,author,Dr Gears,-1,\n\
+                   scp-002,author,Someone,,\n";
+
+        // Only the first error for a page is kept
+        let pages = parse_csv(csv).unwrap();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(
+            pages[0].1,
+            Err(str!("line 3: invalid attribution type 'writer'")),
+        );
+        assert_eq!(pages[1].1, Err(str!("line 5: invalid user ID '-1'")));
+        assert!(pages[2].1.is_ok());
+    }
+
+    #[test]
+    fn csv_bad_structure() {
+        assert!(parse_csv("page_slug,type\nscp-173,author\n").is_err());
+        assert!(parse_csv("page_slug,type,user_name,user_id,date\nscp-173\n").is_err());
+    }
+
+    #[test]
+    fn plan_actions() {
+        let existing = vec![full("scp-001", "Dr Gears"), full("scp-002", "Someone")];
+        let pages = vec![
+            page("scp-173", "Moto42"),
+            page("scp-001", "Dr Gears"),
+            page("scp-002", "Someone Else"),
+        ];
+
+        let plan = plan_import(pages, existing, false);
+        assert_eq!(
+            actions(&plan),
+            [
+                ("scp-173", ImportAction::Create),
+                ("scp-001", ImportAction::Unchanged),
+                ("scp-002", ImportAction::Update),
+            ],
+        );
+
+        // Unchanged pages are not written
+        let writes: Vec<_> = plan
+            .writes
+            .iter()
+            .map(|full| full.page_slug.as_str())
+            .collect();
+        assert_eq!(writes, ["scp-173", "scp-002"]);
+        assert!(attribution("Moto42").same_entries(&plan.writes[0].attribution));
+        assert!(attribution("Someone Else").same_entries(&plan.writes[1].attribution));
+
+        let report = &plan.report;
+        assert!(!report.dry_run);
+        assert_eq!(
+            (
+                report.created,
+                report.updated,
+                report.unchanged,
+                report.rejected
+            ),
+            (1, 1, 1, 0),
+        );
+    }
+
+    #[test]
+    fn plan_entry_ids() {
+        let with_id = |user_name, id: &str| {
+            Attribution(vec![AttributionEntry {
+                id: Some(str!(id)),
+                ..entry(AttributionType::Author, user_name)
+            }])
+        };
+
+        let existing = vec![
+            FullAttribution {
+                page_slug: str!("scp-173"),
+                attribution: with_id("Moto42", "0000000000000001"),
+            },
+            FullAttribution {
+                page_slug: str!("scp-001"),
+                attribution: with_id("Dr Gears", "0000000000000002"),
+            },
+            // Stored before entries had IDs
+            full("scp-002", "Someone"),
+        ];
+
+        let pages = vec![
+            page("scp-173", "Moto42"),
+            (str!("scp-001"), Ok(with_id("Dr Gears", "0000000000000003"))),
+            page("scp-002", "Someone"),
+            page("scp-003", "Someone Else"),
+        ];
+
+        // Entries without IDs match existing ones, whether or not they have an ID
+        let plan = plan_import(pages, existing, false);
+        assert_eq!(
+            actions(&plan),
+            [
+                ("scp-173", ImportAction::Unchanged),
+                ("scp-001", ImportAction::Update),
+                ("scp-002", ImportAction::Unchanged),
+                ("scp-003", ImportAction::Create),
+            ],
+        );
+
+        // Written entries are given IDs, keeping any they were given
+        assert_eq!(
+            plan.writes[0].attribution,
+            with_id("Dr Gears", "0000000000000003")
+        );
+        assert!(plan.writes[1].attribution.0[0].id.is_some());
+    }
+
+    #[test]
+    fn report_written() {
+        let existing = vec![full("scp-001", "Dr Gears")];
+        let pages = vec![
+            page("scp-173", "Moto42"),
+            page("scp-001", "Dr Gears"),
+            page("scp-002", "Someone"),
+            page("scp-003", "Someone Else"),
+        ];
+
+        // As if the batch with the last page failed
+        let mut report = plan_import(pages, existing, false).report;
+        assert_eq!(report.imported, 0);
+
+        let written = hashmap! {
+            str!("scp-173") => 1,
+            str!("scp-002") => 1,
+        };
+        report.set_written(&written);
+
+        let revisions: Vec<_> = report
+            .pages
+            .iter()
+            .map(|report| (report.page_slug.as_str(), report.revision))
+            .collect();
+
+        assert_eq!(
+            revisions,
+            [
+                ("scp-173", Some(1)),
+                ("scp-001", None),
+                ("scp-002", Some(1)),
+                ("scp-003", None),
+            ],
+        );
+        assert_eq!(report.imported, 2);
+    }
+
+    #[test]
+    fn plan_normalizes() {
+        // Entries are sorted and validated as in a regular update,
+        // so an import matching the stored attribution is unchanged
+        let mut dated = entry(AttributionType::Rewrite, "Moto42");
+        dated.date = Some(str!("2010-01-01"));
+        let undated = entry(AttributionType::Author, " Moto42 ");
+
+        let existing = vec![FullAttribution {
+            page_slug: str!("scp-173"),
+            attribution: Attribution(vec![
+                entry(AttributionType::Author, "Moto42"),
+                dated.clone(),
+            ]),
+        }];
+
+        let pages = vec![(str!("scp-173"), Ok(Attribution(vec![dated, undated])))];
+        let plan = plan_import(pages, existing, true);
+        assert_eq!(actions(&plan), [("scp-173", ImportAction::Unchanged)]);
+        assert!(plan.writes.is_empty());
+        assert!(plan.report.dry_run);
+    }
+
+    #[test]
+    fn plan_rejects() {
+        let mut bad_date = attribution("Moto42");
+        bad_date.0[0].date = Some(str!("July 2008"));
+
+        let pages = vec![
+            page("scp-173", "Moto42"),
+            page("scp-173", "Moto42"),
+            page("", "Moto42"),
+            (str!("scp-001"), Ok(Attribution(vec![]))),
+            (str!("scp-002"), Ok(bad_date)),
+            (str!("scp-003"), Err(str!("line 2: bad row"))),
+            page("scp-004", " "),
+        ];
+
+        let plan = plan_import(pages, vec![], false);
+        let messages: Vec<_> = plan
+            .report
+            .pages
+            .iter()
+            .map(|report| (report.action, report.message.as_deref()))
+            .collect();
+
+        assert_eq!(
+            messages,
+            [
+                (ImportAction::Create, None),
+                (
+                    ImportAction::Reject,
+                    Some("Page appears more than once in the import"),
+                ),
+                (ImportAction::Reject, Some("Page slug cannot be empty")),
+                (
+                    ImportAction::Reject,
+                    Some("Page has no attribution entries")
+                ),
+                (
+                    ImportAction::Reject,
+                    Some("Date value 'July 2008' is invalid (must be YYYY-MM-DD)"),
+                ),
+                (ImportAction::Reject, Some("line 2: bad row")),
+                (
+                    ImportAction::Reject,
+                    Some("Username cannot be an empty string or only whitespace"),
+                ),
+            ],
+        );
+
+        assert_eq!(plan.report.created, 1);
+        assert_eq!(plan.report.rejected, 6);
+    }
+}
@@ -0,0 +1,286 @@
+/*
+ * lib.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+#[macro_use]
+extern crate maplit;
+
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate str_macro;
+
+#[macro_use]
+extern crate tracing;
+
+#[macro_use]
+mod macros;
+
+mod apikey;
+mod attribution;
+mod auth;
+mod batch;
+mod etag;
+mod export;
+mod handlers;
+mod history;
+mod import;
+mod license;
+mod object;
+mod pagination;
+mod password;
+mod patch;
+mod ratelimit;
+mod result;
+mod session;
+mod translation;
+mod user;
+mod utils;
+mod wikidot;
+
+pub mod storage;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+mod build {
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+use self::etag::format_etag;
+use self::export::{export_site, OutputFormat, JSON_CONTENT_TYPE};
+use self::handlers::*;
+use self::license::StatementFormat;
+use self::storage::Storage;
+use self::utils::input_error;
+use hyper::Body as HyperBody;
+use lambda_http::http::header::{HeaderValue, ETAG, RETRY_AFTER};
+use lambda_http::http::StatusCode;
+use lambda_http::{http::Method, Body, Error, Request, RequestExt, Response};
+use std::sync::Arc;
+
+/// Gets the format requested, or returns an error response.
+macro_rules! request_format {
+    ($format:expr) => {
+        match $format {
+            Ok(format) => format,
+            Err(message) => {
+                let body = input_error(&message)?;
+                return build_response(400, JSON_CONTENT_TYPE, body.into());
+            }
+        }
+    };
+}
+
+/// Main handler for requests.
+///
+/// This dispatches to the appropriate handler function, then returns the response.
+/// It is shared between the Lambda worker and the standalone server, so that both
+/// have identical behavior.
+pub async fn function_handler(
+    storage: &dyn Storage,
+    req: Request,
+) -> Result<Response<Body>, Error> {
+    // Perform routing based on request
+    let path = req.uri().path();
+    let method = req.method();
+    info!(method = method.as_str(), path);
+
+    let (status, body) = match (path, method) {
+        ("/attribution/page", &Method::GET) => {
+            let format = request_format!(OutputFormat::from_request(&req));
+            let mut revision = None;
+            let (status, body) =
+                handle_get_page(storage, req, format, &mut revision).await?;
+            let response = format_response(status, format.content_type(), body)?;
+            return with_etag(response, revision);
+        }
+        ("/attribution/page", &Method::PUT) => {
+            let mut revision = None;
+            let (status, body) = handle_set_page(storage, req, &mut revision).await?;
+            info!(status, body);
+            let response = build_response(status, JSON_CONTENT_TYPE, body.into())?;
+            return with_etag(with_retry_after(response)?, revision);
+        }
+        ("/attribution/page", &Method::PATCH) => {
+            let mut revision = None;
+            let (status, body) = handle_patch_page(storage, req, &mut revision).await?;
+            info!(status, body);
+            let response = build_response(status, JSON_CONTENT_TYPE, body.into())?;
+            return with_etag(response, revision);
+        }
+        ("/attribution/pages", &Method::GET) => handle_get_pages(storage, req).await?,
+        ("/attribution/batch", &Method::PUT) => handle_batch_write(storage, req).await?,
+        ("/attribution/page/history", &Method::GET) => {
+            handle_get_page_history(storage, req).await?
+        }
+        ("/attribution/page/rename", &Method::PUT) => {
+            handle_rename_page(storage, req).await?
+        }
+        ("/attribution/page/revert", &Method::PUT) => {
+            handle_revert_page(storage, req).await?
+        }
+        ("/attribution/translations", &Method::GET) => {
+            handle_get_translations(storage, req).await?
+        }
+        ("/attribution/translations", &Method::PUT) => {
+            handle_set_translation(storage, req).await?
+        }
+        ("/attribution/user", &Method::GET) => handle_get_user(storage, req).await?,
+        ("/attribution/site", &Method::GET) => {
+            let format = request_format!(OutputFormat::from_request(&req));
+            let (status, body) = handle_get_site(storage, req, format).await?;
+            return format_response(status, format.content_type(), body);
+        }
+        ("/attribution/site/export", &Method::GET) => {
+            let format = request_format!(OutputFormat::from_request(&req));
+            let (status, body) = handle_export_site(storage, req, format).await?;
+            return format_response(status, format.export_content_type(), body);
+        }
+        ("/attribution/license", &Method::GET) => {
+            let format = request_format!(StatementFormat::from_request(&req));
+            let (status, body) = handle_get_license(storage, req, format).await?;
+            return format_response(status, format.content_type(), body);
+        }
+        ("/attribution/import", &Method::PUT) => handle_import(storage, req).await?,
+        ("/wikidot/template", &Method::GET) => {
+            handle_get_wikidot_template(storage, req).await?
+        }
+        ("/wikidot/template", &Method::PUT) => {
+            handle_set_wikidot_template(storage, req).await?
+        }
+        ("/password/check", &Method::PUT) => handle_password_check(storage, req).await?,
+        ("/password/update", &Method::PUT) => {
+            handle_password_update(storage, req).await?
+        }
+        ("/apikey/create", &Method::PUT) => handle_api_key_create(storage, req).await?,
+        ("/apikey/list", &Method::PUT) => handle_api_key_list(storage, req).await?,
+        ("/apikey/revoke", &Method::PUT) => handle_api_key_revoke(storage, req).await?,
+        ("/info", _) => handle_info()?,
+        ("/ping", _) => handle_ping()?,
+        _ => handle_missing_route(method.as_str(), path)?,
+    };
+    info!(status, body);
+    let response = build_response(status, JSON_CONTENT_TYPE, body.into())?;
+    with_retry_after(response)
+}
+
+/// Handler for requests when the response can be streamed.
+///
+/// Site exports are sent as they are read from storage, rather than being
+/// collected first. All other requests are passed to [`function_handler`].
+pub async fn streaming_handler(
+    storage: Arc<dyn Storage>,
+    req: Request,
+) -> Result<Response<HyperBody>, Error> {
+    if req.method() == Method::GET && req.uri().path() == "/attribution/site/export" {
+        let site_slug = req
+            .query_string_parameters_ref()
+            .and_then(|params| params.first("site"))
+            .map(String::from);
+
+        let format = OutputFormat::from_request(&req);
+
+        // If the request is invalid, fall through to return the usual error
+        if let (Some(site_slug), Ok(format)) = (site_slug, format) {
+            info!(site_slug, "Streaming site attribution export");
+
+            let (sender, body) = HyperBody::channel();
+            tokio::spawn(async move {
+                export_site(&*storage, &site_slug, format, sender).await
+            });
+
+            return build_response(200, format.export_content_type(), body);
+        }
+    }
+
+    let response = function_handler(&*storage, req).await?;
+    let response = response.map(|body| match body {
+        Body::Empty => HyperBody::empty(),
+        Body::Text(text) => HyperBody::from(text),
+        Body::Binary(bytes) => HyperBody::from(bytes),
+    });
+
+    Ok(response)
+}
+
+/// Builds the response for a route which may return a format other than JSON.
+///
+/// Errors are always returned as JSON.
+fn format_response(
+    status: u16,
+    content_type: &'static str,
+    body: String,
+) -> Result<Response<Body>, Error> {
+    // Don't log the body, it may contain an entire site
+    info!(status, content_type);
+
+    let content_type = match status {
+        200 => content_type,
+        _ => JSON_CONTENT_TYPE,
+    };
+
+    build_response(status, content_type, body.into())
+}
+
+/// Adds an `ETag` header for the page revision to a response, if there is one.
+fn with_etag<B>(
+    mut response: Response<B>,
+    revision: Option<u64>,
+) -> Result<Response<B>, Error> {
+    if let Some(revision) = revision {
+        let etag = HeaderValue::from_str(&format_etag(revision))?;
+        response.headers_mut().insert(ETAG, etag);
+    }
+
+    Ok(response)
+}
+
+fn build_response<B>(
+    status: u16,
+    content_type: &str,
+    body: B,
+) -> Result<Response<B>, Error> {
+    let response = Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .header(
+            "X-AttributionMetadataService-Version",
+            self::build::PKG_VERSION,
+        )
+        .body(body)
+        .map_err(Box::new)?;
+
+    Ok(response)
+}
+
+/// Adds a `Retry-After` header to a rate limited response, with the delay given in its body.
+fn with_retry_after(mut response: Response<Body>) -> Result<Response<Body>, Error> {
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(response);
+    }
+
+    let retry_after = match response.body() {
+        Body::Text(text) => serde_json::from_str::<serde_json::Value>(text)?
+            .get("retry_after")
+            .and_then(serde_json::Value::as_u64),
+        _ => None,
+    };
+
+    if let Some(retry_after) = retry_after {
+        let value = HeaderValue::from(retry_after);
+        response.headers_mut().insert(RETRY_AFTER, value);
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,547 @@
+/*
+ * license.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Attribution statements for reusing pages under their license.
+//!
+//! Statements list the title, authors, source, and license of a page,
+//! as recommended by Creative Commons, with each on its own labelled line.
+//! This keeps them simple to localize for each site's language.
+
+use crate::object::{Attribution, AttributionType};
+use crate::wikidot::escape_user_name;
+use lambda_http::{Request, RequestExt};
+
+const LICENSE_NAME: &str = "CC BY-SA 3.0";
+const LICENSE_URL: &str = "https://creativecommons.org/licenses/by-sa/3.0/";
+
+pub const HTML_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StatementFormat {
+    Text,
+    Html,
+    Wikidot,
+}
+
+impl StatementFormat {
+    /// Gets the format requested by the `format` URL parameter, which defaults to text.
+    pub fn from_request(req: &Request) -> Result<Self, String> {
+        let format = req
+            .query_string_parameters_ref()
+            .and_then(|params| params.first("format"));
+
+        match format {
+            None => Ok(StatementFormat::Text),
+            Some(format) => StatementFormat::try_from(format).map_err(|_| {
+                format!(
+                    "URL parameter 'format' must be 'text', 'html', or 'wikidot', not '{format}'",
+                )
+            }),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            StatementFormat::Html => HTML_CONTENT_TYPE,
+            StatementFormat::Text | StatementFormat::Wikidot => TEXT_CONTENT_TYPE,
+        }
+    }
+}
+
+impl TryFrom<&'_ str> for StatementFormat {
+    type Error = StatementFormatConversionError;
+
+    fn try_from(value: &str) -> Result<StatementFormat, Self::Error> {
+        match value {
+            "text" => Ok(StatementFormat::Text),
+            "html" => Ok(StatementFormat::Html),
+            "wikidot" => Ok(StatementFormat::Wikidot),
+            _ => Err(StatementFormatConversionError),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StatementFormatConversionError;
+
+/// The localized labels used in statements.
+#[derive(Debug)]
+struct Messages {
+    title: &'static str,
+    author: &'static str,
+    rewrite: &'static str,
+    translator: &'static str,
+    source: &'static str,
+    license: &'static str,
+
+    /// Placed after each label.
+    colon: &'static str,
+
+    /// Placed between names in a list.
+    separator: &'static str,
+}
+
+impl Messages {
+    fn get(language: &str) -> Option<&'static Messages> {
+        let messages = match language {
+            "en" => &EN,
+            "cs" => &CS,
+            "de" => &DE,
+            "es" => &ES,
+            "fr" => &FR,
+            "it" => &IT,
+            "ja" => &JA,
+            "ko" => &KO,
+            "pl" => &PL,
+            "pt" => &PT,
+            "ru" => &RU,
+            "th" => &TH,
+            "uk" => &UK,
+            "vi" => &VI,
+            "zh" => &ZH,
+            "zh-tw" => &ZH_TW,
+            _ => return None,
+        };
+
+        Some(messages)
+    }
+
+    fn attribution_type(
+        &self,
+        attribution_type: AttributionType,
+    ) -> Option<&'static str> {
+        match attribution_type {
+            AttributionType::Author => Some(self.author),
+            AttributionType::Rewrite => Some(self.rewrite),
+            AttributionType::Translator => Some(self.translator),
+
+            // Maintaining a page doesn't make someone one of its authors
+            AttributionType::Maintainer => None,
+        }
+    }
+}
+
+/// Gets the language of a site, which statements are written in by default.
+pub fn site_language(site_slug: &str) -> &'static str {
+    match site_slug {
+        "scp-cs" => "cs",
+        "scp-wiki-de" => "de",
+        "lafundacionscp" => "es",
+        "fondationscp" => "fr",
+        "fondazionescp" => "it",
+        "scp-jp" => "ja",
+        "scpko" => "ko",
+        "scp-pl" => "pl",
+        "scp-pt-br" => "pt",
+        "scp-ru" => "ru",
+        "scp-th" => "th",
+        "scp-ukrainian" => "uk",
+        "scp-vn" => "vi",
+        "scp-wiki-cn" => "zh",
+        "scp-zh-tr" => "zh-tw",
+        _ => "en",
+    }
+}
+
+/// Checks if statements can be written in the given language.
+pub fn is_supported_language(language: &str) -> bool {
+    Messages::get(language).is_some()
+}
+
+/// Gets the canonical URL of a page on Wikidot.
+pub fn page_url(site_slug: &str, page_slug: &str) -> String {
+    format!("https://{site_slug}.wikidot.com/{page_slug}")
+}
+
+/// Builds the license statement for a page.
+///
+/// The language must be one for which `is_supported_language()` is true.
+pub fn build_statement(
+    attribution: &Attribution,
+    title: &str,
+    url: &str,
+    language: &str,
+    format: StatementFormat,
+) -> String {
+    let messages = Messages::get(language).expect("Unsupported statement language");
+    let mut lines = Vec::new();
+
+    let line = |label: &str, value: String| match format {
+        StatementFormat::Text => format!("{label}{}{value}", messages.colon),
+        StatementFormat::Html => {
+            format!(
+                "<strong>{}</strong>{}{value}",
+                escape_html(label),
+                messages.colon
+            )
+        }
+        StatementFormat::Wikidot => format!("**{label}**{}{value}", messages.colon),
+    };
+
+    lines.push(line(
+        messages.title,
+        match format {
+            StatementFormat::Text | StatementFormat::Wikidot => str!(title),
+            StatementFormat::Html => escape_html(title),
+        },
+    ));
+
+    for attribution_type in [
+        AttributionType::Author,
+        AttributionType::Rewrite,
+        AttributionType::Translator,
+    ] {
+        let label = match messages.attribution_type(attribution_type) {
+            Some(label) => label,
+            None => continue,
+        };
+
+        let users = attribution
+            .0
+            .iter()
+            .filter(|entry| entry.attribution_type == attribution_type)
+            .map(|entry| match format {
+                StatementFormat::Text => entry.user_name.clone(),
+                StatementFormat::Html => escape_html(&entry.user_name),
+                StatementFormat::Wikidot => {
+                    format!("[[*user {}]]", escape_user_name(&entry.user_name))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if !users.is_empty() {
+            lines.push(line(label, users.join(messages.separator)));
+        }
+    }
+
+    let (source, license) = match format {
+        StatementFormat::Text => (str!(url), format!("{LICENSE_NAME} ({LICENSE_URL})")),
+        StatementFormat::Html => {
+            let url = escape_html(url);
+            (
+                format!("<a href=\"{url}\">{url}</a>"),
+                format!("<a href=\"{LICENSE_URL}\">{LICENSE_NAME}</a>"),
+            )
+        }
+        StatementFormat::Wikidot => {
+            (str!(url), format!("[{LICENSE_URL} {LICENSE_NAME}]"))
+        }
+    };
+
+    lines.push(line(messages.source, source));
+    lines.push(line(messages.license, license));
+
+    match format {
+        StatementFormat::Text | StatementFormat::Wikidot => lines.join("\n") + "\n",
+        StatementFormat::Html => format!("<p>\n{}\n</p>\n", lines.join("<br>\n")),
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[rustfmt::skip]
+const EN: Messages = Messages {
+    title: "Title", author: "Author", rewrite: "Rewrite", translator: "Translation",
+    source: "Source", license: "License", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const CS: Messages = Messages {
+    title: "Název", author: "Autor", rewrite: "Přepis", translator: "Překlad",
+    source: "Zdroj", license: "Licence", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const DE: Messages = Messages {
+    title: "Titel", author: "Autor", rewrite: "Überarbeitung", translator: "Übersetzung",
+    source: "Quelle", license: "Lizenz", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const ES: Messages = Messages {
+    title: "Título", author: "Autor", rewrite: "Reescritura", translator: "Traducción",
+    source: "Fuente", license: "Licencia", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const FR: Messages = Messages {
+    title: "Titre", author: "Auteur", rewrite: "Réécriture", translator: "Traduction",
+    source: "Source", license: "Licence", colon: " : ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const IT: Messages = Messages {
+    title: "Titolo", author: "Autore", rewrite: "Riscrittura", translator: "Traduzione",
+    source: "Fonte", license: "Licenza", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const JA: Messages = Messages {
+    title: "タイトル", author: "著者", rewrite: "リライト", translator: "翻訳",
+    source: "出典", license: "ライセンス", colon: "：", separator: "、",
+};
+
+#[rustfmt::skip]
+const KO: Messages = Messages {
+    title: "제목", author: "저자", rewrite: "재작성", translator: "번역",
+    source: "출처", license: "라이선스", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const PL: Messages = Messages {
+    title: "Tytuł", author: "Autor", rewrite: "Przepisanie", translator: "Tłumaczenie",
+    source: "Źródło", license: "Licencja", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const PT: Messages = Messages {
+    title: "Título", author: "Autor", rewrite: "Reescrita", translator: "Tradução",
+    source: "Fonte", license: "Licença", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const RU: Messages = Messages {
+    title: "Название", author: "Автор", rewrite: "Переработка", translator: "Перевод",
+    source: "Источник", license: "Лицензия", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const TH: Messages = Messages {
+    title: "ชื่อเรื่อง", author: "ผู้เขียน", rewrite: "การเขียนใหม่", translator: "การแปล",
+    source: "แหล่งที่มา", license: "สัญญาอนุญาต", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const UK: Messages = Messages {
+    title: "Назва", author: "Автор", rewrite: "Переробка", translator: "Переклад",
+    source: "Джерело", license: "Ліцензія", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const VI: Messages = Messages {
+    title: "Tiêu đề", author: "Tác giả", rewrite: "Viết lại", translator: "Bản dịch",
+    source: "Nguồn", license: "Giấy phép", colon: ": ", separator: ", ",
+};
+
+#[rustfmt::skip]
+const ZH: Messages = Messages {
+    title: "标题", author: "作者", rewrite: "重写", translator: "翻译",
+    source: "来源", license: "许可协议", colon: "：", separator: "、",
+};
+
+#[rustfmt::skip]
+const ZH_TW: Messages = Messages {
+    title: "標題", author: "作者", rewrite: "重寫", translator: "翻譯",
+    source: "來源", license: "授權條款", colon: "：", separator: "、",
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::AttributionEntry;
+
+    const URL: &str = "https://scp-wiki.wikidot.com/scp-173";
+
+    fn attribution(entries: &[(AttributionType, &str)]) -> Attribution {
+        let entries = entries
+            .iter()
+            .map(|&(attribution_type, user_name)| AttributionEntry {
+                id: None,
+                attribution_type,
+                user_name: str!(user_name),
+                user_id: None,
+                date: None,
+            })
+            .collect();
+
+        Attribution(entries)
+    }
+
+    fn text(attribution: &Attribution) -> String {
+        build_statement(attribution, "SCP-173", URL, "en", StatementFormat::Text)
+    }
+
+    #[test]
+    fn all_types() {
+        let attribution = attribution(&[
+            (AttributionType::Translator, "Dave"),
+            (AttributionType::Author, "Moto42"),
+            (AttributionType::Maintainer, "Carol"),
+            (AttributionType::Rewrite, "Bob"),
+            (AttributionType::Author, "Alice"),
+        ]);
+
+        // Maintainers are never listed
+        assert_eq!(
+            text(&attribution),
+            "Title: SCP-173\n\
+             Author: Moto42, Alice\n\
+             Rewrite: Bob\n\
+             Translation: Dave\n\
+             Source: https://scp-wiki.wikidot.com/scp-173\n\
+             License: CC BY-SA 3.0 (https://creativecommons.org/licenses/by-sa/3.0/)\n",
+        );
+    }
+
+    #[test]
+    fn type_combinations() {
+        const TYPES: [(AttributionType, &str); 4] = [
+            (AttributionType::Author, "Author: A\n"),
+            (AttributionType::Rewrite, "Rewrite: B\n"),
+            (AttributionType::Translator, "Translation: C\n"),
+            (AttributionType::Maintainer, ""),
+        ];
+        const NAMES: [&str; 4] = ["A", "B", "C", "D"];
+
+        // Every subset of types, including none at all
+        for mask in 0..(1 << TYPES.len()) {
+            let included = |index: usize| mask & (1 << index) != 0;
+            let entries: Vec<_> = (0..TYPES.len())
+                .filter(|&index| included(index))
+                .map(|index| (TYPES[index].0, NAMES[index]))
+                .collect();
+
+            let lines: String = (0..TYPES.len())
+                .filter(|&index| included(index))
+                .map(|index| TYPES[index].1)
+                .collect();
+
+            assert_eq!(
+                text(&attribution(&entries)),
+                format!(
+                    "Title: SCP-173\n{lines}Source: {URL}\n\
+                     License: CC BY-SA 3.0 (https://creativecommons.org/licenses/by-sa/3.0/)\n",
+                ),
+                "Unexpected statement for {entries:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn html() {
+        let attribution = attribution(&[
+            (AttributionType::Author, "<b>Moto42</b>"),
+            (AttributionType::Translator, "Tom & Jerry's"),
+        ]);
+
+        let statement =
+            build_statement(&attribution, "\"Peanut\"", URL, "en", StatementFormat::Html);
+
+        assert_eq!(
+            statement,
+            "<p>\n\
+             <strong>Title</strong>: &quot;Peanut&quot;<br>\n\
+             <strong>Author</strong>: &lt;b&gt;Moto42&lt;/b&gt;<br>\n\
+             <strong>Translation</strong>: Tom &amp; Jerry&#39;s<br>\n\
+             <strong>Source</strong>: <a href=\"https://scp-wiki.wikidot.com/scp-173\">https://scp-wiki.wikidot.com/scp-173</a><br>\n\
+             <strong>License</strong>: <a href=\"https://creativecommons.org/licenses/by-sa/3.0/\">CC BY-SA 3.0</a>\n\
+             </p>\n",
+        );
+    }
+
+    #[test]
+    fn wikidot() {
+        let attribution = attribution(&[
+            (AttributionType::Author, "Moto42"),
+            (AttributionType::Author, "]] [[module Css]]"),
+        ]);
+
+        let statement =
+            build_statement(&attribution, "SCP-173", URL, "en", StatementFormat::Wikidot);
+
+        assert_eq!(
+            statement,
+            "**Title**: SCP-173\n\
+             **Author**: [[*user Moto42]], [[*user -- --module Css--]]\n\
+             **Source**: https://scp-wiki.wikidot.com/scp-173\n\
+             **License**: [https://creativecommons.org/licenses/by-sa/3.0/ CC BY-SA 3.0]\n",
+        );
+    }
+
+    #[test]
+    fn no_attribution() {
+        // Nothing is listed, but the statement is still complete
+        for attribution in [
+            attribution(&[]),
+            attribution(&[(AttributionType::Maintainer, "Carol")]),
+        ] {
+            assert_eq!(
+                text(&attribution),
+                "Title: SCP-173\n\
+                 Source: https://scp-wiki.wikidot.com/scp-173\n\
+                 License: CC BY-SA 3.0 (https://creativecommons.org/licenses/by-sa/3.0/)\n",
+            );
+        }
+    }
+
+    #[test]
+    fn localized() {
+        let attribution = attribution(&[
+            (AttributionType::Author, "Alice"),
+            (AttributionType::Author, "Bob"),
+        ]);
+
+        let statement =
+            build_statement(&attribution, "SCP-173", URL, "ja", StatementFormat::Text);
+        assert!(statement.starts_with("タイトル：SCP-173\n著者：Alice、Bob\n"));
+
+        let statement =
+            build_statement(&attribution, "SCP-173", URL, "fr", StatementFormat::Text);
+        assert!(statement.starts_with("Titre : SCP-173\nAuteur : Alice, Bob\n"));
+    }
+
+    #[test]
+    fn languages() {
+        assert_eq!(site_language("scp-wiki"), "en");
+        assert_eq!(site_language("scp-jp"), "ja");
+        assert_eq!(site_language("scp-zh-tr"), "zh-tw");
+
+        // Every site's default language is supported
+        for site_slug in ["scp-wiki", "scp-cs", "scp-wiki-de", "scp-ru", "scp-zh-tr"] {
+            assert!(is_supported_language(site_language(site_slug)));
+        }
+
+        assert!(!is_supported_language("xx"));
+        assert!(!is_supported_language(""));
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!(
+            StatementFormat::try_from("html").ok(),
+            Some(StatementFormat::Html)
+        );
+        assert_eq!(
+            StatementFormat::try_from("wikidot").ok(),
+            Some(StatementFormat::Wikidot),
+        );
+        assert!(StatementFormat::try_from("pdf").is_err());
+        assert_eq!(StatementFormat::Html.content_type(), HTML_CONTENT_TYPE);
+        assert_eq!(StatementFormat::Wikidot.content_type(), TEXT_CONTENT_TYPE);
+    }
+}
@@ -29,14 +29,37 @@ macro_rules! parse_body {
     }};
 }
 
-macro_rules! check_password {
-    ($dynamo:expr, $site_slug:expr, $password:expr, $password_type:expr $(,)?) => {
-        match check_password(&$dynamo, &$site_slug, &$password, $password_type).await {
-            Ok(true) => (),
-            Ok(false) => return Ok((403, invalid_password($password_type)?)),
+macro_rules! check_credentials {
+    (
+        $storage:expr,
+        $req:expr,
+        $site_slug:expr,
+        [$(($password:expr, $password_type:expr)),+ $(,)?] $(,)?
+    ) => {
+        match check_credentials(
+            $storage,
+            &$req,
+            &$site_slug,
+            &[$(($password.as_deref(), $password_type)),+],
+        )
+        .await
+        {
+            Ok(Authorization::Granted(actor)) => actor,
+            Ok(Authorization::Denied(password_type)) => {
+                return Ok((403, invalid_password(password_type)?))
+            }
+            Ok(Authorization::Forbidden(password_type)) => {
+                return Ok((403, insufficient_scope(password_type)?))
+            }
+            Ok(Authorization::RateLimited(retry_after)) => {
+                return Ok((429, rate_limited(retry_after)?))
+            }
             Err(error) => return Ok((500, service_error(&*error)?)),
         }
     };
+    ($storage:expr, $req:expr, $site_slug:expr, $password:expr, $password_type:expr $(,)?) => {
+        check_credentials!($storage, $req, $site_slug, [($password, $password_type)])
+    };
 }
 
 macro_rules! json_output {
@@ -48,6 +71,15 @@ macro_rules! json_output {
     };
 }
 
+macro_rules! data_output {
+    ($future:expr) => {
+        match $future.await {
+            Ok(object) => Ok((200, ServiceResult::success(object).to_json()?)),
+            Err(error) => Ok((500, service_error(&*error)?)),
+        }
+    };
+}
+
 macro_rules! success_output {
     ($future:expr) => {
         match $future.await {
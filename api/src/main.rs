@@ -11,68 +11,14 @@
  *
  */
 
-#[macro_use]
-extern crate maplit;
-
-#[macro_use]
-extern crate serde;
-
-#[macro_use]
-extern crate str_macro;
-
 #[macro_use]
 extern crate tracing;
 
-#[macro_use]
-mod macros;
-
-mod attribution;
-mod handlers;
-mod object;
-mod password;
-mod result;
-mod utils;
-
-mod build {
-    include!(concat!(env!("OUT_DIR"), "/built.rs"));
-}
-
-use self::handlers::*;
-use lambda_http::{self, http::Method, service_fn, Body, Error, Request, Response};
-
-/// Main handler for Lambda requests.
-///
-/// This dispatches to the appropriate handler function, then returns the response.
-async fn function_handler(req: Request) -> Result<Response<Body>, Error> {
-    // Perform routing based on request
-    let path = req.uri().path();
-    let method = req.method();
-    info!(method = method.as_str(), path);
-
-    let (status, body) = match (path, method) {
-        ("/attribution/page", &Method::GET) => handle_get_page(req).await?,
-        ("/attribution/page", &Method::PUT) => handle_set_page(req).await?,
-        ("/attribution/site", &Method::GET) => handle_get_site(req).await?,
-        ("/password/check", &Method::PUT) => handle_password_check(req).await?,
-        ("/password/update", &Method::PUT) => handle_password_update(req).await?,
-        ("/info", _) => handle_info()?,
-        ("/ping", _) => handle_ping()?,
-        _ => handle_missing_route(method.as_str(), path)?,
-    };
-    info!(status, body);
-
-    let response = Response::builder()
-        .status(status)
-        .header("Content-Type", "text/json")
-        .header(
-            "X-AttributionMetadataService-Version",
-            self::build::PKG_VERSION,
-        )
-        .body(body.into())
-        .map_err(Box::new)?;
-
-    Ok(response)
-}
+use attribution_metadata::storage::{self, Storage};
+use attribution_metadata::{function_handler, streaming_handler};
+use lambda_http::{self, service_fn, Error};
+use std::env;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -85,5 +31,14 @@ async fn main() -> Result<(), Error> {
         .init();
 
     info!("Starting AttributionMetadataService lambda worker");
-    lambda_http::run(service_fn(function_handler)).await
+    let storage: Arc<dyn Storage> = Arc::from(storage::connect().await?);
+
+    // Requires the function to be invoked with response streaming enabled
+    if env::var("ATTRIBUTION_LAMBDA_STREAMING").as_deref() == Ok("true") {
+        info!("Using streaming responses");
+        let handler = |req| streaming_handler(Arc::clone(&storage), req);
+        return lambda_http::run_with_streaming_response(service_fn(handler)).await;
+    }
+
+    lambda_http::run(service_fn(|req| function_handler(&*storage, req))).await
 }
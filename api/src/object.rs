@@ -12,15 +12,22 @@
  */
 
 use crate::utils::replace_in_place;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use aws_sdk_dynamodb::types::AttributeValue;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use std::collections::HashSet;
+use std::fmt::Write;
 use std::num::NonZeroU32;
 
 /// Verifies that a string is a date in ISO-8601 format.
 static DATE_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^[0-9]{4}-[0-9]{2}-[0-9]{2}$").unwrap());
 
+/// Verifies that a string is a valid entry ID.
+static ENTRY_ID_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[0-9A-Za-z_-]{1,32}$").unwrap());
+
 /// Yields whitespace at the beginning or end of a string.
 static WHITESPACE_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?:^\s+|\s+$)").unwrap());
@@ -62,8 +69,15 @@ impl TryFrom<&'_ str> for AttributionType {
 #[derive(Debug)]
 pub struct AttributionTypeConversionError;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct AttributionEntry {
+    /// Identifies this entry within its page, staying the same across edits.
+    ///
+    /// This is assigned when the entry is first stored, so it may be omitted
+    /// when writing. Entries stored before IDs existed may not have one yet.
+    #[serde(default)]
+    pub id: Option<String>,
+
     #[serde(rename = "type")]
     pub attribution_type: AttributionType,
     pub user_name: String,
@@ -71,28 +85,27 @@ pub struct AttributionEntry {
     pub date: Option<String>,
 }
 
-impl TryFrom<AttributionEntry> for AttributeValue {
-    type Error = String;
-
-    fn try_from(
-        AttributionEntry {
-            attribution_type,
-            mut user_name,
-            user_id,
-            date,
-        }: AttributionEntry,
-    ) -> Result<AttributeValue, String> {
-        let attribution_type = attribution_type.field_name();
-        debug!(attribution_type, user_name, user_id, date);
+impl AttributionEntry {
+    /// Normalizes and validates this entry prior to it being stored.
+    ///
+    /// Returns an error message suitable for the user if the entry is invalid.
+    pub fn validate(&mut self) -> Result<(), String> {
+        debug!(
+            id = self.id,
+            attribution_type = self.attribution_type.field_name(),
+            user_name = self.user_name,
+            user_id = self.user_id,
+            date = self.date,
+        );
 
         // Trim whitespace off of username
         //
         // It can only be a mistake, and instead of
         // returning an error we can just fix it ourselves.
-        replace_in_place(&mut user_name, &WHITESPACE_REGEX, "");
+        replace_in_place(&mut self.user_name, &WHITESPACE_REGEX, "");
 
         // Check that username isn't an empty string
-        if user_name.is_empty() {
+        if self.user_name.is_empty() {
             error!("Passed username was empty");
             return Err(str!(
                 "Username cannot be an empty string or only whitespace",
@@ -100,7 +113,7 @@ impl TryFrom<AttributionEntry> for AttributeValue {
         }
 
         // Check date against pattern
-        if let Some(ref date) = date {
+        if let Some(ref date) = self.date {
             if !DATE_REGEX.is_match(date) {
                 error!("Date value was invalid");
                 return Err(format!(
@@ -109,9 +122,53 @@ impl TryFrom<AttributionEntry> for AttributeValue {
             }
         }
 
+        // Check ID, if the client passed one back
+        if let Some(ref id) = self.id {
+            if !ENTRY_ID_REGEX.is_match(id) {
+                error!("Entry ID was invalid");
+                return Err(format!("Entry ID '{id}' is invalid"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn without_id(&self) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            ..self.clone()
+        }
+    }
+
+    /// Whether this entry credits the same user in the same way as another.
+    ///
+    /// Users are compared by ID if both entries have one, and otherwise by name.
+    pub fn is_same_credit(&self, other: &AttributionEntry) -> bool {
+        self.attribution_type == other.attribution_type
+            && match (self.user_id, other.user_id) {
+                (Some(user_id), Some(other_id)) => user_id == other_id,
+                _ => self.user_name.eq_ignore_ascii_case(&other.user_name),
+            }
+    }
+}
+
+impl TryFrom<AttributionEntry> for AttributeValue {
+    type Error = String;
+
+    fn try_from(mut entry: AttributionEntry) -> Result<AttributeValue, String> {
+        entry.validate()?;
+
+        let AttributionEntry {
+            id,
+            attribution_type,
+            user_name,
+            user_id,
+            date,
+        } = entry;
+
         // Build final map
-        Ok(AttributeValue::M(hashmap! {
-            str!("type") => AttributeValue::S(str!(attribution_type)),
+        let mut map = hashmap! {
+            str!("type") => AttributeValue::S(str!(attribution_type.field_name())),
             str!("user_name") => AttributeValue::S(user_name),
             str!("user_id") => match user_id {
                 Some(num) => AttributeValue::N(str!(num)),
@@ -121,7 +178,13 @@ impl TryFrom<AttributionEntry> for AttributeValue {
                 Some(date) => AttributeValue::S(date),
                 None => AttributeValue::Null(true),
             },
-        }))
+        };
+
+        if let Some(id) = id {
+            map.insert(str!("id"), AttributeValue::S(id));
+        }
+
+        Ok(AttributeValue::M(map))
     }
 }
 
@@ -136,6 +199,11 @@ impl From<&'_ AttributeValue> for AttributionEntry {
             .as_m()
             .expect("Top-level item for attribution not map");
 
+        // Items written before entries had IDs lack this field
+        let id = map
+            .get("id")
+            .map(|value| str!(value.as_s().expect("Field 'id' not string")));
+
         let attribution_type_raw: &str =
             map["type"].as_s().expect("Field 'type' not string");
 
@@ -166,6 +234,7 @@ impl From<&'_ AttributeValue> for AttributionEntry {
         };
 
         AttributionEntry {
+            id,
             attribution_type,
             user_name,
             user_id,
@@ -174,7 +243,7 @@ impl From<&'_ AttributeValue> for AttributionEntry {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Attribution(pub Vec<AttributionEntry>);
 
 impl Attribution {
@@ -187,6 +256,91 @@ impl Attribution {
             None => String::new(), // We want all null entries to be first
         });
     }
+
+    /// Normalizes and validates every entry in this attribution list.
+    pub fn validate(&mut self) -> Result<(), String> {
+        let mut ids = HashSet::new();
+
+        for entry in &mut self.0 {
+            entry.validate()?;
+
+            if let Some(ref id) = entry.id {
+                if !ids.insert(id) {
+                    error!("Entry ID was duplicated");
+                    return Err(format!("Entry ID '{id}' is used more than once"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks if another attribution list has the same entries as this one.
+    ///
+    /// Entries here without an ID match the other entry whatever its ID is, since
+    /// entries stored before IDs existed don't have one until the page is next written.
+    pub fn same_entries(&self, other: &Attribution) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(entry, other)| match entry.id {
+                    Some(_) => entry == other,
+                    None => entry.without_id() == other.without_id(),
+                })
+    }
+
+    /// Gives an ID to every entry which doesn't have one.
+    ///
+    /// Entries written without an ID keep the ID of the matching entry
+    /// in the page's previous attribution, if there is one, so that
+    /// clients which don't know about IDs don't change them.
+    pub fn assign_ids(&mut self, previous: Option<&Attribution>) {
+        let mut used = self
+            .0
+            .iter()
+            .filter_map(|entry| entry.id.clone())
+            .collect::<HashSet<_>>();
+
+        for index in 0..self.0.len() {
+            if self.0[index].id.is_some() {
+                continue;
+            }
+
+            let matching = previous.and_then(|previous| {
+                previous.0.iter().find_map(|existing| match existing.id {
+                    Some(ref id)
+                        if !used.contains(id)
+                            && existing.is_same_credit(&self.0[index]) =>
+                    {
+                        Some(id.clone())
+                    }
+                    _ => None,
+                })
+            });
+
+            let id = matching.unwrap_or_else(|| loop {
+                let id = generate_entry_id();
+                if !used.contains(&id) {
+                    break id;
+                }
+            });
+
+            used.insert(id.clone());
+            self.0[index].id = Some(id);
+        }
+    }
+}
+
+/// Creates a new random ID for an attribution entry.
+fn generate_entry_id() -> String {
+    let mut bytes = [0; 8];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
 }
 
 impl TryFrom<Attribution> for AttributeValue {
@@ -231,3 +385,138 @@ pub struct FullAttribution {
     pub page_slug: String,
     pub attribution: Attribution,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(attribution_type: AttributionType, user_name: &str) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }
+    }
+
+    #[test]
+    fn validate_normalizes() {
+        let mut attribution =
+            Attribution(vec![entry(AttributionType::Author, "  Alice\t")]);
+        attribution.validate().unwrap();
+        assert_eq!(attribution.0[0].user_name, "Alice");
+    }
+
+    #[test]
+    fn validate_errors() {
+        let mut attribution = Attribution(vec![entry(AttributionType::Author, "   ")]);
+        assert_eq!(
+            attribution.validate(),
+            Err(str!(
+                "Username cannot be an empty string or only whitespace"
+            )),
+        );
+
+        let mut bad_date = entry(AttributionType::Author, "Alice");
+        bad_date.date = Some(str!("01/02/2020"));
+        let mut attribution = Attribution(vec![bad_date]);
+        assert_eq!(
+            attribution.validate(),
+            Err(str!(
+                "Date value '01/02/2020' is invalid (must be YYYY-MM-DD)"
+            )),
+        );
+    }
+
+    #[test]
+    fn sort_undated_first() {
+        let mut first = entry(AttributionType::Author, "Alice");
+        first.date = Some(str!("2010-05-01"));
+        let mut second = entry(AttributionType::Rewrite, "Bob");
+        second.date = Some(str!("2008-07-25"));
+        let undated = entry(AttributionType::Maintainer, "Carol");
+
+        let mut attribution =
+            Attribution(vec![first.clone(), second.clone(), undated.clone()]);
+        attribution.sort();
+        assert_eq!(attribution.0, vec![undated, second, first]);
+    }
+
+    #[test]
+    fn assign_ids() {
+        let mut alice = entry(AttributionType::Author, "Alice");
+        alice.id = Some(str!("0000000000000001"));
+        let mut bob = entry(AttributionType::Translator, "Bob");
+        bob.user_id = NonZeroU32::new(2);
+        bob.id = Some(str!("0000000000000002"));
+        let previous = Attribution(vec![alice, bob]);
+
+        // Entries keep the ID of the same credit, matching users by ID when both have one
+        let mut renamed_bob = entry(AttributionType::Translator, "Robert");
+        renamed_bob.user_id = NonZeroU32::new(2);
+        let mut attribution = Attribution(vec![
+            entry(AttributionType::Author, "ALICE"),
+            renamed_bob,
+            entry(AttributionType::Rewrite, "Alice"),
+        ]);
+        attribution.assign_ids(Some(&previous));
+
+        let ids: Vec<_> = attribution
+            .0
+            .iter()
+            .map(|entry| entry.id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids[..2], ["0000000000000001", "0000000000000002"]);
+        assert_eq!(ids[2].len(), 16);
+        assert!(!ids[..2].contains(&ids[2]));
+
+        // An ID already given is kept, and not reused for another entry
+        let mut taken = entry(AttributionType::Maintainer, "Carol");
+        taken.id = Some(str!("0000000000000001"));
+        let mut attribution =
+            Attribution(vec![entry(AttributionType::Author, "Alice"), taken]);
+        attribution.assign_ids(Some(&previous));
+        assert_ne!(attribution.0[0].id.as_deref(), Some("0000000000000001"));
+        assert_eq!(attribution.0[1].id.as_deref(), Some("0000000000000001"));
+    }
+
+    #[test]
+    fn same_entries() {
+        let mut with_id = entry(AttributionType::Author, "Alice");
+        with_id.id = Some(str!("0000000000000001"));
+        let mut other_id = with_id.clone();
+        other_id.id = Some(str!("0000000000000002"));
+        let without_id = entry(AttributionType::Author, "Alice");
+
+        let same = |left: &AttributionEntry, right: &AttributionEntry| {
+            Attribution(vec![left.clone()])
+                .same_entries(&Attribution(vec![right.clone()]))
+        };
+
+        assert!(same(&with_id, &with_id));
+        assert!(same(&without_id, &with_id));
+        assert!(!same(&with_id, &without_id));
+        assert!(!same(&with_id, &other_id));
+        assert!(!same(&without_id, &entry(AttributionType::Author, "Bob")));
+        assert!(!Attribution(vec![without_id.clone()]).same_entries(&Attribution(vec![])));
+    }
+
+    #[test]
+    fn dynamo_round_trip() {
+        let mut dated = entry(AttributionType::Translator, "Alice");
+        dated.id = Some(str!("0123456789abcdef"));
+        dated.user_id = NonZeroU32::new(42);
+        dated.date = Some(str!("2020-01-01"));
+
+        let attribution = Attribution(vec![dated, entry(AttributionType::Author, "Bob")]);
+        let value = AttributeValue::try_from(attribution.clone()).unwrap();
+        assert_eq!(Attribution::from(&value), attribution);
+    }
+
+    #[test]
+    fn dynamo_invalid() {
+        let attribution = Attribution(vec![entry(AttributionType::Author, "")]);
+        assert!(AttributeValue::try_from(attribution).is_err());
+    }
+}
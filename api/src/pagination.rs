@@ -0,0 +1,108 @@
+/*
+ * pagination.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Cursors for paging through the attributions of a site.
+//!
+//! A cursor is the base64-encoded JSON of the key of the last page returned,
+//! in the same way as DynamoDB's `LastEvaluatedKey`. Clients should treat it
+//! as opaque, and only pass it back as-is to fetch the next set of pages.
+
+use crate::object::FullAttribution;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+/// The number of pages returned if the request does not specify a limit.
+pub const DEFAULT_PAGE_LIMIT: u32 = 500;
+
+/// The most pages that can be requested at once.
+pub const MAX_PAGE_LIMIT: u32 = 1000;
+
+/// A portion of a site's attribution, with a cursor to continue from.
+#[derive(Serialize, Debug)]
+pub struct SiteAttributionPage {
+    pub attributions: Vec<FullAttribution>,
+
+    /// The cursor to pass to get the following pages.
+    ///
+    /// If `None`, then there are no more pages in the site.
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Cursor {
+    #[serde(rename = "site")]
+    site_slug: String,
+
+    #[serde(rename = "page")]
+    page_slug: String,
+}
+
+/// Creates a cursor to continue listing a site after the given page.
+pub fn encode_cursor(site_slug: &str, page_slug: String) -> String {
+    let cursor = Cursor {
+        site_slug: str!(site_slug),
+        page_slug,
+    };
+
+    let json = serde_json::to_vec(&cursor).expect("Unable to serialize cursor");
+    URL_SAFE_NO_PAD.encode(json)
+}
+
+/// Gets the page slug to continue after from a cursor.
+///
+/// Fails if the cursor is malformed, or was issued for a different site.
+pub fn decode_cursor(site_slug: &str, cursor: &str) -> Result<String, &'static str> {
+    let cursor: Cursor = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or("invalid cursor")?;
+
+    if cursor.site_slug != site_slug {
+        return Err("cursor is for a different site");
+    }
+
+    Ok(cursor.page_slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cursor = encode_cursor("scp-wiki", str!("scp-173"));
+        assert_eq!(decode_cursor("scp-wiki", &cursor), Ok(str!("scp-173")));
+    }
+
+    #[test]
+    fn different_site() {
+        let cursor = encode_cursor("scp-wiki", str!("scp-173"));
+        assert_eq!(
+            decode_cursor("scp-jp", &cursor),
+            Err("cursor is for a different site"),
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            decode_cursor("scp-wiki", "not a cursor!"),
+            Err("invalid cursor")
+        );
+        assert_eq!(decode_cursor("scp-wiki", ""), Err("invalid cursor"));
+
+        let json = URL_SAFE_NO_PAD.encode(r#"{"site":"scp-wiki"}"#);
+        assert_eq!(decode_cursor("scp-wiki", &json), Err("invalid cursor"));
+    }
+}
@@ -11,10 +11,19 @@
  *
  */
 
-use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoClient};
+use crate::storage::Storage;
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
 use lambda_http::Error;
+use subtle::ConstantTimeEq;
 
-const TABLE: &str = "attribution_metadata_password";
+/// The prefix of stored passwords which are Argon2id hashes.
+///
+/// Any stored value without this prefix is a legacy plaintext password,
+/// which is replaced with a hash the next time it is successfully used.
+const HASH_PREFIX: &str = "$argon2id$";
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
@@ -50,13 +59,22 @@ pub struct UpdatePasswordInput {
 
     #[serde(rename = "type")]
     pub password_type: PasswordType,
-    pub old_password: String,
     pub new_password: String,
-    pub admin_password: String,
+
+    /// The current password of the type being changed.
+    ///
+    /// This must always be the password itself, so that a session token
+    /// alone is not enough to change the password it was issued for.
+    pub old_password: String,
+
+    /// The site's admin password.
+    ///
+    /// May be omitted if an admin session token is passed instead.
+    pub admin_password: Option<String>,
 }
 
 pub async fn check_password(
-    dynamo: &DynamoClient,
+    storage: &dyn Storage,
     site_slug: &str,
     password: &str,
     password_type: PasswordType,
@@ -67,46 +85,122 @@ pub async fn check_password(
     }
 
     // Make request
-    let field = password_type.field_name();
-    let result = dynamo
-        .get_item()
-        .table_name(TABLE)
-        .key("site_slug", AttributeValue::S(str!(site_slug)))
-        .projection_expression(field)
-        .send()
-        .await?;
-
-    match result.item() {
-        None => Ok(false),
-        Some(items) => {
-            // Yes, a fixed-time equality setup is not used here. It's not even hashed.
-            // This is a basic check to prevent the relatively infrequent incidence of
-            // attribution-metadata update requests from being spoofed.
-
-            let actual = items[field]
-                .as_s()
-                .expect("Password field in database not string");
-
-            Ok(password == actual)
-        }
+    let stored = match storage.get_password(site_slug, password_type).await? {
+        Some(stored) => stored,
+        None => return Ok(false),
+    };
+
+    if stored.starts_with(HASH_PREFIX) {
+        let hash = PasswordHash::new(&stored)?;
+        let valid = Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok();
+
+        return Ok(valid);
     }
+
+    // Legacy plaintext password, compare in constant time,
+    // then upgrade it to a hash if it was correct.
+    let valid: bool = password.as_bytes().ct_eq(stored.as_bytes()).into();
+    if valid {
+        info!(
+            "Migrating plaintext {} password to hash",
+            password_type.field_name(),
+        );
+
+        update_password(storage, str!(site_slug), str!(password), password_type).await?;
+    }
+
+    Ok(valid)
 }
 
 pub async fn update_password(
-    dynamo: &DynamoClient,
+    storage: &dyn Storage,
     site_slug: String,
     password: String,
     password_type: PasswordType,
 ) -> Result<(), Error> {
-    let field = password_type.field_name();
-    dynamo
-        .update_item()
-        .table_name(TABLE)
-        .key("site_slug", AttributeValue::S(site_slug))
-        .update_expression(format!("SET {field} = :passwd"))
-        .expression_attribute_values(":passwd", AttributeValue::S(password))
-        .send()
-        .await?;
-
-    Ok(())
+    let hash = hash_password(&password)?;
+    storage.set_password(site_slug, password_type, hash).await
+}
+
+/// Produces an Argon2id hash of the password in PHC string format, using a random salt.
+fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn hashed() {
+        let storage = MemoryStorage::new();
+        update_password(
+            &storage,
+            str!("scp-wiki"),
+            str!("hunter2"),
+            PasswordType::Regular,
+        )
+        .await
+        .unwrap();
+
+        let stored = storage
+            .get_password("scp-wiki", PasswordType::Regular)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(stored.starts_with(HASH_PREFIX));
+        assert!(!stored.contains("hunter2"));
+
+        let check = |password, password_type| {
+            check_password(&storage, "scp-wiki", password, password_type)
+        };
+
+        assert!(check("hunter2", PasswordType::Regular).await.unwrap());
+        assert!(!check("hunter3", PasswordType::Regular).await.unwrap());
+        assert!(!check("", PasswordType::Regular).await.unwrap());
+        assert!(!check("hunter2", PasswordType::Admin).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn plaintext_migration() {
+        let storage = MemoryStorage::new();
+        storage
+            .set_password(str!("scp-wiki"), PasswordType::Admin, str!("hunter2"))
+            .await
+            .unwrap();
+
+        let stored = || storage.get_password("scp-wiki", PasswordType::Admin);
+
+        // A wrong password leaves it as is
+        let valid = check_password(&storage, "scp-wiki", "hunter3", PasswordType::Admin)
+            .await
+            .unwrap();
+        assert!(!valid);
+        assert_eq!(stored().await.unwrap().as_deref(), Some("hunter2"));
+
+        // The right one replaces it with a hash, which then still works
+        for _ in 0..2 {
+            let valid =
+                check_password(&storage, "scp-wiki", "hunter2", PasswordType::Admin)
+                    .await
+                    .unwrap();
+            assert!(valid);
+
+            let stored = stored().await.unwrap().unwrap();
+            assert!(stored.starts_with(HASH_PREFIX));
+        }
+    }
+
+    #[test]
+    fn hash_salted() {
+        let first = hash_password("hunter2").unwrap();
+        let second = hash_password("hunter2").unwrap();
+        assert_ne!(first, second);
+    }
 }
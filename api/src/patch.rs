@@ -0,0 +1,384 @@
+/*
+ * patch.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Partial edits to a page's attribution list.
+//!
+//! Rather than sending the whole list to change one entry, a client sends
+//! operations which are applied to the page's current attribution.
+
+use crate::object::{Attribution, AttributionEntry, AttributionType};
+use serde::{Deserialize, Deserializer};
+use std::num::NonZeroU32;
+
+/// A single change to make to a page's attribution list.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PatchOperation {
+    /// Adds a new entry to the page.
+    Add { entry: AttributionEntry },
+
+    /// Removes the matching entries from the page.
+    Remove { target: EntryTarget },
+
+    /// Changes fields of the matching entries.
+    ///
+    /// Fields which are omitted are left as is, and `null` clears them.
+    Update {
+        target: EntryTarget,
+
+        #[serde(default, deserialize_with = "deserialize_present")]
+        date: Option<Option<String>>,
+
+        #[serde(default, deserialize_with = "deserialize_present")]
+        user_id: Option<Option<NonZeroU32>>,
+    },
+}
+
+/// Selects entries of a page, either by entry ID, or by type and user.
+///
+/// The user is given by ID, name, or both. Names are compared ignoring case.
+#[derive(Deserialize, Debug, Clone)]
+pub struct EntryTarget {
+    pub id: Option<String>,
+
+    #[serde(rename = "type")]
+    pub attribution_type: Option<AttributionType>,
+    pub user_name: Option<String>,
+    pub user_id: Option<NonZeroU32>,
+}
+
+impl EntryTarget {
+    fn matches(&self, entry: &AttributionEntry) -> bool {
+        if let Some(ref id) = self.id {
+            if entry.id.as_ref() != Some(id) {
+                return false;
+            }
+        }
+
+        if let Some(attribution_type) = self.attribution_type {
+            if entry.attribution_type != attribution_type {
+                return false;
+            }
+        }
+
+        if let Some(user_id) = self.user_id {
+            if entry.user_id != Some(user_id) {
+                return false;
+            }
+        }
+
+        if let Some(ref user_name) = self.user_name {
+            if !entry.user_name.eq_ignore_ascii_case(user_name.trim()) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn describe(&self) -> String {
+        if let Some(ref id) = self.id {
+            return format!("entry with ID '{id}'");
+        }
+
+        let attribution_type = self
+            .attribution_type
+            .map_or("entry", AttributionType::field_name);
+
+        match (&self.user_name, self.user_id) {
+            (Some(user_name), _) => format!("{attribution_type} '{}'", user_name.trim()),
+            (None, Some(user_id)) => format!("{attribution_type} with user ID {user_id}"),
+            (None, None) => str!(attribution_type),
+        }
+    }
+}
+
+/// Applies operations in order to a page's attribution list.
+///
+/// An operation which would do nothing is an error, since the client's view of
+/// the page is evidently wrong. The error contains a message suitable for the user.
+/// The result is not yet sorted or validated, and added entries have no ID yet.
+pub fn apply_patch(
+    attribution: Option<Attribution>,
+    operations: Vec<PatchOperation>,
+) -> Result<Attribution, String> {
+    let mut entries = attribution
+        .map(|attribution| attribution.0)
+        .unwrap_or_default();
+
+    for (index, operation) in operations.into_iter().enumerate() {
+        debug!(index, "Applying patch operation");
+
+        match operation {
+            PatchOperation::Add { mut entry } => {
+                entry.validate()?;
+
+                // The same credit twice is always a mistake
+                if entries
+                    .iter()
+                    .any(|existing| existing.is_same_credit(&entry))
+                {
+                    return Err(format!(
+                        "operation {index}: page already has {} '{}'",
+                        entry.attribution_type.field_name(),
+                        entry.user_name,
+                    ));
+                }
+
+                entries.push(entry);
+            }
+            PatchOperation::Remove { target } => {
+                check_target(index, &target)?;
+
+                let before = entries.len();
+                entries.retain(|entry| !target.matches(entry));
+
+                if entries.len() == before {
+                    return Err(no_match(index, &target));
+                }
+            }
+            PatchOperation::Update {
+                target,
+                date,
+                user_id,
+            } => {
+                check_target(index, &target)?;
+
+                let mut found = false;
+                for entry in entries.iter_mut().filter(|entry| target.matches(entry)) {
+                    found = true;
+
+                    if let Some(ref date) = date {
+                        entry.date = date.clone();
+                    }
+
+                    if let Some(user_id) = user_id {
+                        entry.user_id = user_id;
+                    }
+                }
+
+                if !found {
+                    return Err(no_match(index, &target));
+                }
+            }
+        }
+    }
+
+    Ok(Attribution(entries))
+}
+
+fn check_target(index: usize, target: &EntryTarget) -> Result<(), String> {
+    let has_user = target.user_name.is_some() || target.user_id.is_some();
+    let has_credit = target.attribution_type.is_some() && has_user;
+
+    if target.id.is_none() && !has_credit {
+        return Err(format!(
+            "operation {index}: target must have an 'id', or a 'type' with a 'user_name' or 'user_id'",
+        ));
+    }
+
+    Ok(())
+}
+
+fn no_match(index: usize, target: &EntryTarget) -> String {
+    format!("operation {index}: page has no {}", target.describe())
+}
+
+/// Distinguishes a field set to `null` from one which is absent.
+///
+/// Used with `#[serde(default)]`, so that an absent field is `None`
+/// and a present one is `Some`, whatever its value.
+fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(
+        attribution_type: AttributionType,
+        user_name: &str,
+        user_id: Option<u32>,
+        date: Option<&str>,
+    ) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: str!(user_name),
+            user_id: user_id.and_then(NonZeroU32::new),
+            date: date.map(String::from),
+        }
+    }
+
+    fn page() -> Option<Attribution> {
+        Some(Attribution(vec![
+            entry(
+                AttributionType::Author,
+                "Alice",
+                Some(1),
+                Some("2023-01-01"),
+            ),
+            entry(AttributionType::Translator, "Bob", None, None),
+        ]))
+    }
+
+    fn operations(value: serde_json::Value) -> Vec<PatchOperation> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn add() {
+        let patched = apply_patch(
+            None,
+            operations(json!([
+                { "op": "add", "entry": { "type": "author", "user_name": " Carol " } },
+            ])),
+        )
+        .unwrap();
+        assert_eq!(
+            patched,
+            Attribution(vec![entry(AttributionType::Author, "Carol", None, None)]),
+        );
+
+        // Another type for the same user is a separate credit
+        let patched = apply_patch(
+            page(),
+            operations(json!([
+                { "op": "add", "entry": { "type": "rewrite", "user_name": "alice" } },
+            ])),
+        )
+        .unwrap();
+        assert_eq!(patched.0.len(), 3);
+
+        for entry in [
+            json!({ "type": "author", "user_name": "ALICE" }),
+            json!({ "type": "author", "user_name": "Someone", "user_id": 1 }),
+            json!({ "type": "author", "user_name": "" }),
+        ] {
+            let result =
+                apply_patch(page(), operations(json!([{ "op": "add", "entry": entry }])));
+            assert!(result.is_err(), "added {entry}");
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let patched = apply_patch(
+            page(),
+            operations(json!([
+                { "op": "remove", "target": { "type": "author", "user_id": 1 } },
+                { "op": "remove", "target": { "type": "translator", "user_name": "bob" } },
+            ])),
+        )
+        .unwrap();
+        assert_eq!(patched, Attribution(vec![]));
+
+        let error = apply_patch(
+            page(),
+            operations(json!([
+                { "op": "remove", "target": { "type": "author", "user_name": "Alice" } },
+                { "op": "remove", "target": { "type": "author", "user_name": "Alice" } },
+            ])),
+        )
+        .unwrap_err();
+        assert_eq!(error, "operation 1: page has no author 'Alice'");
+
+        // Both the name and ID must match
+        let error = apply_patch(
+            page(),
+            operations(json!([
+                { "op": "remove", "target": { "type": "author", "user_name": "Alice", "user_id": 2 } },
+            ])),
+        )
+        .unwrap_err();
+        assert_eq!(error, "operation 0: page has no author 'Alice'");
+
+        let error = apply_patch(
+            page(),
+            operations(json!([{ "op": "remove", "target": { "type": "author" } }])),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "operation 0: target must have an 'id', or a 'type' with a 'user_name' or 'user_id'",
+        );
+    }
+
+    #[test]
+    fn target_id() {
+        let mut page = page();
+        page.as_mut().unwrap().0[1].id = Some(str!("0123456789abcdef"));
+
+        let patched = apply_patch(
+            page.clone(),
+            operations(json!([
+                { "op": "update", "target": { "id": "0123456789abcdef" }, "user_id": 2 },
+            ])),
+        )
+        .unwrap();
+        assert_eq!(patched.0[1].user_id, NonZeroU32::new(2));
+
+        // Any other fields given must match as well
+        let error = apply_patch(
+            page,
+            operations(json!([
+                { "op": "remove", "target": { "id": "0123456789abcdef", "type": "author" } },
+            ])),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "operation 0: page has no entry with ID '0123456789abcdef'"
+        );
+    }
+
+    #[test]
+    fn update() {
+        // Absent fields are kept, null ones are cleared
+        let patched = apply_patch(
+            page(),
+            operations(json!([
+                { "op": "update", "target": { "type": "author", "user_name": "alice" }, "date": null },
+                { "op": "update", "target": { "type": "translator", "user_name": "Bob" }, "user_id": 2, "date": "2023-02-01" },
+            ])),
+        )
+        .unwrap();
+        assert_eq!(
+            patched,
+            Attribution(vec![
+                entry(AttributionType::Author, "Alice", Some(1), None),
+                entry(
+                    AttributionType::Translator,
+                    "Bob",
+                    Some(2),
+                    Some("2023-02-01")
+                ),
+            ]),
+        );
+
+        let error = apply_patch(
+            page(),
+            operations(json!([
+                { "op": "update", "target": { "type": "author", "user_id": 2 }, "date": null },
+            ])),
+        )
+        .unwrap_err();
+        assert_eq!(error, "operation 0: page has no author with user ID 2");
+    }
+}
@@ -0,0 +1,381 @@
+/*
+ * ratelimit.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Tracking of failed credential checks, to slow down brute-forcing.
+//!
+//! Failures are counted both for each client of a site, and for the site as
+//! a whole. Once the number of failures passes a threshold, further attempts
+//! are refused for an exponentially increasing period after each failure.
+//!
+//! The site-wide count only refuses password checks. Session tokens and API
+//! keys cannot feasibly be guessed, so they keep working while a site is under
+//! attack, instead of the attacker being able to lock every user out.
+//!
+//! Each attempt is counted as a failure before any credentials are checked,
+//! and given back if it turns out not to have failed. This way, parallel
+//! requests cannot all slip through under the threshold before any of their
+//! failures are recorded.
+
+use crate::storage::Storage;
+use crate::utils::unix_time;
+use lambda_http::Error;
+
+/// Number of failures allowed from one client before backoff begins.
+const CLIENT_FREE_ATTEMPTS: u32 = 5;
+
+/// Number of failures allowed across a whole site before backoff begins.
+const SITE_FREE_ATTEMPTS: u32 = 50;
+
+/// The longest time attempts can be refused for after a failure, in seconds.
+const MAX_LOCKOUT: u64 = 60 * 60;
+
+/// How long after the last failure its count is forgotten, in seconds.
+pub const ATTEMPT_WINDOW: u64 = 24 * 60 * 60;
+
+/// The failed attempts recorded for a site or client.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LoginAttempts {
+    pub failures: u32,
+
+    /// When the last failure occurred, in seconds since the UNIX epoch.
+    pub last_failure: u64,
+}
+
+impl LoginAttempts {
+    #[inline]
+    pub fn is_stale(&self, now: u64) -> bool {
+        now.saturating_sub(self.last_failure) > ATTEMPT_WINDOW
+    }
+
+    /// Determines how many seconds remain until another attempt is allowed, if any.
+    fn retry_after(&self, free_attempts: u32, now: u64) -> Option<u64> {
+        if self.failures < free_attempts || self.is_stale(now) {
+            return None;
+        }
+
+        let exponent = (self.failures - free_attempts).min(16);
+        let lockout = (1 << exponent).min(MAX_LOCKOUT);
+        let until = self.last_failure + lockout;
+        (until > now).then(|| until - now)
+    }
+}
+
+/// The keys under which attempts for a particular request are tracked.
+#[derive(Debug, Clone)]
+pub struct RateLimitKeys {
+    site: String,
+    client: Option<String>,
+}
+
+impl RateLimitKeys {
+    pub fn new(site_slug: &str, client: Option<&str>) -> Self {
+        RateLimitKeys {
+            site: format!("site:{site_slug}"),
+            client: client.map(|client| format!("client:{site_slug}:{client}")),
+        }
+    }
+
+    /// Lists each key an attempt is counted under, with its threshold,
+    /// and whether passing that threshold refuses the attempt.
+    ///
+    /// If the client's address is not known, it cannot be told apart from
+    /// any other, so it falls back to the site-wide count for everything.
+    fn buckets(&self, checks_password: bool) -> Vec<(&str, u32, bool)> {
+        match self.client {
+            Some(ref client) => vec![
+                (client, CLIENT_FREE_ATTEMPTS, true),
+                (&self.site, SITE_FREE_ATTEMPTS, checks_password),
+            ],
+            None => vec![(&self.site, SITE_FREE_ATTEMPTS, true)],
+        }
+    }
+}
+
+/// A credential check in progress, which has already been counted as a failure.
+///
+/// Once the outcome is known, it must be finished with one of
+/// [`succeed`](Self::succeed), [`release`](Self::release), or
+/// [`fail`](Self::fail).
+#[derive(Debug)]
+#[must_use]
+pub struct Attempt {
+    timestamp: u64,
+    reservations: Vec<Reservation>,
+}
+
+#[derive(Debug)]
+struct Reservation {
+    key: String,
+    is_client: bool,
+    retry_after: Option<u64>,
+
+    /// When the failure before this one occurred, if it is still counted.
+    previous_failure: Option<u64>,
+}
+
+/// Counts a new attempt as a failure, before its credentials are checked.
+///
+/// `checks_password` is whether any of the credentials is a password,
+/// since only those are refused by the site-wide count.
+pub async fn begin_attempt(
+    storage: &dyn Storage,
+    keys: &RateLimitKeys,
+    checks_password: bool,
+) -> Result<Attempt, Error> {
+    let timestamp = unix_time();
+    let mut reservations = Vec::new();
+
+    for (key, free_attempts, enforced) in keys.buckets(checks_password) {
+        // The attempts from before this one decide whether it is allowed
+        let previous = storage
+            .record_login_failure(str!(key), timestamp)
+            .await?
+            .filter(|attempts| !attempts.is_stale(timestamp));
+
+        let retry_after = previous
+            .filter(|_| enforced)
+            .and_then(|attempts| attempts.retry_after(free_attempts, timestamp));
+
+        reservations.push(Reservation {
+            key: str!(key),
+            is_client: keys.client.as_deref() == Some(key),
+            retry_after,
+            previous_failure: previous.map(|attempts| attempts.last_failure),
+        });
+    }
+
+    Ok(Attempt {
+        timestamp,
+        reservations,
+    })
+}
+
+impl Attempt {
+    /// Checks if this attempt must be refused without being made.
+    ///
+    /// Returns the number of seconds until another attempt is allowed if so.
+    pub fn retry_after(&self) -> Option<u64> {
+        let retry_after = self
+            .reservations
+            .iter()
+            .filter_map(|reservation| reservation.retry_after)
+            .max();
+
+        if let Some(seconds) = retry_after {
+            warn!(seconds, "Rate limiting attempt");
+        }
+
+        retry_after
+    }
+
+    /// Records that the attempt failed, which was already counted.
+    pub fn fail(self) {
+        for reservation in self.reservations {
+            info!(key = reservation.key, "Recorded failed attempt");
+        }
+    }
+
+    /// Records that the attempt was not a failure after all, giving back its count.
+    pub async fn release(self, storage: &dyn Storage) -> Result<(), Error> {
+        for reservation in self.reservations {
+            storage
+                .release_login_attempt(
+                    reservation.key,
+                    self.timestamp,
+                    reservation.previous_failure,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Records a successful attempt, resetting the client's failure count.
+    ///
+    /// The site-wide count is only given back this attempt,
+    /// so one client cannot clear failures caused by others.
+    pub async fn succeed(self, storage: &dyn Storage) -> Result<(), Error> {
+        for reservation in self.reservations {
+            if reservation.is_client {
+                storage.clear_login_attempts(reservation.key).await?;
+            } else {
+                storage
+                    .release_login_attempt(
+                        reservation.key,
+                        self.timestamp,
+                        reservation.previous_failure,
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const CLIENT_KEY: &str = "client:scp-wiki:192.0.2.1";
+    const SITE_KEY: &str = "site:scp-wiki";
+
+    fn attempts(failures: u32, last_failure: u64) -> LoginAttempts {
+        LoginAttempts {
+            failures,
+            last_failure,
+        }
+    }
+
+    async fn fail_times(storage: &dyn Storage, key: &str, times: u32) {
+        let now = unix_time();
+        for _ in 0..times {
+            storage.record_login_failure(str!(key), now).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let now = 1_000_000;
+        assert_eq!(attempts(4, now).retry_after(5, now), None);
+        assert_eq!(attempts(5, now).retry_after(5, now), Some(1));
+        assert_eq!(attempts(6, now).retry_after(5, now), Some(2));
+        assert_eq!(attempts(8, now - 3).retry_after(5, now), Some(5));
+        assert_eq!(attempts(6, now - 2).retry_after(5, now), None);
+        assert_eq!(attempts(100, now).retry_after(5, now), Some(MAX_LOCKOUT));
+
+        // Stale failures are forgotten
+        let stale = now - ATTEMPT_WINDOW - 1;
+        assert!(attempts(100, stale).is_stale(now));
+        assert_eq!(attempts(100, stale).retry_after(5, now), None);
+    }
+
+    #[test]
+    fn buckets() {
+        let keys = RateLimitKeys::new("scp-wiki", Some("192.0.2.1"));
+        assert_eq!(
+            keys.buckets(true),
+            vec![
+                (CLIENT_KEY, CLIENT_FREE_ATTEMPTS, true),
+                (SITE_KEY, SITE_FREE_ATTEMPTS, true),
+            ],
+        );
+        assert_eq!(
+            keys.buckets(false),
+            vec![
+                (CLIENT_KEY, CLIENT_FREE_ATTEMPTS, true),
+                (SITE_KEY, SITE_FREE_ATTEMPTS, false),
+            ],
+        );
+
+        // Without an address, the site count applies to everything
+        let keys = RateLimitKeys::new("scp-wiki", None);
+        assert_eq!(
+            keys.buckets(false),
+            vec![(SITE_KEY, SITE_FREE_ATTEMPTS, true)],
+        );
+    }
+
+    #[tokio::test]
+    async fn parallel_attempts() {
+        let storage = MemoryStorage::new();
+        let keys = RateLimitKeys::new("scp-wiki", Some("192.0.2.1"));
+
+        // None of these have finished, but each is already counted
+        let mut pending = Vec::new();
+        for _ in 0..CLIENT_FREE_ATTEMPTS {
+            let attempt = begin_attempt(&storage, &keys, true).await.unwrap();
+            assert_eq!(attempt.retry_after(), None);
+            pending.push(attempt);
+        }
+
+        for _ in 0..2 {
+            begin_attempt(&storage, &keys, true).await.unwrap().fail();
+        }
+
+        let attempt = begin_attempt(&storage, &keys, true).await.unwrap();
+        assert!(attempt.retry_after().is_some());
+        attempt.release(&storage).await.unwrap();
+
+        for attempt in pending {
+            attempt.fail();
+        }
+    }
+
+    #[tokio::test]
+    async fn release() {
+        let storage = MemoryStorage::new();
+        let keys = RateLimitKeys::new("scp-wiki", Some("192.0.2.1"));
+        fail_times(&storage, CLIENT_KEY, 2).await;
+        let before = storage.get_login_attempts(CLIENT_KEY).await.unwrap();
+
+        let attempt = begin_attempt(&storage, &keys, true).await.unwrap();
+        let counted = storage.get_login_attempts(CLIENT_KEY).await.unwrap();
+        assert_eq!(counted.map(|attempts| attempts.failures), Some(3));
+
+        attempt.release(&storage).await.unwrap();
+        assert_eq!(
+            storage.get_login_attempts(CLIENT_KEY).await.unwrap(),
+            before
+        );
+
+        // The site key had no failures before, so it is removed again
+        assert_eq!(storage.get_login_attempts(SITE_KEY).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn succeed() {
+        let storage = MemoryStorage::new();
+        let keys = RateLimitKeys::new("scp-wiki", Some("192.0.2.1"));
+        fail_times(&storage, CLIENT_KEY, 3).await;
+        fail_times(&storage, SITE_KEY, 3).await;
+
+        let attempt = begin_attempt(&storage, &keys, true).await.unwrap();
+        attempt.succeed(&storage).await.unwrap();
+
+        // Only the client's own count is reset
+        assert_eq!(storage.get_login_attempts(CLIENT_KEY).await.unwrap(), None);
+        let site = storage.get_login_attempts(SITE_KEY).await.unwrap();
+        assert_eq!(site.map(|attempts| attempts.failures), Some(3));
+    }
+
+    #[tokio::test]
+    async fn site_lockout() {
+        let storage = MemoryStorage::new();
+        fail_times(&storage, SITE_KEY, SITE_FREE_ATTEMPTS + 5).await;
+
+        // Passwords are refused for every client
+        let keys = RateLimitKeys::new("scp-wiki", Some("198.51.100.7"));
+        let attempt = begin_attempt(&storage, &keys, true).await.unwrap();
+        assert!(attempt.retry_after().is_some());
+        attempt.release(&storage).await.unwrap();
+
+        // But tokens and keys are still checked
+        let attempt = begin_attempt(&storage, &keys, false).await.unwrap();
+        assert_eq!(attempt.retry_after(), None);
+        attempt.succeed(&storage).await.unwrap();
+
+        // Unless the client cannot be told apart from the attacker
+        let keys = RateLimitKeys::new("scp-wiki", None);
+        let attempt = begin_attempt(&storage, &keys, false).await.unwrap();
+        assert!(attempt.retry_after().is_some());
+        attempt.release(&storage).await.unwrap();
+
+        // Other sites are unaffected
+        let keys = RateLimitKeys::new("scp-jp", None);
+        let attempt = begin_attempt(&storage, &keys, true).await.unwrap();
+        assert_eq!(attempt.retry_after(), None);
+        attempt.succeed(&storage).await.unwrap();
+    }
+}
@@ -21,6 +21,16 @@ pub enum ServiceResult<T> {
         error: (),
         data: T,
     },
+    RateLimited {
+        error: &'static str,
+        message: String,
+        retry_after: u64,
+    },
+    Rejected {
+        error: &'static str,
+        message: String,
+        data: T,
+    },
     Error {
         error: &'static str,
         message: String,
@@ -31,6 +41,15 @@ impl<T> ServiceResult<T> {
     pub fn success(data: T) -> Self {
         ServiceResult::Success { data, error: () }
     }
+
+    /// An error which comes with data describing what was wrong.
+    pub fn rejected(error_type: &'static str, message: String, data: T) -> Self {
+        ServiceResult::Rejected {
+            error: error_type,
+            message,
+            data,
+        }
+    }
 }
 
 impl ServiceResult<()> {
@@ -40,6 +59,16 @@ impl ServiceResult<()> {
             message,
         }
     }
+
+    pub fn rate_limited(retry_after: u64) -> Self {
+        ServiceResult::RateLimited {
+            error: "rate-limited",
+            message: format!(
+                "Too many invalid attempts, try again in {retry_after} seconds",
+            ),
+            retry_after,
+        }
+    }
 }
 
 impl<T> ServiceResult<T>
@@ -0,0 +1,256 @@
+/*
+ * server.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Standalone HTTP server, for running the service outside of AWS Lambda.
+//!
+//! Incoming requests are converted into the same form Lambda provides,
+//! then passed through the regular [`streaming_handler`].
+
+use crate::auth::ClientAddress;
+use crate::result::ServiceResult;
+use crate::storage::Storage;
+use crate::streaming_handler;
+use crate::utils::service_error;
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_LENGTH;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body as HyperBody, Request as HyperRequest, Response as HyperResponse};
+use lambda_http::aws_lambda_events::query_map::QueryMap;
+use lambda_http::{Body, Error, Request, RequestExt};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// The largest request body accepted, in bytes.
+///
+/// This is the same as the limit on Lambda request payloads.
+const MAX_BODY_SIZE: usize = 6 * 1024 * 1024;
+
+/// Serves requests on the given address until interrupted.
+pub async fn run(storage: Box<dyn Storage>, address: SocketAddr) -> Result<(), Error> {
+    let storage: Arc<dyn Storage> = Arc::from(storage);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let storage = Arc::clone(&storage);
+        let client = ClientAddress(conn.remote_addr().ip());
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let storage = Arc::clone(&storage);
+                async move { handle(storage, client, req).await }
+            }))
+        }
+    });
+
+    let server = hyper::Server::try_bind(&address)?.serve(make_service);
+    info!("Listening on http://{}", server.local_addr());
+
+    server
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("Unable to install Ctrl-C handler");
+
+            info!("Shutting down server");
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn handle(
+    storage: Arc<dyn Storage>,
+    client: ClientAddress,
+    req: HyperRequest<HyperBody>,
+) -> Result<HyperResponse<HyperBody>, Infallible> {
+    let mut req = match convert_request(req).await {
+        Ok(Some(req)) => req,
+        Ok(None) => return Ok(too_large_response()),
+        Err(error) => return Ok(error_response(&*error)),
+    };
+
+    req.extensions_mut().insert(client);
+
+    match streaming_handler(storage, req).await {
+        Ok(response) => Ok(response),
+        Err(error) => Ok(error_response(&*error)),
+    }
+}
+
+/// Converts a request from hyper into the form produced by the Lambda runtime.
+///
+/// Returns `None` if the body is larger than [`MAX_BODY_SIZE`].
+async fn convert_request(req: HyperRequest<HyperBody>) -> Result<Option<Request>, Error> {
+    let (parts, body) = req.into_parts();
+
+    // Refuse early if the client says the body is too large
+    let content_length = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+
+    if content_length.is_some_and(|length| length > MAX_BODY_SIZE) {
+        warn!(content_length, "Request body is too large");
+        return Ok(None);
+    }
+
+    let bytes = match read_body(body).await? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+
+    let body = if bytes.is_empty() {
+        Body::Empty
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(text) => Body::Text(text),
+            Err(error) => Body::Binary(error.into_bytes()),
+        }
+    };
+
+    let query: QueryMap = parts
+        .uri
+        .query()
+        .unwrap_or_default()
+        .parse()
+        .expect("Query string parsing is infallible");
+
+    let req = Request::from_parts(parts, body).with_query_string_parameters(query);
+    Ok(Some(req))
+}
+
+/// Reads a request body, stopping once it is larger than [`MAX_BODY_SIZE`].
+///
+/// This is checked as it is read, since the `Content-Length` header may be
+/// missing or wrong.
+async fn read_body(mut body: HyperBody) -> Result<Option<Vec<u8>>, Error> {
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+            warn!("Request body is too large");
+            return Ok(None);
+        }
+
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(Some(bytes))
+}
+
+fn too_large_response() -> HyperResponse<HyperBody> {
+    let message = format!("Request body is larger than {MAX_BODY_SIZE} bytes");
+    let body = ServiceResult::error("too-large", message)
+        .to_json()
+        .unwrap_or_default();
+
+    let mut response = HyperResponse::new(HyperBody::from(body));
+    *response.status_mut() = hyper::StatusCode::PAYLOAD_TOO_LARGE;
+    response
+}
+
+fn error_response(
+    error: &(dyn std::error::Error + Send + Sync),
+) -> HyperResponse<HyperBody> {
+    let body = service_error(error).unwrap_or_default();
+
+    let mut response = HyperResponse::new(HyperBody::from(body));
+    *response.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use std::net::IpAddr;
+
+    fn storage() -> Arc<dyn Storage> {
+        Arc::new(MemoryStorage::new())
+    }
+
+    fn client() -> ClientAddress {
+        ClientAddress(IpAddr::from([127, 0, 0, 1]))
+    }
+
+    fn request(body: Vec<u8>) -> HyperRequest<HyperBody> {
+        HyperRequest::builder()
+            .method("POST")
+            .uri("/nonexistent")
+            .body(HyperBody::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn body_at_limit() {
+        let req = convert_request(request(vec![b'a'; MAX_BODY_SIZE]))
+            .await
+            .unwrap()
+            .expect("Body at limit was refused");
+
+        match req.body() {
+            Body::Text(text) => assert_eq!(text.len(), MAX_BODY_SIZE),
+            body => panic!("Unexpected body {body:?}"),
+        }
+
+        let response = handle(storage(), client(), request(vec![b'a'; MAX_BODY_SIZE]))
+            .await
+            .unwrap();
+
+        assert_ne!(response.status(), hyper::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn body_too_large() {
+        let response =
+            handle(storage(), client(), request(vec![b'a'; MAX_BODY_SIZE + 1]))
+                .await
+                .unwrap();
+
+        assert_eq!(response.status(), hyper::StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "too-large");
+    }
+
+    #[tokio::test]
+    async fn streamed_body_too_large() {
+        // Without a Content-Length header, the limit is enforced while reading
+        let (mut sender, body) = HyperBody::channel();
+        tokio::spawn(async move {
+            for _ in 0..7 {
+                let chunk = vec![b'a'; 1024 * 1024];
+                if sender.send_data(chunk.into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let req = HyperRequest::builder().uri("/").body(body).unwrap();
+        assert!(convert_request(req).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn content_length_too_large() {
+        let req = HyperRequest::builder()
+            .uri("/")
+            .header(CONTENT_LENGTH, MAX_BODY_SIZE + 1)
+            .body(HyperBody::from("{}"))
+            .unwrap();
+
+        assert!(convert_request(req).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,270 @@
+/*
+ * session.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Short-lived session tokens, issued after a successful password check.
+//!
+//! A token is `<payload>.<signature>`, where the payload is the base64-encoded
+//! JSON of [`SessionClaims`], and the signature is its HMAC-SHA256. Tokens are
+//! scoped to a site and password type, and carry a fingerprint of the stored
+//! password, so changing that password revokes all sessions issued for it.
+
+use crate::password::PasswordType;
+use crate::storage::Storage;
+use crate::utils::unix_time;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use lambda_http::Error;
+use once_cell::sync::Lazy;
+use sha2::Sha256;
+use std::env;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+
+/// How long an issued session token remains valid.
+const SESSION_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// The key used to sign session tokens, from `ATTRIBUTION_SESSION_SECRET`.
+///
+/// Every instance of the service must use the same key to accept each other's
+/// tokens, so if it is not set, no tokens are issued or accepted at all.
+static SESSION_SECRET: Lazy<Option<Vec<u8>>> =
+    Lazy::new(|| match env::var("ATTRIBUTION_SESSION_SECRET") {
+        Ok(secret) if !secret.is_empty() => Some(secret.into_bytes()),
+        _ => {
+            warn!("No session secret configured, session tokens are disabled");
+            None
+        }
+    });
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SessionClaims {
+    site: String,
+
+    #[serde(rename = "type")]
+    password_type: PasswordType,
+
+    /// When this session expires, in seconds since the UNIX epoch.
+    expires: u64,
+
+    /// Fingerprint of the stored password this session was issued for.
+    password: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Session {
+    pub token: String,
+    pub expires: u64,
+}
+
+/// Issues a new session token for the given site and password type.
+///
+/// Returns `None` if session tokens are disabled, since no secret is configured.
+/// The caller must have already verified the password.
+pub async fn issue_session(
+    storage: &dyn Storage,
+    site_slug: &str,
+    password_type: PasswordType,
+) -> Result<Option<Session>, Error> {
+    let secret = match *SESSION_SECRET {
+        Some(ref secret) => secret,
+        None => return Ok(None),
+    };
+
+    let stored = storage
+        .get_password(site_slug, password_type)
+        .await?
+        .ok_or("Cannot issue session, no password is set")?;
+
+    let expires = unix_time() + SESSION_DURATION.as_secs();
+    let claims = SessionClaims {
+        site: str!(site_slug),
+        password_type,
+        expires,
+        password: fingerprint(secret, &stored),
+    };
+
+    Ok(Some(Session {
+        token: encode_token(secret, &claims)?,
+        expires,
+    }))
+}
+
+/// Checks if the session token is valid for the given site and password type.
+pub async fn check_session(
+    storage: &dyn Storage,
+    token: &str,
+    site_slug: &str,
+    password_type: PasswordType,
+) -> Result<bool, Error> {
+    let secret = match *SESSION_SECRET {
+        Some(ref secret) => secret,
+        None => {
+            warn!("Session token passed, but session tokens are disabled");
+            return Ok(false);
+        }
+    };
+
+    let claims = match decode_token(secret, token) {
+        Some(claims) => claims,
+        None => {
+            warn!("Session token is malformed or has an invalid signature");
+            return Ok(false);
+        }
+    };
+
+    if !claims.is_valid_for(site_slug, password_type, unix_time()) {
+        return Ok(false);
+    }
+
+    // Ensure the password hasn't been changed since the session was issued
+    match storage.get_password(site_slug, password_type).await? {
+        None => Ok(false),
+        Some(stored) => {
+            let expected = fingerprint(secret, &stored);
+            Ok(expected.as_bytes().ct_eq(claims.password.as_bytes()).into())
+        }
+    }
+}
+
+impl SessionClaims {
+    /// Checks the scope and expiry of the session, but not its password fingerprint.
+    fn is_valid_for(
+        &self,
+        site_slug: &str,
+        password_type: PasswordType,
+        now: u64,
+    ) -> bool {
+        if self.site != site_slug || self.password_type != password_type {
+            warn!("Session token is not scoped for this site and password type");
+            return false;
+        }
+
+        if self.expires <= now {
+            info!("Session token has expired");
+            return false;
+        }
+
+        true
+    }
+}
+
+fn encode_token(secret: &[u8], claims: &SessionClaims) -> Result<String, Error> {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?);
+    let signature = {
+        let mut mac = new_mac(secret);
+        mac.update(payload.as_bytes());
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    };
+
+    Ok(format!("{payload}.{signature}"))
+}
+
+fn decode_token(secret: &[u8], token: &str) -> Option<SessionClaims> {
+    let (payload, signature) = token.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+    let mut mac = new_mac(secret);
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature).ok()?;
+
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+fn fingerprint(secret: &[u8], stored_password: &str) -> String {
+    let mut mac = new_mac(secret);
+    mac.update(b"password\0");
+    mac.update(stored_password.as_bytes());
+
+    let bytes = mac.finalize().into_bytes();
+    URL_SAFE_NO_PAD.encode(&bytes[..16])
+}
+
+#[inline]
+fn new_mac(secret: &[u8]) -> Hmac<Sha256> {
+    Hmac::new_from_slice(secret).expect("HMAC accepts keys of any length")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+
+    fn claims(expires: u64) -> SessionClaims {
+        SessionClaims {
+            site: str!("scp-wiki"),
+            password_type: PasswordType::Admin,
+            expires,
+            password: fingerprint(SECRET, "stored password"),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let token = encode_token(SECRET, &claims(1000)).unwrap();
+        let decoded = decode_token(SECRET, &token).unwrap();
+
+        assert_eq!(decoded.site, "scp-wiki");
+        assert_eq!(decoded.password_type, PasswordType::Admin);
+        assert_eq!(decoded.expires, 1000);
+        assert_eq!(decoded.password, fingerprint(SECRET, "stored password"));
+    }
+
+    #[test]
+    fn wrong_secret() {
+        let token = encode_token(SECRET, &claims(1000)).unwrap();
+        assert!(decode_token(b"other secret", &token).is_none());
+    }
+
+    #[test]
+    fn tampered() {
+        let token = encode_token(SECRET, &claims(1000)).unwrap();
+        let (_, signature) = token.split_once('.').unwrap();
+
+        // Extend the expiry without re-signing
+        let payload =
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims(u64::MAX)).unwrap());
+        let forged = format!("{payload}.{signature}");
+        assert!(decode_token(SECRET, &forged).is_none());
+
+        assert!(decode_token(SECRET, "").is_none());
+        assert!(decode_token(SECRET, &payload).is_none());
+        assert!(decode_token(SECRET, &format!("{payload}.!!!")).is_none());
+    }
+
+    #[test]
+    fn expiry() {
+        let claims = claims(1000);
+        assert!(claims.is_valid_for("scp-wiki", PasswordType::Admin, 999));
+        assert!(!claims.is_valid_for("scp-wiki", PasswordType::Admin, 1000));
+        assert!(!claims.is_valid_for("scp-wiki", PasswordType::Admin, 1001));
+    }
+
+    #[test]
+    fn scope() {
+        let claims = claims(1000);
+        assert!(!claims.is_valid_for("scp-jp", PasswordType::Admin, 0));
+        assert!(!claims.is_valid_for("scp-wiki", PasswordType::Regular, 0));
+    }
+
+    #[test]
+    fn password_fingerprint() {
+        let first = fingerprint(SECRET, "stored password");
+        assert_eq!(first, fingerprint(SECRET, "stored password"));
+        assert_ne!(first, fingerprint(SECRET, "changed password"));
+        assert_ne!(first, fingerprint(b"other secret", "stored password"));
+        assert!(!first.contains("stored password"));
+    }
+}
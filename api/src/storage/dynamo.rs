@@ -0,0 +1,2363 @@
+/*
+ * storage/dynamo.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+use super::{BatchResult, RenameResult, Storage, WriteResult};
+use crate::apikey::ApiKey;
+use crate::batch::PageWrite;
+use crate::history::{Change, Revision};
+use crate::object::{Attribution, FullAttribution};
+use crate::password::PasswordType;
+use crate::ratelimit::{LoginAttempts, ATTEMPT_WINDOW};
+use crate::translation::{PageReference, PageTranslations};
+use crate::user::{entries_by_user, UserAttribution};
+use crate::wikidot::WikidotTemplate;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::types::{
+    AttributeValue, Delete, KeysAndAttributes, Put, ReturnConsumedCapacity, ReturnValue,
+    TransactWriteItem, Update,
+};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::Error;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::num::NonZeroU32;
+use std::time::Duration;
+use std::{env, iter};
+use tokio::time;
+
+const ATTRIBUTION_TABLE: &str = "attribution_metadata";
+const PASSWORD_TABLE: &str = "attribution_metadata_password";
+const API_KEY_TABLE: &str = "attribution_metadata_api_key";
+const LOGIN_ATTEMPT_TABLE: &str = "attribution_metadata_login_attempt";
+const HISTORY_TABLE: &str = "attribution_metadata_history";
+const TEMPLATE_TABLE: &str = "attribution_metadata_template";
+const USER_TABLE: &str = "attribution_metadata_user";
+const TRANSLATION_TABLE: &str = "attribution_metadata_translation";
+const ALIAS_TABLE: &str = "attribution_metadata_alias";
+
+/// Global secondary index on `TRANSLATION_TABLE`, for finding the translations of a page.
+const TRANSLATION_ORIGINAL_INDEX: &str = "original_key-index";
+
+/// How many times to make a write, if the page keeps being
+/// written concurrently, before giving up.
+const WRITE_ATTEMPTS: u32 = 3;
+
+/// The most items DynamoDB allows in a single transaction.
+const MAX_TRANSACTION_ITEMS: usize = 100;
+
+/// Positions of the items in a rename transaction whose conditions decide its result.
+const RENAME_DELETE_OLD: usize = 0;
+const RENAME_PUT_NEW: usize = 1;
+const RENAME_DELETE_ALIAS: usize = 2;
+
+/// The most keys DynamoDB allows in a single batch get.
+const MAX_BATCH_GET_KEYS: usize = 100;
+
+/// How many times to retry keys a batch get left unprocessed before giving up.
+const MAX_BATCH_GET_RETRIES: u32 = 5;
+
+/// How many pages' latest revisions to query at the same time.
+const PARALLEL_REVISION_QUERIES: usize = 25;
+
+/// Storage backend which persists data in AWS DynamoDB.
+#[derive(Debug, Clone)]
+pub struct DynamoStorage {
+    dynamo: DynamoClient,
+
+    /// Page slugs at which to split site queries, so that the ranges can be run in parallel.
+    ///
+    /// If empty, each site is read in a single sequence of queries.
+    site_query_splits: Vec<String>,
+}
+
+/// A write to a page, ready to be made as part of a transaction.
+#[derive(Debug)]
+enum PreparedWrite {
+    /// The transaction items for the write, and the page's revision after it.
+    Items(Vec<TransactWriteItem>, u64),
+
+    /// The write changes nothing, so the page stays at this revision.
+    Unchanged(u64),
+
+    /// The page is not at the revision the write expected.
+    Conflict,
+}
+
+impl DynamoStorage {
+    pub async fn connect() -> Self {
+        let config = aws_config::load_from_env().await;
+        let dynamo = DynamoClient::new(&config);
+
+        // Comma-separated list of page slugs, e.g. "scp-2,scp-5,t"
+        let site_query_splits = env::var("ATTRIBUTION_DYNAMO_SITE_SPLITS")
+            .map(|value| value.split(',').map(|slug| str!(slug.trim())).collect())
+            .unwrap_or_default();
+
+        DynamoStorage::new(dynamo, site_query_splits)
+    }
+
+    pub fn new(dynamo: DynamoClient, mut site_query_splits: Vec<String>) -> Self {
+        site_query_splits.retain(|slug| !slug.is_empty());
+        site_query_splits.sort();
+        site_query_splits.dedup();
+
+        DynamoStorage {
+            dynamo,
+            site_query_splits,
+        }
+    }
+
+    /// Sets a page's attribution, or deletes it if `None`, appending a revision
+    /// to its history in the same transaction.
+    ///
+    /// If another write to the page happens concurrently, the revision number will
+    /// already be taken and the transaction fails, so the history is never inconsistent.
+    /// When the change expects a particular revision, this is reported as a conflict.
+    /// Otherwise the write is retried on top of the new latest revision.
+    async fn write_with_revision(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+        attribution: Option<Attribution>,
+        change: &Change,
+    ) -> Result<WriteResult, Error> {
+        for attempt in 1..=WRITE_ATTEMPTS {
+            let (items, revision) = match self
+                .prepare_write(site_slug, page_slug, attribution.clone(), change)
+                .await?
+            {
+                PreparedWrite::Items(items, revision) => (items, revision),
+                PreparedWrite::Unchanged(revision) => {
+                    return Ok(WriteResult::Written(revision))
+                }
+                PreparedWrite::Conflict => return Ok(WriteResult::Conflict),
+            };
+
+            let result = self
+                .dynamo
+                .transact_write_items()
+                .set_transact_items(Some(items))
+                .send()
+                .await;
+
+            match result {
+                Ok(_) => return Ok(WriteResult::Written(revision)),
+                Err(SdkError::ServiceError(ref error))
+                    if error.err().is_transaction_canceled_exception() =>
+                {
+                    warn!(
+                        site_slug,
+                        page_slug,
+                        attempt,
+                        "Page was written concurrently, transaction cancelled",
+                    );
+
+                    if change.expected_revision.is_some() {
+                        return Ok(WriteResult::Conflict);
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        error!(
+            site_slug,
+            page_slug, "Page kept being written concurrently, giving up"
+        );
+        Err("Page is being written concurrently, try again".into())
+    }
+
+    /// Builds the transaction items to write a page, along with a new revision for its history.
+    async fn prepare_write(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+        mut attribution: Option<Attribution>,
+        change: &Change,
+    ) -> Result<PreparedWrite, Error> {
+        let page_key = page_key(site_slug, page_slug);
+        let latest = self.get_latest_revision(&page_key).await?;
+
+        if change
+            .expected_revision
+            .is_some_and(|expected| expected != latest)
+        {
+            return Ok(PreparedWrite::Conflict);
+        }
+
+        let previous = self.get_stored_page(site_slug, page_slug).await?;
+
+        // Deleting a nonexistent page changes nothing
+        if previous.is_none() && attribution.is_none() {
+            return Ok(PreparedWrite::Unchanged(latest));
+        }
+
+        let write = match attribution {
+            Some(ref mut attribution) => {
+                attribution.assign_ids(previous.as_ref());
+
+                let update = Update::builder()
+                    .table_name(ATTRIBUTION_TABLE)
+                    .key("site_slug", AttributeValue::S(str!(site_slug)))
+                    .key("page_slug", AttributeValue::S(str!(page_slug)))
+                    .update_expression(
+                        "SET attribution = :attribution, updated_by = :updated_by",
+                    )
+                    .expression_attribute_values(
+                        ":attribution",
+                        attribution.clone().try_into()?,
+                    )
+                    .expression_attribute_values(
+                        ":updated_by",
+                        AttributeValue::S(str!(change.actor)),
+                    )
+                    .build();
+
+                TransactWriteItem::builder().update(update).build()
+            }
+            None => {
+                let delete = Delete::builder()
+                    .table_name(ATTRIBUTION_TABLE)
+                    .key("site_slug", AttributeValue::S(str!(site_slug)))
+                    .key("page_slug", AttributeValue::S(str!(page_slug)))
+                    .build();
+
+                TransactWriteItem::builder().delete(delete).build()
+            }
+        };
+
+        // Update the user index for any users added, changed, or removed
+        let user_writes =
+            user_index_writes(site_slug, page_slug, &previous, &attribution)?;
+
+        let revision = Revision::new(latest + 1, previous, attribution, change);
+        info!(
+            page_key,
+            revision = revision.revision,
+            actor = revision.actor,
+            "Writing page with new revision",
+        );
+
+        let revision = Put::builder()
+            .table_name(HISTORY_TABLE)
+            .set_item(Some(revision_item(page_key, revision)?))
+            .condition_expression("attribute_not_exists(revision)")
+            .build();
+
+        let mut items = vec![write, TransactWriteItem::builder().put(revision).build()];
+        items.extend(user_writes);
+
+        Ok(PreparedWrite::Items(items, latest + 1))
+    }
+
+    /// Gets many items from a table at once, retrying any keys which DynamoDB left unprocessed.
+    ///
+    /// The items are returned in no particular order, and keys with no item are left out.
+    async fn batch_get(
+        &self,
+        table: &str,
+        keys: Vec<HashMap<String, AttributeValue>>,
+        projection: &str,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+        let mut items = Vec::new();
+
+        for chunk in keys.chunks(MAX_BATCH_GET_KEYS) {
+            let mut request = KeysAndAttributes::builder()
+                .set_keys(Some(chunk.to_vec()))
+                .projection_expression(projection)
+                .build();
+            let mut retries = 0;
+
+            loop {
+                let result = self
+                    .dynamo
+                    .batch_get_item()
+                    .request_items(table, request)
+                    .send()
+                    .await?;
+
+                if let Some(responses) =
+                    result.responses().and_then(|tables| tables.get(table))
+                {
+                    items.extend(responses.iter().cloned());
+                }
+
+                retries += 1;
+                match batch_get_retry(table, result.unprocessed_keys(), retries)? {
+                    Some((unprocessed, delay)) => {
+                        time::sleep(delay).await;
+                        request = unprocessed;
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Reads one page of a Scan of every site's attribution.
+    ///
+    /// Returns the site slug, page slug, and attribution of each item, along
+    /// with the key to continue from, if there are more.
+    async fn scan_attribution(
+        &self,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    ) -> Result<
+        (
+            Vec<(String, String, Attribution)>,
+            Option<HashMap<String, AttributeValue>>,
+        ),
+        Error,
+    > {
+        let result = self
+            .dynamo
+            .scan()
+            .table_name(ATTRIBUTION_TABLE)
+            .projection_expression("site_slug, page_slug, attribution")
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+
+        let pages = result
+            .items()
+            .unwrap_or_default()
+            .iter()
+            .map(|item| {
+                let site_slug = item["site_slug"]
+                    .as_s()
+                    .expect("Field 'site_slug' not string");
+                let page_slug = item["page_slug"]
+                    .as_s()
+                    .expect("Field 'page_slug' not string");
+
+                (
+                    site_slug.clone(),
+                    page_slug.clone(),
+                    Attribution::from(&item["attribution"]),
+                )
+            })
+            .collect();
+
+        Ok((pages, result.last_evaluated_key))
+    }
+
+    /// Adds the entries of every page to the user index, where they are missing.
+    ///
+    /// The index is only updated when a page is written, so this must be run once
+    /// for pages last written before the index existed. Entries already in the index
+    /// are left as they are, since writes keep them up to date.
+    ///
+    /// Returns the number of entries added.
+    pub async fn backfill_user_index(&self) -> Result<usize, Error> {
+        let mut added = 0;
+        let mut exclusive_start_key = None;
+
+        loop {
+            let (pages, last_evaluated_key) =
+                self.scan_attribution(exclusive_start_key).await?;
+
+            for (site_slug, page_slug, attribution) in pages {
+                let page_key = page_key(&site_slug, &page_slug);
+
+                for (user_id, entries) in entries_by_user(&attribution) {
+                    let result = self
+                        .dynamo
+                        .put_item()
+                        .table_name(USER_TABLE)
+                        .item("user_id", AttributeValue::N(str!(user_id)))
+                        .item("page_key", AttributeValue::S(page_key.clone()))
+                        .item("site_slug", AttributeValue::S(site_slug.clone()))
+                        .item("page_slug", AttributeValue::S(page_slug.clone()))
+                        .item("attribution", entries.try_into()?)
+                        .condition_expression("attribute_not_exists(page_key)")
+                        .send()
+                        .await;
+
+                    match result {
+                        Ok(_) => added += 1,
+                        Err(SdkError::ServiceError(ref error))
+                            if error.err().is_conditional_check_failed_exception() => {}
+                        Err(error) => return Err(error.into()),
+                    }
+                }
+            }
+
+            info!(added, "Backfilled user index for scanned pages");
+
+            match last_evaluated_key {
+                None => break,
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(last_evaluated_key)
+                }
+            }
+        }
+
+        Ok(added)
+    }
+
+    /// Gives an ID to every entry of every page which doesn't have one.
+    ///
+    /// Pages stored before entries had IDs only get them when next written, until
+    /// which their entries cannot be referred to by ID. This must be run once to
+    /// give them IDs. A page is skipped if it is written while this is running,
+    /// since the write gives it IDs itself.
+    ///
+    /// Returns the number of pages updated.
+    pub async fn backfill_entry_ids(&self) -> Result<usize, Error> {
+        let mut updated = 0;
+        let mut exclusive_start_key = None;
+
+        loop {
+            let (pages, last_evaluated_key) =
+                self.scan_attribution(exclusive_start_key).await?;
+
+            for (site_slug, page_slug, attribution) in pages {
+                if attribution.0.iter().all(|entry| entry.id.is_some()) {
+                    continue;
+                }
+
+                let mut with_ids = attribution.clone();
+                with_ids.assign_ids(None);
+
+                let result = self
+                    .dynamo
+                    .update_item()
+                    .table_name(ATTRIBUTION_TABLE)
+                    .key("site_slug", AttributeValue::S(site_slug.clone()))
+                    .key("page_slug", AttributeValue::S(page_slug.clone()))
+                    .update_expression("SET attribution = :attribution")
+                    .condition_expression("attribution = :previous")
+                    .expression_attribute_values(":attribution", with_ids.try_into()?)
+                    .expression_attribute_values(":previous", attribution.try_into()?)
+                    .send()
+                    .await;
+
+                match result {
+                    Ok(_) => updated += 1,
+                    Err(SdkError::ServiceError(ref error))
+                        if error.err().is_conditional_check_failed_exception() =>
+                    {
+                        info!(
+                            site_slug,
+                            page_slug, "Page changed during backfill, skipping"
+                        );
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+
+            info!(updated, "Backfilled entry IDs for scanned pages");
+
+            match last_evaluated_key {
+                None => break,
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(last_evaluated_key)
+                }
+            }
+        }
+
+        Ok(updated)
+    }
+
+    /// Gets the translation link items which refer to the given page as their original.
+    async fn get_translation_items(
+        &self,
+        original_key: &str,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+        let mut items = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let result = self
+                .dynamo
+                .query()
+                .table_name(TRANSLATION_TABLE)
+                .index_name(TRANSLATION_ORIGINAL_INDEX)
+                .key_condition_expression("original_key = :original_key")
+                .expression_attribute_values(
+                    ":original_key",
+                    AttributeValue::S(str!(original_key)),
+                )
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            items.extend(result.items().unwrap_or_default().iter().cloned());
+
+            match result.last_evaluated_key {
+                None => break,
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(last_evaluated_key)
+                }
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Gets the attribution stored under exactly this page slug, ignoring aliases.
+    ///
+    /// This uses a consistent read, since it is for checking a page before writing to it.
+    async fn get_stored_page(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<Attribution>, Error> {
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(ATTRIBUTION_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("page_slug", AttributeValue::S(str!(page_slug)))
+            .projection_expression("attribution")
+            .consistent_read(true)
+            .send()
+            .await?;
+
+        Ok(result
+            .item()
+            .map(|item| Attribution::from(&item["attribution"])))
+    }
+
+    /// Gets the number of the latest revision of a page, or 0 if it has none.
+    async fn get_latest_revision(&self, page_key: &str) -> Result<u64, Error> {
+        query_latest_revision(self.dynamo.clone(), str!(page_key)).await
+    }
+
+    /// Gets the old slugs of a site which are aliases of the given page.
+    async fn get_aliases_of(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Vec<String>, Error> {
+        let mut aliases = Vec::new();
+        let mut exclusive_start_key = None;
+
+        // Few pages are renamed, so filtering a site's aliases is cheap
+        loop {
+            let result = self
+                .dynamo
+                .query()
+                .table_name(ALIAS_TABLE)
+                .key_condition_expression("site_slug = :site_slug")
+                .filter_expression("page_slug = :page_slug")
+                .expression_attribute_values(
+                    ":site_slug",
+                    AttributeValue::S(str!(site_slug)),
+                )
+                .expression_attribute_values(
+                    ":page_slug",
+                    AttributeValue::S(str!(page_slug)),
+                )
+                .projection_expression("alias_slug")
+                .consistent_read(true)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in result.items().unwrap_or_default() {
+                let alias_slug = item["alias_slug"]
+                    .as_s()
+                    .expect("Field 'alias_slug' not string");
+
+                aliases.push(alias_slug.clone());
+            }
+
+            match result.last_evaluated_key {
+                None => break,
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(last_evaluated_key)
+                }
+            }
+        }
+
+        Ok(aliases)
+    }
+
+    /// Makes a single attempt at renaming a page.
+    ///
+    /// Returns `None` if the transaction was cancelled by a concurrent write
+    /// which doesn't decide the result, so the rename should be tried again.
+    async fn try_rename_page(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+        new_page_slug: &str,
+        change: &Change,
+    ) -> Result<Option<RenameResult>, Error> {
+        let previous = match self.get_stored_page(site_slug, page_slug).await? {
+            Some(attribution) => attribution,
+            None => return Ok(Some(RenameResult::MissingPage)),
+        };
+
+        // The page is written anew, so give any entries from before IDs existed one
+        let mut attribution = previous.clone();
+        attribution.assign_ids(None);
+
+        if self
+            .get_stored_page(site_slug, new_page_slug)
+            .await?
+            .is_some()
+        {
+            return Ok(Some(RenameResult::ExistingPage));
+        }
+
+        // Another page's old slug can't be taken, since reads of it would change pages
+        let alias_target = self
+            .dynamo
+            .get_item()
+            .table_name(ALIAS_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("alias_slug", AttributeValue::S(str!(new_page_slug)))
+            .consistent_read(true)
+            .send()
+            .await?
+            .item
+            .map(|item| {
+                item["page_slug"]
+                    .as_s()
+                    .expect("Field 'page_slug' not string")
+                    .clone()
+            });
+
+        if alias_target.is_some_and(|target| target != page_slug) {
+            return Ok(Some(RenameResult::ExistingPage));
+        }
+
+        let old_key = page_key(site_slug, page_slug);
+        let new_key = page_key(site_slug, new_page_slug);
+        let old_latest = self.get_latest_revision(&old_key).await?;
+        let new_latest = self.get_latest_revision(&new_key).await?;
+
+        // Renaming back to an old slug makes it a page again rather than an alias
+        let mut aliases = self.get_aliases_of(site_slug, page_slug).await?;
+        aliases.retain(|alias_slug| alias_slug != new_page_slug);
+
+        let translation_of = self
+            .dynamo
+            .get_item()
+            .table_name(TRANSLATION_TABLE)
+            .key("page_key", AttributeValue::S(old_key.clone()))
+            .consistent_read(true)
+            .send()
+            .await?
+            .item
+            .map(|item| {
+                convert_page_reference(&item, "original_site_slug", "original_page_slug")
+            });
+
+        // The link items of this page are handled separately, so skip them here
+        let translations = self
+            .get_translation_items(&old_key)
+            .await?
+            .into_iter()
+            .filter(|item| {
+                let page_key = item["page_key"]
+                    .as_s()
+                    .expect("Field 'page_key' not string");
+                *page_key != old_key && *page_key != new_key
+            })
+            .collect::<Vec<_>>();
+
+        info!(
+            site_slug,
+            page_slug,
+            new_page_slug,
+            aliases = aliases.len(),
+            translations = translations.len(),
+            "Renaming page",
+        );
+
+        // Move the attribution item, failing if either page changed in the meantime
+        let delete = Delete::builder()
+            .table_name(ATTRIBUTION_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("page_slug", AttributeValue::S(str!(page_slug)))
+            .condition_expression("attribute_exists(page_slug)")
+            .build();
+
+        let put = Put::builder()
+            .table_name(ATTRIBUTION_TABLE)
+            .item("site_slug", AttributeValue::S(str!(site_slug)))
+            .item("page_slug", AttributeValue::S(str!(new_page_slug)))
+            .item("attribution", attribution.clone().try_into()?)
+            .item("updated_by", AttributeValue::S(str!(change.actor)))
+            .condition_expression("attribute_not_exists(page_slug)")
+            .build();
+
+        // The new slug is now a page rather than an alias, unless it became
+        // another page's alias in the meantime
+        let delete_alias = Delete::builder()
+            .table_name(ALIAS_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("alias_slug", AttributeValue::S(str!(new_page_slug)))
+            .condition_expression(
+                "attribute_not_exists(alias_slug) OR page_slug = :page_slug",
+            )
+            .expression_attribute_values(":page_slug", AttributeValue::S(str!(page_slug)))
+            .build();
+
+        // These must be the first items, in the order given by the RENAME_* indices
+        let mut items = vec![
+            TransactWriteItem::builder().delete(delete).build(),
+            TransactWriteItem::builder().put(put).build(),
+            TransactWriteItem::builder().delete(delete_alias).build(),
+        ];
+
+        // Record the move in the history of both slugs
+        let old_revision = Revision::new(old_latest + 1, Some(previous), None, change);
+        let new_revision =
+            Revision::new(new_latest + 1, None, Some(attribution.clone()), change);
+
+        for (page_key, revision) in [
+            (old_key.clone(), old_revision),
+            (new_key.clone(), new_revision),
+        ] {
+            let put = Put::builder()
+                .table_name(HISTORY_TABLE)
+                .set_item(Some(revision_item(page_key, revision)?))
+                .condition_expression("attribute_not_exists(revision)")
+                .build();
+
+            items.push(TransactWriteItem::builder().put(put).build());
+        }
+
+        // Move the page's users in the user index
+        let attribution = Some(attribution);
+        items.extend(user_index_writes(
+            site_slug,
+            page_slug,
+            &attribution,
+            &None,
+        )?);
+        items.extend(user_index_writes(
+            site_slug,
+            new_page_slug,
+            &None,
+            &attribution,
+        )?);
+
+        // The old slug and its aliases now refer to the new slug
+        for alias_slug in aliases.into_iter().chain(iter::once(str!(page_slug))) {
+            let put = Put::builder()
+                .table_name(ALIAS_TABLE)
+                .item("site_slug", AttributeValue::S(str!(site_slug)))
+                .item("alias_slug", AttributeValue::S(alias_slug))
+                .item("page_slug", AttributeValue::S(str!(new_page_slug)))
+                .build();
+
+            items.push(TransactWriteItem::builder().put(put).build());
+        }
+
+        // Move the page's own translation link, replacing any left at the new slug
+        match translation_of {
+            Some(mut original) => {
+                // A page can't be a translation of itself, but just in case
+                if original.site_slug == site_slug && original.page_slug == page_slug {
+                    original.page_slug = str!(new_page_slug);
+                }
+
+                let delete = Delete::builder()
+                    .table_name(TRANSLATION_TABLE)
+                    .key("page_key", AttributeValue::S(old_key.clone()))
+                    .build();
+
+                let put = Put::builder()
+                    .table_name(TRANSLATION_TABLE)
+                    .item("page_key", AttributeValue::S(new_key.clone()))
+                    .item("site_slug", AttributeValue::S(str!(site_slug)))
+                    .item("page_slug", AttributeValue::S(str!(new_page_slug)))
+                    .item(
+                        "original_key",
+                        AttributeValue::S(page_key(
+                            &original.site_slug,
+                            &original.page_slug,
+                        )),
+                    )
+                    .item("original_site_slug", AttributeValue::S(original.site_slug))
+                    .item("original_page_slug", AttributeValue::S(original.page_slug))
+                    .build();
+
+                items.push(TransactWriteItem::builder().delete(delete).build());
+                items.push(TransactWriteItem::builder().put(put).build());
+            }
+            None => {
+                let delete = Delete::builder()
+                    .table_name(TRANSLATION_TABLE)
+                    .key("page_key", AttributeValue::S(new_key.clone()))
+                    .build();
+
+                items.push(TransactWriteItem::builder().delete(delete).build());
+            }
+        }
+
+        // Point the translations of this page at its new slug
+        for item in translations {
+            let update = Update::builder()
+                .table_name(TRANSLATION_TABLE)
+                .key("page_key", item["page_key"].clone())
+                .update_expression(
+                    "SET original_key = :original_key, original_page_slug = :original_page_slug",
+                )
+                .expression_attribute_values(":original_key", AttributeValue::S(new_key.clone()))
+                .expression_attribute_values(
+                    ":original_page_slug",
+                    AttributeValue::S(str!(new_page_slug)),
+                )
+                .build();
+
+            items.push(TransactWriteItem::builder().update(update).build());
+        }
+
+        // Aliases and translations are unbounded, so this can exceed what a transaction allows
+        if items.len() > MAX_TRANSACTION_ITEMS {
+            warn!(
+                site_slug,
+                page_slug,
+                items = items.len(),
+                "Rename has too many items for a transaction",
+            );
+            return Ok(Some(RenameResult::TooLarge));
+        }
+
+        let result = self
+            .dynamo
+            .transact_write_items()
+            .set_transact_items(Some(items))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(Some(RenameResult::Renamed)),
+            Err(SdkError::ServiceError(ref error))
+                if error.err().is_transaction_canceled_exception() =>
+            {
+                let codes = match error.err() {
+                    TransactWriteItemsError::TransactionCanceledException(error) => error
+                        .cancellation_reasons()
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|reason| reason.code())
+                        .collect(),
+                    _ => Vec::new(),
+                };
+
+                Ok(rename_cancelled(&codes))
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for DynamoStorage {
+    async fn get_page_attribution(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<Attribution>, Error> {
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(ATTRIBUTION_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("page_slug", AttributeValue::S(str!(page_slug)))
+            .projection_expression("attribution")
+            .send()
+            .await?;
+
+        if let Some(item) = result.item() {
+            let object = &item["attribution"];
+            return Ok(Some(object.into()));
+        }
+
+        // Check if this is the old slug of a renamed page
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(ALIAS_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("alias_slug", AttributeValue::S(str!(page_slug)))
+            .send()
+            .await?;
+
+        let page_slug = match result.item() {
+            None => return Ok(None),
+            Some(item) => item["page_slug"]
+                .as_s()
+                .expect("Field 'page_slug' not string"),
+        };
+
+        debug!(page_slug, "Resolved page alias");
+
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(ATTRIBUTION_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("page_slug", AttributeValue::S(str!(page_slug)))
+            .projection_expression("attribution")
+            .send()
+            .await?;
+
+        Ok(result
+            .item()
+            .map(|item| Attribution::from(&item["attribution"])))
+    }
+
+    async fn get_pages_attribution(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, Option<Attribution>>, Error> {
+        let page_keys = |page_slugs: &BTreeSet<&str>, slug_field: &str| {
+            page_slugs
+                .iter()
+                .map(|page_slug| {
+                    hashmap! {
+                        str!("site_slug") => AttributeValue::S(str!(site_slug)),
+                        str!(slug_field) => AttributeValue::S(str!(page_slug)),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Duplicate keys in a batch get are an error
+        let requested = page_slugs
+            .iter()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>();
+
+        let mut pages = self
+            .batch_get(
+                ATTRIBUTION_TABLE,
+                page_keys(&requested, "page_slug"),
+                "page_slug, attribution",
+            )
+            .await?
+            .iter()
+            .map(|item| {
+                let page_slug = item["page_slug"]
+                    .as_s()
+                    .expect("Field 'page_slug' not string");
+
+                (str!(page_slug), Attribution::from(&item["attribution"]))
+            })
+            .collect::<HashMap<_, _>>();
+
+        // Check if any missing pages are the old slugs of renamed pages
+        let missing = requested
+            .iter()
+            .copied()
+            .filter(|page_slug| !pages.contains_key(*page_slug))
+            .collect::<BTreeSet<_>>();
+
+        let mut aliases = HashMap::new();
+        if !missing.is_empty() {
+            let items = self
+                .batch_get(
+                    ALIAS_TABLE,
+                    page_keys(&missing, "alias_slug"),
+                    "alias_slug, page_slug",
+                )
+                .await?;
+
+            for item in items {
+                let get_slug = |field: &str| {
+                    str!(item[field]
+                        .as_s()
+                        .unwrap_or_else(|_| panic!("Field '{field}' not string")))
+                };
+
+                aliases.insert(get_slug("alias_slug"), get_slug("page_slug"));
+            }
+        }
+
+        let targets = aliases
+            .values()
+            .map(String::as_str)
+            .filter(|page_slug| !pages.contains_key(*page_slug))
+            .collect::<BTreeSet<_>>();
+
+        if !targets.is_empty() {
+            debug!(aliases = targets.len(), "Resolving page aliases");
+
+            let items = self
+                .batch_get(
+                    ATTRIBUTION_TABLE,
+                    page_keys(&targets, "page_slug"),
+                    "page_slug, attribution",
+                )
+                .await?;
+
+            for item in items {
+                let page_slug = item["page_slug"]
+                    .as_s()
+                    .expect("Field 'page_slug' not string");
+
+                pages.insert(str!(page_slug), Attribution::from(&item["attribution"]));
+            }
+        }
+
+        let attributions = page_slugs
+            .iter()
+            .map(|page_slug| {
+                let stored_slug = aliases.get(page_slug).unwrap_or(page_slug);
+                let attribution = pages.get(stored_slug).cloned();
+                (page_slug.clone(), attribution)
+            })
+            .collect();
+
+        Ok(attributions)
+    }
+
+    async fn get_alias_target(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<String>, Error> {
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(ALIAS_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("alias_slug", AttributeValue::S(str!(page_slug)))
+            .consistent_read(true)
+            .send()
+            .await?;
+
+        let target = match result.item() {
+            None => return Ok(None),
+            Some(item) => item["page_slug"]
+                .as_s()
+                .expect("Field 'page_slug' not string"),
+        };
+
+        // A page stored under the slug takes precedence, as when reading
+        if self.get_stored_page(site_slug, page_slug).await?.is_some() {
+            return Ok(None);
+        }
+
+        Ok(Some(str!(target)))
+    }
+
+    async fn get_page_revision(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<u64, Error> {
+        self.get_latest_revision(&page_key(site_slug, page_slug))
+            .await
+    }
+
+    async fn get_page_revisions(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, u64>, Error> {
+        // Each page's history is a separate partition, so query several at a time
+        let mut revisions = BTreeMap::new();
+        for chunk in page_slugs.chunks(PARALLEL_REVISION_QUERIES) {
+            let tasks = chunk
+                .iter()
+                .map(|page_slug| {
+                    let query = query_latest_revision(
+                        self.dynamo.clone(),
+                        page_key(site_slug, page_slug),
+                    );
+                    (page_slug, tokio::spawn(query))
+                })
+                .collect::<Vec<_>>();
+
+            for (page_slug, task) in tasks {
+                revisions.insert(page_slug.clone(), task.await??);
+            }
+        }
+
+        Ok(revisions)
+    }
+
+    async fn update_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        attribution: Attribution,
+        change: &Change,
+    ) -> Result<WriteResult, Error> {
+        self.write_with_revision(&site_slug, &page_slug, Some(attribution), change)
+            .await
+    }
+
+    async fn delete_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        change: &Change,
+    ) -> Result<WriteResult, Error> {
+        self.write_with_revision(&site_slug, &page_slug, None, change)
+            .await
+    }
+
+    async fn rename_page(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        new_page_slug: String,
+        change: &Change,
+    ) -> Result<RenameResult, Error> {
+        for attempt in 1..=WRITE_ATTEMPTS {
+            let result = self
+                .try_rename_page(&site_slug, &page_slug, &new_page_slug, change)
+                .await?;
+
+            match result {
+                Some(result) => return Ok(result),
+                None => warn!(
+                    site_slug,
+                    page_slug, attempt, "Page was written concurrently, rename cancelled",
+                ),
+            }
+        }
+
+        error!(
+            site_slug,
+            page_slug, "Page kept being written concurrently, giving up"
+        );
+        Err("Page is being written concurrently, try again".into())
+    }
+
+    async fn write_pages(
+        &self,
+        site_slug: String,
+        writes: Vec<PageWrite>,
+        change: &Change,
+    ) -> Result<BatchResult, Error> {
+        let conditional = writes.iter().any(|write| write.expected_revision.is_some());
+
+        for attempt in 1..=WRITE_ATTEMPTS {
+            let mut items = Vec::new();
+            let mut revisions = Vec::new();
+
+            for PageWrite {
+                page_slug,
+                attribution,
+                expected_revision,
+            } in writes.iter().cloned()
+            {
+                let change = Change {
+                    expected_revision,
+                    ..change.clone()
+                };
+
+                match self
+                    .prepare_write(&site_slug, &page_slug, attribution, &change)
+                    .await?
+                {
+                    PreparedWrite::Items(page_items, revision) => {
+                        items.extend(page_items);
+                        revisions.push(revision);
+                    }
+                    PreparedWrite::Unchanged(revision) => revisions.push(revision),
+                    PreparedWrite::Conflict => return Ok(BatchResult::Conflict),
+                }
+            }
+
+            // Each page also changes its user index entries, so the number of pages alone
+            // doesn't determine whether the batch fits.
+            if items.len() > MAX_TRANSACTION_ITEMS {
+                warn!(
+                    site_slug,
+                    items = items.len(),
+                    "Batch has too many items for a transaction",
+                );
+                return Ok(BatchResult::TooLarge);
+            }
+
+            // A transaction cannot be empty
+            if items.is_empty() {
+                return Ok(BatchResult::Written(revisions));
+            }
+
+            info!(site_slug, items = items.len(), "Writing batch of pages");
+            let result = self
+                .dynamo
+                .transact_write_items()
+                .set_transact_items(Some(items))
+                .send()
+                .await;
+
+            match result {
+                Ok(_) => return Ok(BatchResult::Written(revisions)),
+                Err(SdkError::ServiceError(ref error))
+                    if error.err().is_transaction_canceled_exception() =>
+                {
+                    warn!(
+                        site_slug,
+                        attempt, "A page was written concurrently, transaction cancelled"
+                    );
+
+                    // Without expected revisions, the batch can be made on top of the other write
+                    if conditional {
+                        return Ok(BatchResult::Conflict);
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        error!(
+            site_slug,
+            "Pages kept being written concurrently, giving up"
+        );
+        Err("Pages are being written concurrently, try again".into())
+    }
+
+    async fn get_page_history(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Vec<Revision>, Error> {
+        let mut revisions = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let result = self
+                .dynamo
+                .query()
+                .table_name(HISTORY_TABLE)
+                .set_exclusive_start_key(exclusive_start_key)
+                .key_condition_expression("page_key = :page_key")
+                .expression_attribute_values(
+                    ":page_key",
+                    AttributeValue::S(page_key(site_slug, page_slug)),
+                )
+                .send()
+                .await?;
+
+            if let Some(items) = result.items() {
+                revisions.extend(items.iter().map(convert_revision));
+            }
+
+            match result.last_evaluated_key {
+                None => break,
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(last_evaluated_key);
+                }
+            }
+        }
+
+        Ok(revisions)
+    }
+
+    async fn get_site_attribution(
+        &self,
+        site_slug: &str,
+    ) -> Result<Vec<FullAttribution>, Error> {
+        // Split the site into contiguous ranges of page slugs, and query each in parallel
+        let tasks = site_query_ranges(&self.site_query_splits)
+            .into_iter()
+            .map(|(start, end)| {
+                let query = query_site_range(
+                    self.dynamo.clone(),
+                    str!(site_slug),
+                    start,
+                    end.clone(),
+                );
+                (end, tokio::spawn(query))
+            })
+            .collect::<Vec<_>>();
+
+        let mut ranges = Vec::new();
+        for (end, task) in tasks {
+            ranges.push((end, task.await??));
+        }
+
+        Ok(merge_site_ranges(ranges))
+    }
+
+    async fn get_site_attribution_page(
+        &self,
+        site_slug: &str,
+        limit: u32,
+        start_after: Option<&str>,
+    ) -> Result<(Vec<FullAttribution>, Option<String>), Error> {
+        info!("Running site query (limit {limit}, start after {start_after:?})");
+
+        let exclusive_start_key = start_after.map(|page_slug| {
+            hashmap! {
+                str!("site_slug") => AttributeValue::S(str!(site_slug)),
+                str!("page_slug") => AttributeValue::S(str!(page_slug)),
+            }
+        });
+
+        let result = self
+            .dynamo
+            .query()
+            .table_name(ATTRIBUTION_TABLE)
+            .key_condition_expression("site_slug = :site_slug")
+            .expression_attribute_values(":site_slug", AttributeValue::S(str!(site_slug)))
+            .set_exclusive_start_key(exclusive_start_key)
+            .limit(limit as i32)
+            .send()
+            .await?;
+
+        let attributions = result
+            .items()
+            .unwrap_or_default()
+            .iter()
+            .map(convert_full_attribution)
+            .collect();
+
+        // This may be present even if there are no more items,
+        // in which case the next request simply returns none.
+        let last_page = result.last_evaluated_key().map(|key| {
+            key["page_slug"]
+                .as_s()
+                .expect("Field 'page_slug' not string")
+                .clone()
+        });
+
+        Ok((attributions, last_page))
+    }
+
+    async fn get_page_translations(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<PageTranslations, Error> {
+        let page_key = page_key(site_slug, page_slug);
+
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(TRANSLATION_TABLE)
+            .key("page_key", AttributeValue::S(page_key.clone()))
+            .send()
+            .await?;
+
+        let translation_of = result.item().map(|item| {
+            convert_page_reference(item, "original_site_slug", "original_page_slug")
+        });
+
+        let mut translations = self
+            .get_translation_items(&page_key)
+            .await?
+            .iter()
+            .map(|item| convert_page_reference(item, "site_slug", "page_slug"))
+            .collect::<Vec<_>>();
+
+        // Index items are not returned in any particular order
+        translations.sort();
+
+        Ok(PageTranslations {
+            translation_of,
+            translations,
+        })
+    }
+
+    async fn set_translation_of(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        translation_of: Option<PageReference>,
+    ) -> Result<(), Error> {
+        match translation_of {
+            Some(original) => {
+                let original_key = page_key(&original.site_slug, &original.page_slug);
+                let page_key = page_key(&site_slug, &page_slug);
+
+                self.dynamo
+                    .put_item()
+                    .table_name(TRANSLATION_TABLE)
+                    .item("page_key", AttributeValue::S(page_key))
+                    .item("site_slug", AttributeValue::S(site_slug))
+                    .item("page_slug", AttributeValue::S(page_slug))
+                    .item("original_key", AttributeValue::S(original_key))
+                    .item("original_site_slug", AttributeValue::S(original.site_slug))
+                    .item("original_page_slug", AttributeValue::S(original.page_slug))
+                    .send()
+                    .await?;
+            }
+            None => {
+                self.dynamo
+                    .delete_item()
+                    .table_name(TRANSLATION_TABLE)
+                    .key(
+                        "page_key",
+                        AttributeValue::S(page_key(&site_slug, &page_slug)),
+                    )
+                    .send()
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_user_attribution(
+        &self,
+        user_id: NonZeroU32,
+    ) -> Result<Vec<UserAttribution>, Error> {
+        let mut attributions = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let result = self
+                .dynamo
+                .query()
+                .table_name(USER_TABLE)
+                .key_condition_expression("user_id = :user_id")
+                .expression_attribute_values(":user_id", AttributeValue::N(str!(user_id)))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in result.items().unwrap_or_default() {
+                let site_slug = item["site_slug"]
+                    .as_s()
+                    .expect("Field 'site_slug' not string");
+
+                let page_slug = item["page_slug"]
+                    .as_s()
+                    .expect("Field 'page_slug' not string");
+
+                let entries = Attribution::from(&item["attribution"]);
+                attributions
+                    .extend(UserAttribution::from_page(site_slug, page_slug, &entries));
+            }
+
+            match result.last_evaluated_key {
+                None => break,
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(last_evaluated_key)
+                }
+            }
+        }
+
+        // Items are ordered by page key, in which the '/' separator
+        // can sort differently than the end of a site slug
+        attributions.sort_by(|a, b| {
+            (&a.site_slug, &a.page_slug).cmp(&(&b.site_slug, &b.page_slug))
+        });
+
+        Ok(attributions)
+    }
+
+    async fn get_password(
+        &self,
+        site_slug: &str,
+        password_type: PasswordType,
+    ) -> Result<Option<String>, Error> {
+        let field = password_type.field_name();
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(PASSWORD_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .projection_expression(field)
+            .send()
+            .await?;
+
+        match result.item().and_then(|item| item.get(field)) {
+            None => Ok(None),
+            Some(value) => {
+                let password = value
+                    .as_s()
+                    .expect("Password field in database not string")
+                    .clone();
+
+                Ok(Some(password))
+            }
+        }
+    }
+
+    async fn set_password(
+        &self,
+        site_slug: String,
+        password_type: PasswordType,
+        password: String,
+    ) -> Result<(), Error> {
+        let field = password_type.field_name();
+        self.dynamo
+            .update_item()
+            .table_name(PASSWORD_TABLE)
+            .key("site_slug", AttributeValue::S(site_slug))
+            .update_expression(format!("SET {field} = :passwd"))
+            .expression_attribute_values(":passwd", AttributeValue::S(password))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_wikidot_template(
+        &self,
+        site_slug: &str,
+    ) -> Result<Option<WikidotTemplate>, Error> {
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(TEMPLATE_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .send()
+            .await?;
+
+        match result.item() {
+            None => Ok(None),
+            Some(item) => {
+                let json = item["template"]
+                    .as_s()
+                    .expect("Template field in database not string");
+
+                Ok(Some(serde_json::from_str(json)?))
+            }
+        }
+    }
+
+    async fn set_wikidot_template(
+        &self,
+        site_slug: String,
+        template: WikidotTemplate,
+    ) -> Result<(), Error> {
+        // Stored as JSON, so new template fields don't need a new schema
+        let json = serde_json::to_string(&template)?;
+
+        self.dynamo
+            .put_item()
+            .table_name(TEMPLATE_TABLE)
+            .item("site_slug", AttributeValue::S(site_slug))
+            .item("template", AttributeValue::S(json))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_api_key(
+        &self,
+        site_slug: String,
+        api_key: ApiKey,
+    ) -> Result<(), Error> {
+        let ApiKey {
+            key_id,
+            name,
+            scope,
+            secret_hash,
+            created,
+            expires,
+        } = api_key;
+
+        self.dynamo
+            .put_item()
+            .table_name(API_KEY_TABLE)
+            .item("site_slug", AttributeValue::S(site_slug))
+            .item("key_id", AttributeValue::S(key_id))
+            .item("name", AttributeValue::S(name))
+            .item("scope", AttributeValue::S(str!(scope.field_name())))
+            .item("secret_hash", AttributeValue::S(secret_hash))
+            .item("created", AttributeValue::N(str!(created)))
+            .item(
+                "expires",
+                match expires {
+                    Some(expires) => AttributeValue::N(str!(expires)),
+                    None => AttributeValue::Null(true),
+                },
+            )
+            .condition_expression("attribute_not_exists(key_id)")
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_api_key(
+        &self,
+        site_slug: &str,
+        key_id: &str,
+    ) -> Result<Option<ApiKey>, Error> {
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(API_KEY_TABLE)
+            .key("site_slug", AttributeValue::S(str!(site_slug)))
+            .key("key_id", AttributeValue::S(str!(key_id)))
+            .send()
+            .await?;
+
+        Ok(result.item().map(convert_api_key))
+    }
+
+    async fn list_api_keys(&self, site_slug: &str) -> Result<Vec<ApiKey>, Error> {
+        let mut api_keys = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let result = self
+                .dynamo
+                .query()
+                .table_name(API_KEY_TABLE)
+                .set_exclusive_start_key(exclusive_start_key)
+                .key_condition_expression("site_slug = :site_slug")
+                .expression_attribute_values(
+                    ":site_slug",
+                    AttributeValue::S(str!(site_slug)),
+                )
+                .send()
+                .await?;
+
+            if let Some(items) = result.items() {
+                api_keys.extend(items.iter().map(convert_api_key));
+            }
+
+            match result.last_evaluated_key {
+                None => break,
+                Some(last_evaluated_key) => {
+                    exclusive_start_key = Some(last_evaluated_key);
+                }
+            }
+        }
+
+        Ok(api_keys)
+    }
+
+    async fn delete_api_key(
+        &self,
+        site_slug: String,
+        key_id: String,
+    ) -> Result<bool, Error> {
+        let result = self
+            .dynamo
+            .delete_item()
+            .table_name(API_KEY_TABLE)
+            .key("site_slug", AttributeValue::S(site_slug))
+            .key("key_id", AttributeValue::S(key_id))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await?;
+
+        Ok(result.attributes().is_some())
+    }
+
+    async fn get_login_attempts(
+        &self,
+        key: &str,
+    ) -> Result<Option<LoginAttempts>, Error> {
+        let result = self
+            .dynamo
+            .get_item()
+            .table_name(LOGIN_ATTEMPT_TABLE)
+            .key("attempt_key", AttributeValue::S(str!(key)))
+            .send()
+            .await?;
+
+        Ok(result.item().map(convert_login_attempts))
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: String,
+        timestamp: u64,
+    ) -> Result<Option<LoginAttempts>, Error> {
+        // The 'expires' attribute is the table's TTL, so stale items get cleaned up
+        let result = self
+            .dynamo
+            .update_item()
+            .table_name(LOGIN_ATTEMPT_TABLE)
+            .key("attempt_key", AttributeValue::S(key.clone()))
+            .update_expression(
+                "SET failures = if_not_exists(failures, :zero) + :one, \
+                last_failure = :timestamp, \
+                expires = :expires",
+            )
+            .condition_expression(
+                "attribute_not_exists(attempt_key) OR last_failure >= :stale_before",
+            )
+            .expression_attribute_values(":zero", AttributeValue::N(str!(0)))
+            .expression_attribute_values(":one", AttributeValue::N(str!(1)))
+            .expression_attribute_values(":timestamp", AttributeValue::N(str!(timestamp)))
+            .expression_attribute_values(
+                ":expires",
+                AttributeValue::N(str!(timestamp + ATTEMPT_WINDOW)),
+            )
+            .expression_attribute_values(
+                ":stale_before",
+                AttributeValue::N(str!(timestamp.saturating_sub(ATTEMPT_WINDOW))),
+            )
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await;
+
+        match result {
+            // No attributes if there was no item before
+            Ok(output) => Ok(output.attributes().map(convert_login_attempts)),
+
+            // The previous failures are stale, so start counting afresh
+            Err(SdkError::ServiceError(ref error))
+                if error.err().is_conditional_check_failed_exception() =>
+            {
+                self.dynamo
+                    .put_item()
+                    .table_name(LOGIN_ATTEMPT_TABLE)
+                    .item("attempt_key", AttributeValue::S(key))
+                    .item("failures", AttributeValue::N(str!(1)))
+                    .item("last_failure", AttributeValue::N(str!(timestamp)))
+                    .item(
+                        "expires",
+                        AttributeValue::N(str!(timestamp + ATTEMPT_WINDOW)),
+                    )
+                    .send()
+                    .await?;
+
+                Ok(None)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn release_login_attempt(
+        &self,
+        key: String,
+        timestamp: u64,
+        previous_failure: Option<u64>,
+    ) -> Result<(), Error> {
+        let result = self
+            .dynamo
+            .update_item()
+            .table_name(LOGIN_ATTEMPT_TABLE)
+            .key("attempt_key", AttributeValue::S(key.clone()))
+            .update_expression("SET failures = failures - :one")
+            .condition_expression("failures > :zero")
+            .expression_attribute_values(":zero", AttributeValue::N(str!(0)))
+            .expression_attribute_values(":one", AttributeValue::N(str!(1)))
+            .return_values(ReturnValue::AllNew)
+            .send()
+            .await;
+
+        let attempts = match result {
+            Ok(output) => match output.attributes() {
+                Some(item) => convert_login_attempts(item),
+                None => return Ok(()),
+            },
+
+            // Nothing left to give back
+            Err(SdkError::ServiceError(ref error))
+                if error.err().is_conditional_check_failed_exception() =>
+            {
+                return Ok(());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        // Each of these only applies if no other attempt changed the item since
+        if attempts.failures == 0 {
+            let result = self
+                .dynamo
+                .delete_item()
+                .table_name(LOGIN_ATTEMPT_TABLE)
+                .key("attempt_key", AttributeValue::S(key))
+                .condition_expression("failures = :zero")
+                .expression_attribute_values(":zero", AttributeValue::N(str!(0)))
+                .send()
+                .await;
+
+            match result {
+                Ok(_) => (),
+                Err(SdkError::ServiceError(ref error))
+                    if error.err().is_conditional_check_failed_exception() => {}
+                Err(error) => return Err(error.into()),
+            }
+        } else if let (true, Some(previous_failure)) =
+            (attempts.last_failure == timestamp, previous_failure)
+        {
+            let result = self
+                .dynamo
+                .update_item()
+                .table_name(LOGIN_ATTEMPT_TABLE)
+                .key("attempt_key", AttributeValue::S(key))
+                .update_expression("SET last_failure = :previous")
+                .condition_expression("last_failure = :timestamp")
+                .expression_attribute_values(
+                    ":previous",
+                    AttributeValue::N(str!(previous_failure)),
+                )
+                .expression_attribute_values(
+                    ":timestamp",
+                    AttributeValue::N(str!(timestamp)),
+                )
+                .send()
+                .await;
+
+            match result {
+                Ok(_) => (),
+                Err(SdkError::ServiceError(ref error))
+                    if error.err().is_conditional_check_failed_exception() => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn clear_login_attempts(&self, key: String) -> Result<(), Error> {
+        self.dynamo
+            .delete_item()
+            .table_name(LOGIN_ATTEMPT_TABLE)
+            .key("attempt_key", AttributeValue::S(key))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn convert_api_key(item: &HashMap<String, AttributeValue>) -> ApiKey {
+    let get_string = |field: &str| -> String {
+        item[field]
+            .as_s()
+            .unwrap_or_else(|_| panic!("Field '{field}' not string"))
+            .clone()
+    };
+
+    let get_number = |value: &AttributeValue| -> u64 {
+        value
+            .as_n()
+            .expect("Timestamp field not number")
+            .parse()
+            .expect("Timestamp field not valid integer value")
+    };
+
+    let scope = get_string("scope")
+        .as_str()
+        .try_into()
+        .expect("Field 'scope' not valid ApiKeyScope enum value");
+
+    let expires = match &item["expires"] {
+        AttributeValue::Null(true) => None,
+        value => Some(get_number(value)),
+    };
+
+    ApiKey {
+        key_id: get_string("key_id"),
+        name: get_string("name"),
+        scope,
+        secret_hash: get_string("secret_hash"),
+        created: get_number(&item["created"]),
+        expires,
+    }
+}
+
+fn convert_login_attempts(item: &HashMap<String, AttributeValue>) -> LoginAttempts {
+    LoginAttempts {
+        failures: get_number(item, "failures")
+            .try_into()
+            .expect("Field 'failures' out of range"),
+        last_failure: get_number(item, "last_failure"),
+    }
+}
+
+fn revision_item(
+    page_key: String,
+    revision: Revision,
+) -> Result<HashMap<String, AttributeValue>, Error> {
+    let Revision {
+        revision,
+        timestamp,
+        actor,
+        summary,
+        previous,
+        attribution,
+    } = revision;
+
+    let optional_attribution =
+        |attribution: Option<Attribution>| -> Result<AttributeValue, Error> {
+            match attribution {
+                Some(attribution) => Ok(attribution.try_into()?),
+                None => Ok(AttributeValue::Null(true)),
+            }
+        };
+
+    Ok(hashmap! {
+        str!("page_key") => AttributeValue::S(page_key),
+        str!("revision") => AttributeValue::N(str!(revision)),
+        str!("timestamp") => AttributeValue::N(str!(timestamp)),
+        str!("actor") => AttributeValue::S(actor),
+        str!("summary") => match summary {
+            Some(summary) => AttributeValue::S(summary),
+            None => AttributeValue::Null(true),
+        },
+        str!("previous") => optional_attribution(previous)?,
+        str!("attribution") => optional_attribution(attribution)?,
+    })
+}
+
+/// Builds the writes to the user index for a change to a page's attribution.
+///
+/// Each item holds all of one user's entries on the page, so only users whose
+/// entries have changed need to be written.
+fn user_index_writes(
+    site_slug: &str,
+    page_slug: &str,
+    previous: &Option<Attribution>,
+    attribution: &Option<Attribution>,
+) -> Result<Vec<TransactWriteItem>, Error> {
+    let user_entries = |attribution: &Option<Attribution>| match attribution {
+        Some(attribution) => entries_by_user(attribution),
+        None => BTreeMap::new(),
+    };
+
+    let previous = user_entries(previous);
+    let current = user_entries(attribution);
+    let page_key = page_key(site_slug, page_slug);
+    let mut writes = Vec::new();
+
+    for user_id in previous.keys() {
+        if !current.contains_key(user_id) {
+            let delete = Delete::builder()
+                .table_name(USER_TABLE)
+                .key("user_id", AttributeValue::N(str!(user_id)))
+                .key("page_key", AttributeValue::S(page_key.clone()))
+                .build();
+
+            writes.push(TransactWriteItem::builder().delete(delete).build());
+        }
+    }
+
+    for (user_id, entries) in current {
+        if previous.get(&user_id) == Some(&entries) {
+            continue;
+        }
+
+        let put = Put::builder()
+            .table_name(USER_TABLE)
+            .item("user_id", AttributeValue::N(str!(user_id)))
+            .item("page_key", AttributeValue::S(page_key.clone()))
+            .item("site_slug", AttributeValue::S(str!(site_slug)))
+            .item("page_slug", AttributeValue::S(str!(page_slug)))
+            .item("attribution", entries.try_into()?)
+            .build();
+
+        writes.push(TransactWriteItem::builder().put(put).build());
+    }
+
+    Ok(writes)
+}
+
+fn convert_revision(item: &HashMap<String, AttributeValue>) -> Revision {
+    let optional_attribution = |field: &str| -> Option<Attribution> {
+        match &item[field] {
+            AttributeValue::Null(true) => None,
+            value => Some(value.into()),
+        }
+    };
+
+    let summary = match &item["summary"] {
+        AttributeValue::Null(true) => None,
+        AttributeValue::S(value) => Some(value.clone()),
+        _ => panic!("Field 'summary' not string or null"),
+    };
+
+    Revision {
+        revision: get_number(item, "revision"),
+        timestamp: get_number(item, "timestamp"),
+        actor: item["actor"]
+            .as_s()
+            .expect("Field 'actor' not string")
+            .clone(),
+        summary,
+        previous: optional_attribution("previous"),
+        attribution: optional_attribution("attribution"),
+    }
+}
+
+/// Queries the latest revision in a page's history.
+///
+/// This owns its arguments, so that queries for many pages can be spawned at once.
+async fn query_latest_revision(
+    dynamo: DynamoClient,
+    page_key: String,
+) -> Result<u64, Error> {
+    let result = dynamo
+        .query()
+        .table_name(HISTORY_TABLE)
+        .key_condition_expression("page_key = :page_key")
+        .expression_attribute_values(":page_key", AttributeValue::S(page_key))
+        .projection_expression("revision")
+        .scan_index_forward(false)
+        .limit(1)
+        .consistent_read(true)
+        .send()
+        .await?;
+
+    match result.items().and_then(|items| items.first()) {
+        None => Ok(0),
+        Some(item) => Ok(get_number(item, "revision")),
+    }
+}
+
+/// Splits a site's page slugs into contiguous ranges, divided at each of the given slugs.
+///
+/// Each range is given as its start (inclusive) and end (exclusive), where a bound
+/// of `None` means the range is open on that side. The splits must be sorted.
+fn site_query_ranges(splits: &[String]) -> Vec<(Option<String>, Option<String>)> {
+    let bounds = iter::once(None)
+        .chain(splits.iter().cloned().map(Some))
+        .chain(iter::once(None))
+        .collect::<Vec<_>>();
+
+    bounds
+        .windows(2)
+        .map(|range| (range[0].clone(), range[1].clone()))
+        .collect()
+}
+
+/// Joins the pages queried from each range of a site into a single list, in order.
+///
+/// Each range is given with its end bound. Sort key conditions can't express a
+/// half-open range, so a query with both bounds also returns the page at its end,
+/// which is dropped here since the next range starts with it.
+fn merge_site_ranges(
+    ranges: Vec<(Option<String>, Vec<FullAttribution>)>,
+) -> Vec<FullAttribution> {
+    ranges
+        .into_iter()
+        .flat_map(|(end, pages)| {
+            pages
+                .into_iter()
+                .filter(move |page| end.as_ref() != Some(&page.page_slug))
+        })
+        .collect()
+}
+
+/// Queries all the pages in a site with slugs from `start` to `end`, both inclusive.
+///
+/// A bound of `None` means the range is open on that side. The results for several
+/// ranges are joined with [`merge_site_ranges`].
+async fn query_site_range(
+    dynamo: DynamoClient,
+    site_slug: String,
+    start: Option<String>,
+    end: Option<String>,
+) -> Result<Vec<FullAttribution>, Error> {
+    // Only a range with both bounds includes its end, which the next range also does
+    let key_condition = match (&start, &end) {
+        (None, None) => "site_slug = :site_slug",
+        (Some(_), None) => "site_slug = :site_slug AND page_slug >= :start",
+        (None, Some(_)) => "site_slug = :site_slug AND page_slug < :end",
+        (Some(_), Some(_)) => {
+            "site_slug = :site_slug AND page_slug BETWEEN :start AND :end"
+        }
+    };
+
+    let mut values = hashmap! {
+        str!(":site_slug") => AttributeValue::S(site_slug.clone()),
+    };
+
+    if let Some(ref start) = start {
+        values.insert(str!(":start"), AttributeValue::S(start.clone()));
+    }
+
+    if let Some(ref end) = end {
+        values.insert(str!(":end"), AttributeValue::S(end.clone()));
+    }
+
+    let mut attributions = Vec::new();
+    let mut exclusive_start_key = None;
+    let mut consumed_capacity = 0.0;
+
+    // Maximum body size from DynamoDB is 1 MB, so we may need to fetch repeatedly
+    loop {
+        debug!(
+            start,
+            end, "Running site query (start key {exclusive_start_key:?})"
+        );
+
+        let result = dynamo
+            .query()
+            .table_name(ATTRIBUTION_TABLE)
+            .key_condition_expression(key_condition)
+            .set_expression_attribute_values(Some(values.clone()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await?;
+
+        if let Some(capacity) =
+            result.consumed_capacity().and_then(|c| c.capacity_units())
+        {
+            consumed_capacity += capacity;
+        }
+
+        for item in result.items().unwrap_or_default() {
+            attributions.push(convert_full_attribution(item));
+        }
+
+        // Set flag for last item received to continue pagination
+        match result.last_evaluated_key {
+            None => break,
+            Some(last_evaluated_key) => exclusive_start_key = Some(last_evaluated_key),
+        }
+    }
+
+    info!(
+        site_slug,
+        start,
+        end,
+        pages = attributions.len(),
+        consumed_capacity,
+        "Finished site query",
+    );
+
+    Ok(attributions)
+}
+
+/// Decides whether to retry the keys a batch get left unprocessed, which happens
+/// if it was throttled, or if the response was too large.
+///
+/// Returns the keys to request again and how long to wait first, or `None` if
+/// every key was processed. `retries` counts this retry.
+fn batch_get_retry(
+    table: &str,
+    unprocessed: Option<&HashMap<String, KeysAndAttributes>>,
+    retries: u32,
+) -> Result<Option<(KeysAndAttributes, Duration)>, Error> {
+    let unprocessed = match unprocessed.and_then(|tables| tables.get(table)) {
+        Some(unprocessed) if unprocessed.keys().is_some_and(|keys| !keys.is_empty()) => {
+            unprocessed
+        }
+        _ => return Ok(None),
+    };
+
+    if retries > MAX_BATCH_GET_RETRIES {
+        error!(table, "Batch get still has unprocessed keys, giving up");
+        return Err(format!("Too many retries of batch get from {table}").into());
+    }
+
+    // Back off exponentially, as recommended for throttling
+    let delay = Duration::from_millis(50 << retries);
+    warn!(
+        table,
+        retries,
+        unprocessed = unprocessed.keys().map_or(0, |keys| keys.len()),
+        "Retrying unprocessed keys from batch get",
+    );
+
+    Ok(Some((unprocessed.clone(), delay)))
+}
+
+fn convert_page_reference(
+    item: &HashMap<String, AttributeValue>,
+    site_field: &str,
+    page_field: &str,
+) -> PageReference {
+    let get_string = |field: &str| {
+        item[field]
+            .as_s()
+            .unwrap_or_else(|_| panic!("Field '{field}' not string"))
+            .clone()
+    };
+
+    PageReference {
+        site_slug: get_string(site_field),
+        page_slug: get_string(page_field),
+    }
+}
+
+fn convert_full_attribution(item: &HashMap<String, AttributeValue>) -> FullAttribution {
+    // Extract page_slug
+    let page_slug = item["page_slug"]
+        .as_s()
+        .expect("Field 'page_slug' not string")
+        .clone();
+
+    // Convert from DynamoDB to AttributionEntry
+    let attribution = Attribution::from(&item["attribution"]);
+
+    FullAttribution {
+        page_slug,
+        attribution,
+    }
+}
+
+/// Gets the partition key for a page in tables which are not split by site.
+#[inline]
+/// Decides the result of a rename from why its transaction was cancelled.
+///
+/// Each code is the reason given for the item at that position, if it failed.
+/// Returns `None` if no failed condition decides the result, such as when another
+/// transaction wrote one of the items, so the rename should be tried again.
+fn rename_cancelled(codes: &[Option<&str>]) -> Option<RenameResult> {
+    let failed = |index: usize| {
+        codes.get(index).copied().flatten() == Some("ConditionalCheckFailed")
+    };
+
+    if failed(RENAME_DELETE_OLD) {
+        Some(RenameResult::MissingPage)
+    } else if failed(RENAME_PUT_NEW) || failed(RENAME_DELETE_ALIAS) {
+        Some(RenameResult::ExistingPage)
+    } else {
+        None
+    }
+}
+
+fn page_key(site_slug: &str, page_slug: &str) -> String {
+    format!("{site_slug}/{page_slug}")
+}
+
+fn get_number(item: &HashMap<String, AttributeValue>, field: &str) -> u64 {
+    item[field]
+        .as_n()
+        .unwrap_or_else(|_| panic!("Field '{field}' not number"))
+        .parse()
+        .unwrap_or_else(|_| panic!("Field '{field}' not valid integer value"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_conflicts() {
+        let check = |codes: &[Option<&str>], expected| {
+            assert_eq!(
+                rename_cancelled(codes),
+                expected,
+                "Unexpected result for {codes:?}"
+            );
+        };
+
+        let failed = Some("ConditionalCheckFailed");
+        let none = Some("None");
+
+        // The old page was deleted or renamed first
+        check(&[failed, none, none, none], Some(RenameResult::MissingPage));
+        check(&[failed, failed, none], Some(RenameResult::MissingPage));
+
+        // The new slug was taken by a page, or became another page's alias
+        check(
+            &[none, failed, none, none],
+            Some(RenameResult::ExistingPage),
+        );
+        check(
+            &[none, none, failed, none],
+            Some(RenameResult::ExistingPage),
+        );
+
+        // A concurrent write to a history or index item is retried
+        check(&[none, none, none, failed], None);
+        check(&[none, Some("TransactionConflict"), none], None);
+        check(&[], None);
+    }
+
+    fn keys(table: &str, slugs: &[&str]) -> HashMap<String, KeysAndAttributes> {
+        let keys = slugs
+            .iter()
+            .map(|slug| hashmap! { str!("page_slug") => AttributeValue::S(str!(slug)) })
+            .collect();
+
+        let keys = KeysAndAttributes::builder().set_keys(Some(keys)).build();
+        hashmap! { str!(table) => keys }
+    }
+
+    #[test]
+    fn batch_get_retries() {
+        let table = "attribution";
+
+        // Everything was processed
+        assert!(batch_get_retry(table, None, 1).unwrap().is_none());
+        assert!(batch_get_retry(table, Some(&HashMap::new()), 1)
+            .unwrap()
+            .is_none());
+        assert!(batch_get_retry(table, Some(&keys(table, &[])), 1)
+            .unwrap()
+            .is_none());
+        assert!(
+            batch_get_retry(table, Some(&keys("other", &["scp-173"])), 1)
+                .unwrap()
+                .is_none()
+        );
+
+        // Only the unprocessed keys are requested again, backing off each time
+        let unprocessed = keys(table, &["scp-173", "scp-096"]);
+        for retries in 1..=MAX_BATCH_GET_RETRIES {
+            let (request, delay) = batch_get_retry(table, Some(&unprocessed), retries)
+                .unwrap()
+                .expect("Unprocessed keys not retried");
+
+            assert_eq!(&request, &unprocessed[table]);
+            assert_eq!(delay, Duration::from_millis(50 << retries));
+        }
+
+        // Until it gives up
+        assert!(
+            batch_get_retry(table, Some(&unprocessed), MAX_BATCH_GET_RETRIES + 1)
+                .is_err()
+        );
+    }
+
+    fn pages(slugs: &[&str]) -> Vec<FullAttribution> {
+        slugs
+            .iter()
+            .map(|slug| FullAttribution {
+                page_slug: str!(slug),
+                attribution: Attribution(vec![]),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn site_ranges() {
+        assert_eq!(site_query_ranges(&[]), vec![(None, None)]);
+        assert_eq!(
+            site_query_ranges(&[str!("g"), str!("p")]),
+            vec![
+                (None, Some(str!("g"))),
+                (Some(str!("g")), Some(str!("p"))),
+                (Some(str!("p")), None),
+            ],
+        );
+
+        // Each bounded range also returns the page at its end, which starts the next
+        let ranges = site_query_ranges(&[str!("g"), str!("p")])
+            .into_iter()
+            .zip([
+                pages(&["a", "f", "g"]),
+                pages(&["g", "h", "p"]),
+                pages(&["p", "z"]),
+            ])
+            .map(|((_, end), pages)| (end, pages))
+            .collect();
+
+        let slugs = merge_site_ranges(ranges)
+            .into_iter()
+            .map(|page| page.page_slug)
+            .collect::<Vec<_>>();
+
+        assert_eq!(slugs, ["a", "f", "g", "h", "p", "z"]);
+
+        // Including when a range has no pages, or lacks the page at its end
+        let ranges = vec![
+            (Some(str!("g")), pages(&["a"])),
+            (Some(str!("p")), pages(&[])),
+            (None, pages(&["q"])),
+        ];
+
+        let slugs = merge_site_ranges(ranges)
+            .into_iter()
+            .map(|page| page.page_slug)
+            .collect::<Vec<_>>();
+
+        assert_eq!(slugs, ["a", "q"]);
+    }
+}
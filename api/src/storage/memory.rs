@@ -0,0 +1,1519 @@
+/*
+ * storage/memory.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+use super::{BatchResult, RenameResult, Storage, WriteResult};
+use crate::apikey::ApiKey;
+use crate::batch::PageWrite;
+use crate::history::{Change, Revision};
+use crate::object::{Attribution, FullAttribution};
+use crate::password::PasswordType;
+use crate::ratelimit::LoginAttempts;
+use crate::translation::{PageReference, PageTranslations};
+use crate::user::{entries_by_user, UserAttribution};
+use crate::wikidot::WikidotTemplate;
+use async_trait::async_trait;
+use lambda_http::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::num::NonZeroU32;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Storage backend which keeps all data in process memory.
+///
+/// Nothing is persisted, so this is only intended for local development
+/// and testing. It can be seeded with initial data from a JSON fixture file.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    sites: RwLock<BTreeMap<String, MemorySite>>,
+    login_attempts: RwLock<HashMap<String, LoginAttempts>>,
+}
+
+/// All the data stored for a single site.
+///
+/// This is also the per-site format of fixture files, which are
+/// a JSON object mapping site slugs to these objects.
+#[derive(Deserialize, Debug, Default)]
+struct MemorySite {
+    #[serde(default)]
+    passwords: MemoryPasswords,
+
+    #[serde(default)]
+    pages: BTreeMap<String, Attribution>,
+
+    #[serde(default)]
+    wikidot_template: Option<WikidotTemplate>,
+
+    /// The current slug of each page which has been renamed, by its old slug.
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+
+    /// The original of each page which is a translation.
+    #[serde(default)]
+    translation_of: BTreeMap<String, PageReference>,
+
+    #[serde(skip)]
+    api_keys: BTreeMap<String, ApiKey>,
+
+    #[serde(skip)]
+    history: BTreeMap<String, Vec<Revision>>,
+}
+
+impl MemorySite {
+    /// Sets or removes a page's attribution, recording the change in its history.
+    fn write_page(
+        &mut self,
+        page_slug: String,
+        mut attribution: Option<Attribution>,
+        change: &Change,
+    ) -> WriteResult {
+        let history = self.history.entry(page_slug.clone()).or_default();
+        let latest = history.len() as u64;
+
+        if change
+            .expected_revision
+            .is_some_and(|expected| expected != latest)
+        {
+            return WriteResult::Conflict;
+        }
+
+        let previous = match attribution {
+            Some(ref mut attribution) => {
+                attribution.assign_ids(self.pages.get(&page_slug));
+                self.pages.insert(page_slug, attribution.clone())
+            }
+            None => self.pages.remove(&page_slug),
+        };
+
+        // Deleting a nonexistent page changes nothing
+        if previous.is_none() && attribution.is_none() {
+            return WriteResult::Written(latest);
+        }
+
+        history.push(Revision::new(latest + 1, previous, attribution, change));
+        WriteResult::Written(latest + 1)
+    }
+
+    /// Gets a page's attribution, resolving the slug if it is an alias.
+    fn get_page(&self, page_slug: &str) -> Option<&Attribution> {
+        self.pages.get(page_slug).or_else(|| {
+            self.aliases
+                .get(page_slug)
+                .and_then(|page_slug| self.pages.get(page_slug))
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct MemoryPasswords {
+    regular: Option<String>,
+    admin: Option<String>,
+}
+
+impl MemoryPasswords {
+    fn get(&self, password_type: PasswordType) -> Option<&String> {
+        match password_type {
+            PasswordType::Regular => self.regular.as_ref(),
+            PasswordType::Admin => self.admin.as_ref(),
+        }
+    }
+
+    fn set(&mut self, password_type: PasswordType, password: String) {
+        match password_type {
+            PasswordType::Regular => self.regular = Some(password),
+            PasswordType::Admin => self.admin = Some(password),
+        }
+    }
+}
+
+impl MemoryStorage {
+    #[inline]
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    /// Creates a new in-memory store, seeded from the given fixture file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        info!("Loading in-memory storage fixture from {}", path.display());
+
+        let contents = fs::read(path)?;
+        let mut sites: BTreeMap<String, MemorySite> = serde_json::from_slice(&contents)?;
+
+        // Ensure fixture data looks like what the handlers would have stored
+        for (site_slug, site) in &mut sites {
+            for (page_slug, attribution) in &mut site.pages {
+                attribution.sort();
+                attribution.validate().map_err(|message| {
+                    format!("Invalid fixture attribution for {site_slug}/{page_slug}: {message}")
+                })?;
+                attribution.assign_ids(None);
+            }
+        }
+
+        Ok(MemoryStorage {
+            sites: RwLock::new(sites),
+            login_attempts: RwLock::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get_page_attribution(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<Attribution>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let attribution = sites
+            .get(site_slug)
+            .and_then(|site| site.get_page(page_slug))
+            .cloned();
+
+        Ok(attribution)
+    }
+
+    async fn get_pages_attribution(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, Option<Attribution>>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let site = sites.get(site_slug);
+        let attributions = page_slugs
+            .iter()
+            .map(|page_slug| {
+                let attribution = site.and_then(|site| site.get_page(page_slug)).cloned();
+                (page_slug.clone(), attribution)
+            })
+            .collect();
+
+        Ok(attributions)
+    }
+
+    async fn get_alias_target(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<String>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let target = sites
+            .get(site_slug)
+            .filter(|site| !site.pages.contains_key(page_slug))
+            .and_then(|site| site.aliases.get(page_slug))
+            .cloned();
+
+        Ok(target)
+    }
+
+    async fn get_page_revision(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<u64, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let revision = sites
+            .get(site_slug)
+            .and_then(|site| site.history.get(page_slug))
+            .map_or(0, |history| history.len() as u64);
+
+        Ok(revision)
+    }
+
+    async fn get_page_revisions(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, u64>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let site = sites.get(site_slug);
+        let revisions = page_slugs
+            .iter()
+            .map(|page_slug| {
+                let revision = site
+                    .and_then(|site| site.history.get(page_slug))
+                    .map_or(0, |history| history.len() as u64);
+
+                (page_slug.clone(), revision)
+            })
+            .collect();
+
+        Ok(revisions)
+    }
+
+    async fn update_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        attribution: Attribution,
+        change: &Change,
+    ) -> Result<WriteResult, Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        let result = sites.entry(site_slug).or_default().write_page(
+            page_slug,
+            Some(attribution),
+            change,
+        );
+
+        Ok(result)
+    }
+
+    async fn delete_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        change: &Change,
+    ) -> Result<WriteResult, Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        let result = sites
+            .entry(site_slug)
+            .or_default()
+            .write_page(page_slug, None, change);
+
+        Ok(result)
+    }
+
+    async fn rename_page(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        new_page_slug: String,
+        change: &Change,
+    ) -> Result<RenameResult, Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        let site = sites.entry(site_slug.clone()).or_default();
+
+        let attribution = match site.pages.get(&page_slug) {
+            Some(attribution) => attribution.clone(),
+            None => return Ok(RenameResult::MissingPage),
+        };
+
+        if site.pages.contains_key(&new_page_slug) {
+            return Ok(RenameResult::ExistingPage);
+        }
+
+        // Another page's old slug can't be taken, since reads of it would change pages
+        if site
+            .aliases
+            .get(&new_page_slug)
+            .is_some_and(|target| *target != page_slug)
+        {
+            return Ok(RenameResult::ExistingPage);
+        }
+
+        site.write_page(page_slug.clone(), None, change);
+        site.write_page(new_page_slug.clone(), Some(attribution), change);
+
+        site.aliases.remove(&new_page_slug);
+        for target in site.aliases.values_mut() {
+            if *target == page_slug {
+                target.clone_from(&new_page_slug);
+            }
+        }
+
+        site.aliases
+            .insert(page_slug.clone(), new_page_slug.clone());
+
+        // Move the page's own translation link, replacing any left at the new slug
+        site.translation_of.remove(&new_page_slug);
+        if let Some(original) = site.translation_of.remove(&page_slug) {
+            site.translation_of.insert(new_page_slug.clone(), original);
+        }
+
+        // Point the translations of this page, on any site, at its new slug
+        for site in sites.values_mut() {
+            for original in site.translation_of.values_mut() {
+                if original.site_slug == site_slug && original.page_slug == page_slug {
+                    original.page_slug.clone_from(&new_page_slug);
+                }
+            }
+        }
+
+        Ok(RenameResult::Renamed)
+    }
+
+    async fn write_pages(
+        &self,
+        site_slug: String,
+        writes: Vec<PageWrite>,
+        change: &Change,
+    ) -> Result<BatchResult, Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        let site = sites.entry(site_slug).or_default();
+
+        // Check every page first, since nothing can be undone once written
+        for write in &writes {
+            let latest = site
+                .history
+                .get(&write.page_slug)
+                .map_or(0, |history| history.len() as u64);
+
+            if write
+                .expected_revision
+                .is_some_and(|expected| expected != latest)
+            {
+                return Ok(BatchResult::Conflict);
+            }
+        }
+
+        let change = Change {
+            expected_revision: None,
+            ..change.clone()
+        };
+
+        let revisions = writes
+            .into_iter()
+            .map(|write| {
+                match site.write_page(write.page_slug, write.attribution, &change) {
+                    WriteResult::Written(revision) => revision,
+                    WriteResult::Conflict => {
+                        unreachable!("Unconditional write conflicted")
+                    }
+                }
+            })
+            .collect();
+
+        Ok(BatchResult::Written(revisions))
+    }
+
+    async fn get_page_history(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Vec<Revision>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let revisions = sites
+            .get(site_slug)
+            .and_then(|site| site.history.get(page_slug))
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(revisions)
+    }
+
+    async fn get_site_attribution(
+        &self,
+        site_slug: &str,
+    ) -> Result<Vec<FullAttribution>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let attributions = match sites.get(site_slug) {
+            None => Vec::new(),
+            Some(site) => site
+                .pages
+                .iter()
+                .map(|(page_slug, attribution)| FullAttribution {
+                    page_slug: page_slug.clone(),
+                    attribution: attribution.clone(),
+                })
+                .collect(),
+        };
+
+        Ok(attributions)
+    }
+
+    async fn get_site_attribution_page(
+        &self,
+        site_slug: &str,
+        limit: u32,
+        start_after: Option<&str>,
+    ) -> Result<(Vec<FullAttribution>, Option<String>), Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let site = match sites.get(site_slug) {
+            Some(site) => site,
+            None => return Ok((Vec::new(), None)),
+        };
+
+        let start = match start_after {
+            Some(page_slug) => Bound::Excluded(page_slug),
+            None => Bound::Unbounded,
+        };
+
+        let mut pages = site.pages.range::<str, _>((start, Bound::Unbounded));
+        let attributions = pages
+            .by_ref()
+            .take(limit as usize)
+            .map(|(page_slug, attribution)| FullAttribution {
+                page_slug: page_slug.clone(),
+                attribution: attribution.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let last_page = match pages.next() {
+            Some(_) => attributions
+                .last()
+                .map(|attribution| attribution.page_slug.clone()),
+            None => None,
+        };
+
+        Ok((attributions, last_page))
+    }
+
+    async fn get_page_translations(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<PageTranslations, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let translation_of = sites
+            .get(site_slug)
+            .and_then(|site| site.translation_of.get(page_slug))
+            .cloned();
+
+        let mut translations = Vec::new();
+        for (translation_site_slug, site) in sites.iter() {
+            for (translation_page_slug, original) in &site.translation_of {
+                if original.site_slug == site_slug && original.page_slug == page_slug {
+                    translations.push(PageReference {
+                        site_slug: translation_site_slug.clone(),
+                        page_slug: translation_page_slug.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(PageTranslations {
+            translation_of,
+            translations,
+        })
+    }
+
+    async fn set_translation_of(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        translation_of: Option<PageReference>,
+    ) -> Result<(), Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        let site = sites.entry(site_slug).or_default();
+
+        match translation_of {
+            Some(original) => site.translation_of.insert(page_slug, original),
+            None => site.translation_of.remove(&page_slug),
+        };
+
+        Ok(())
+    }
+
+    async fn get_user_attribution(
+        &self,
+        user_id: NonZeroU32,
+    ) -> Result<Vec<UserAttribution>, Error> {
+        // Small enough to search every page instead of maintaining an index
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let mut attributions = Vec::new();
+
+        for (site_slug, site) in sites.iter() {
+            for (page_slug, attribution) in &site.pages {
+                if let Some(entries) = entries_by_user(attribution).get(&user_id) {
+                    attributions.extend(UserAttribution::from_page(
+                        site_slug, page_slug, entries,
+                    ));
+                }
+            }
+        }
+
+        Ok(attributions)
+    }
+
+    async fn get_password(
+        &self,
+        site_slug: &str,
+        password_type: PasswordType,
+    ) -> Result<Option<String>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let password = sites
+            .get(site_slug)
+            .and_then(|site| site.passwords.get(password_type))
+            .cloned();
+
+        Ok(password)
+    }
+
+    async fn set_password(
+        &self,
+        site_slug: String,
+        password_type: PasswordType,
+        password: String,
+    ) -> Result<(), Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        sites
+            .entry(site_slug)
+            .or_default()
+            .passwords
+            .set(password_type, password);
+
+        Ok(())
+    }
+
+    async fn get_wikidot_template(
+        &self,
+        site_slug: &str,
+    ) -> Result<Option<WikidotTemplate>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let template = sites
+            .get(site_slug)
+            .and_then(|site| site.wikidot_template.clone());
+
+        Ok(template)
+    }
+
+    async fn set_wikidot_template(
+        &self,
+        site_slug: String,
+        template: WikidotTemplate,
+    ) -> Result<(), Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        sites.entry(site_slug).or_default().wikidot_template = Some(template);
+        Ok(())
+    }
+
+    async fn create_api_key(
+        &self,
+        site_slug: String,
+        api_key: ApiKey,
+    ) -> Result<(), Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        sites
+            .entry(site_slug)
+            .or_default()
+            .api_keys
+            .insert(api_key.key_id.clone(), api_key);
+
+        Ok(())
+    }
+
+    async fn get_api_key(
+        &self,
+        site_slug: &str,
+        key_id: &str,
+    ) -> Result<Option<ApiKey>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let api_key = sites
+            .get(site_slug)
+            .and_then(|site| site.api_keys.get(key_id))
+            .cloned();
+
+        Ok(api_key)
+    }
+
+    async fn list_api_keys(&self, site_slug: &str) -> Result<Vec<ApiKey>, Error> {
+        let sites = self.sites.read().expect("Memory storage lock poisoned");
+        let api_keys = match sites.get(site_slug) {
+            None => Vec::new(),
+            Some(site) => site.api_keys.values().cloned().collect(),
+        };
+
+        Ok(api_keys)
+    }
+
+    async fn delete_api_key(
+        &self,
+        site_slug: String,
+        key_id: String,
+    ) -> Result<bool, Error> {
+        let mut sites = self.sites.write().expect("Memory storage lock poisoned");
+        let existed = match sites.get_mut(&site_slug) {
+            None => false,
+            Some(site) => site.api_keys.remove(&key_id).is_some(),
+        };
+
+        Ok(existed)
+    }
+
+    async fn get_login_attempts(
+        &self,
+        key: &str,
+    ) -> Result<Option<LoginAttempts>, Error> {
+        let login_attempts = self
+            .login_attempts
+            .read()
+            .expect("Memory storage lock poisoned");
+
+        Ok(login_attempts.get(key).copied())
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: String,
+        timestamp: u64,
+    ) -> Result<Option<LoginAttempts>, Error> {
+        let mut login_attempts = self
+            .login_attempts
+            .write()
+            .expect("Memory storage lock poisoned");
+
+        let previous = login_attempts.get(&key).copied();
+        let failures = match previous {
+            Some(attempts) if !attempts.is_stale(timestamp) => attempts.failures + 1,
+            _ => 1,
+        };
+
+        login_attempts.insert(
+            key,
+            LoginAttempts {
+                failures,
+                last_failure: timestamp,
+            },
+        );
+
+        Ok(previous)
+    }
+
+    async fn release_login_attempt(
+        &self,
+        key: String,
+        timestamp: u64,
+        previous_failure: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut login_attempts = self
+            .login_attempts
+            .write()
+            .expect("Memory storage lock poisoned");
+
+        if let Some(attempts) = login_attempts.get_mut(&key) {
+            attempts.failures = attempts.failures.saturating_sub(1);
+
+            if attempts.failures == 0 {
+                login_attempts.remove(&key);
+            } else if attempts.last_failure == timestamp {
+                if let Some(previous_failure) = previous_failure {
+                    attempts.last_failure = previous_failure;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn clear_login_attempts(&self, key: String) -> Result<(), Error> {
+        let mut login_attempts = self
+            .login_attempts
+            .write()
+            .expect("Memory storage lock poisoned");
+
+        login_attempts.remove(&key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Actor;
+    use crate::object::{AttributionEntry, AttributionType};
+
+    const SITE: &str = "scp-wiki";
+
+    /// Creates an attribution list whose entry already has an ID, so it is stored as is.
+    fn attribution(user_name: &str) -> Attribution {
+        Attribution(vec![AttributionEntry {
+            id: Some(user_name.to_lowercase()),
+            attribution_type: AttributionType::Author,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }])
+    }
+
+    fn change() -> Change {
+        Change::new(
+            Actor::Password(PasswordType::Regular),
+            Some(str!("Testing")),
+        )
+    }
+
+    #[tokio::test]
+    async fn pages() {
+        let storage = MemoryStorage::new();
+        assert_eq!(
+            storage.get_page_attribution(SITE, "scp-173").await.unwrap(),
+            None
+        );
+
+        for user_name in ["Alice", "Bob"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!("scp-173"),
+                    attribution(user_name),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let stored = storage.get_page_attribution(SITE, "scp-173").await.unwrap();
+        assert_eq!(stored, Some(attribution("Bob")));
+
+        // Pages are kept separately per site
+        let stored = storage
+            .get_page_attribution("scp-jp", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(stored, None);
+
+        storage
+            .delete_page_attribution(str!(SITE), str!("scp-173"), &change())
+            .await
+            .unwrap();
+
+        let stored = storage.get_page_attribution(SITE, "scp-173").await.unwrap();
+        assert_eq!(stored, None);
+    }
+
+    #[tokio::test]
+    async fn entry_ids() {
+        let storage = MemoryStorage::new();
+        let write = |attribution: Attribution| {
+            let storage = &storage;
+            async move {
+                storage
+                    .update_page_attribution(
+                        str!(SITE),
+                        str!("scp-173"),
+                        attribution,
+                        &change(),
+                    )
+                    .await
+                    .unwrap();
+
+                storage
+                    .get_page_attribution(SITE, "scp-173")
+                    .await
+                    .unwrap()
+                    .unwrap()
+            }
+        };
+
+        // Entries are given IDs when first stored, and keep them when written without
+        let mut without_ids = attribution("Alice");
+        without_ids.0[0].id = None;
+        let stored = write(without_ids.clone()).await;
+        let id = stored.0[0].id.clone();
+        assert!(id.is_some());
+        assert_eq!(write(without_ids).await.0[0].id, id);
+
+        // The history records the IDs stored
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        assert_eq!(history[1].previous, Some(stored.clone()));
+        assert_eq!(history[1].attribution, Some(stored));
+
+        // Given IDs are kept as they are
+        assert_eq!(write(attribution("Bob")).await, attribution("Bob"));
+    }
+
+    #[tokio::test]
+    async fn site_in_order() {
+        let storage = MemoryStorage::new();
+        for page_slug in ["scp-173", "scp-002", "scp-1000"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!(page_slug),
+                    attribution("Alice"),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let page_slugs: Vec<_> = storage
+            .get_site_attribution(SITE)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|full| full.page_slug)
+            .collect();
+
+        assert_eq!(page_slugs, ["scp-002", "scp-1000", "scp-173"]);
+        assert!(storage
+            .get_site_attribution("scp-jp")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn pages_batch() {
+        let storage = MemoryStorage::new();
+        for page_slug in ["scp-173", "scp-096"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!(page_slug),
+                    attribution("Alice"),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Missing pages are included, but not pages which weren't asked for
+        let page_slugs = [str!("scp-173"), str!("scp-999")];
+        let stored = storage
+            .get_pages_attribution(SITE, &page_slugs)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored,
+            btreemap! {
+                str!("scp-173") => Some(attribution("Alice")),
+                str!("scp-999") => None,
+            },
+        );
+
+        let stored = storage
+            .get_pages_attribution("scp-jp", &page_slugs)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored,
+            btreemap! {
+                str!("scp-173") => None,
+                str!("scp-999") => None,
+            },
+        );
+
+        let stored = storage.get_pages_attribution(SITE, &[]).await.unwrap();
+        assert!(stored.is_empty());
+    }
+
+    #[tokio::test]
+    async fn passwords() {
+        let storage = MemoryStorage::new();
+        let get = |password_type| storage.get_password(SITE, password_type);
+
+        assert_eq!(get(PasswordType::Regular).await.unwrap(), None);
+        storage
+            .set_password(str!(SITE), PasswordType::Regular, str!("hunter2"))
+            .await
+            .unwrap();
+
+        let password = get(PasswordType::Regular).await.unwrap();
+        assert_eq!(password.as_deref(), Some("hunter2"));
+        assert_eq!(get(PasswordType::Admin).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn load_fixture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("misc/fixture.json");
+        let storage = MemoryStorage::load(&path).unwrap();
+
+        let password = storage
+            .get_password("test-site", PasswordType::Admin)
+            .await
+            .unwrap();
+        assert_eq!(password.as_deref(), Some("admin-password"));
+
+        // Entries are sorted when loaded
+        let attribution = storage
+            .get_page_attribution("test-site", "scp-002")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(attribution
+            .0
+            .windows(2)
+            .all(|pair| pair[0].date <= pair[1].date));
+    }
+
+    #[tokio::test]
+    async fn history() {
+        let storage = MemoryStorage::new();
+        for user_name in ["Alice", "Bob"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!("scp-173"),
+                    attribution(user_name),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Deleting twice only records one revision
+        for _ in 0..2 {
+            storage
+                .delete_page_attribution(str!(SITE), str!("scp-173"), &change())
+                .await
+                .unwrap();
+        }
+
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        let revisions: Vec<_> =
+            history.iter().map(|revision| revision.revision).collect();
+        assert_eq!(revisions, vec![1, 2, 3]);
+
+        assert_eq!(history[0].previous, None);
+        assert_eq!(history[0].attribution, Some(attribution("Alice")));
+        assert_eq!(history[1].previous, Some(attribution("Alice")));
+        assert_eq!(history[1].attribution, Some(attribution("Bob")));
+        assert_eq!(history[2].previous, Some(attribution("Bob")));
+        assert_eq!(history[2].attribution, None);
+
+        assert_eq!(history[0].actor, "password:regular");
+        assert_eq!(history[0].summary.as_deref(), Some("Testing"));
+
+        let history = storage.get_page_history(SITE, "scp-001").await.unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn page_revisions() {
+        let storage = MemoryStorage::new();
+        for user_name in ["Alice", "Bob"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!("scp-173"),
+                    attribution(user_name),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        storage
+            .update_page_attribution(
+                str!("scp-jp"),
+                str!("scp-096"),
+                attribution("Alice"),
+                &change(),
+            )
+            .await
+            .unwrap();
+
+        let page_slugs = [str!("scp-173"), str!("scp-096")];
+        let revisions = storage.get_page_revisions(SITE, &page_slugs).await.unwrap();
+
+        assert_eq!(
+            revisions,
+            btreemap! {
+                str!("scp-173") => 2,
+                str!("scp-096") => 0,
+            },
+        );
+
+        let revisions = storage.get_page_revisions(SITE, &[]).await.unwrap();
+        assert!(revisions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expected_revision() {
+        let storage = MemoryStorage::new();
+        let write = |user_name: Option<&str>, expected_revision| {
+            let storage = &storage;
+            let mut change = change();
+            change.expected_revision = expected_revision;
+            let user_name = user_name.map(attribution);
+            async move {
+                match user_name {
+                    Some(attribution) => storage
+                        .update_page_attribution(
+                            str!(SITE),
+                            str!("scp-173"),
+                            attribution,
+                            &change,
+                        )
+                        .await
+                        .unwrap(),
+                    None => storage
+                        .delete_page_attribution(str!(SITE), str!("scp-173"), &change)
+                        .await
+                        .unwrap(),
+                }
+            }
+        };
+
+        // A page with no revisions is at revision 0
+        assert_eq!(write(Some("Alice"), Some(1)).await, WriteResult::Conflict);
+        assert_eq!(write(Some("Alice"), Some(0)).await, WriteResult::Written(1));
+        assert_eq!(write(Some("Bob"), None).await, WriteResult::Written(2));
+
+        // A stale revision writes nothing
+        assert_eq!(write(Some("Carol"), Some(1)).await, WriteResult::Conflict);
+        assert_eq!(write(None, Some(1)).await, WriteResult::Conflict);
+        assert_eq!(storage.get_page_revision(SITE, "scp-173").await.unwrap(), 2);
+        assert_eq!(
+            storage.get_page_attribution(SITE, "scp-173").await.unwrap(),
+            Some(attribution("Bob")),
+        );
+
+        assert_eq!(write(None, Some(2)).await, WriteResult::Written(3));
+        assert_eq!(
+            storage
+                .get_page_history(SITE, "scp-173")
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn write_pages() {
+        let storage = MemoryStorage::new();
+        let write =
+            |page_slug: &str, user_name: Option<&str>, expected_revision| PageWrite {
+                page_slug: str!(page_slug),
+                attribution: user_name.map(|user_name| {
+                    let mut attribution = attribution(user_name);
+                    attribution.0[0].user_id = NonZeroU32::new(1);
+                    attribution
+                }),
+                expected_revision,
+            };
+        let users = |storage| async move {
+            let storage: &dyn Storage = storage;
+            let user_id = NonZeroU32::new(1).unwrap();
+            let credits = storage.get_user_attribution(user_id).await.unwrap();
+            credits
+                .into_iter()
+                .map(|credit| (credit.page_slug, credit.user_name))
+                .collect::<Vec<_>>()
+        };
+
+        let result = storage
+            .write_pages(
+                str!(SITE),
+                vec![
+                    write("scp-173", Some("Alice"), Some(0)),
+                    write("scp-001", Some("Bob"), None),
+                    // Deleting a page with no attribution changes nothing
+                    write("scp-002", None, None),
+                ],
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, BatchResult::Written(vec![1, 1, 0]));
+        assert_eq!(
+            users(&storage).await,
+            [
+                (str!("scp-001"), str!("Bob")),
+                (str!("scp-173"), str!("Alice"))
+            ],
+        );
+
+        // If any page conflicts, nothing is written
+        let result = storage
+            .write_pages(
+                str!(SITE),
+                vec![
+                    write("scp-173", None, Some(1)),
+                    write("scp-002", Some("Carol"), None),
+                    write("scp-001", Some("Dave"), Some(0)),
+                ],
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, BatchResult::Conflict);
+
+        for (page_slug, revision) in [("scp-173", 1), ("scp-001", 1), ("scp-002", 0)] {
+            let latest = storage.get_page_revision(SITE, page_slug).await.unwrap();
+            assert_eq!(latest, revision, "{page_slug} was written");
+        }
+        assert_eq!(
+            users(&storage).await,
+            [
+                (str!("scp-001"), str!("Bob")),
+                (str!("scp-173"), str!("Alice"))
+            ],
+        );
+
+        let result = storage
+            .write_pages(
+                str!(SITE),
+                vec![
+                    write("scp-173", None, Some(1)),
+                    write("scp-002", Some("Carol"), None),
+                ],
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, BatchResult::Written(vec![2, 1]));
+        assert_eq!(
+            users(&storage).await,
+            [
+                (str!("scp-001"), str!("Bob")),
+                (str!("scp-002"), str!("Carol"))
+            ],
+        );
+
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        assert_eq!(
+            history[1].previous,
+            Some(history[0].attribution.clone().unwrap())
+        );
+        assert_eq!(history[1].attribution, None);
+    }
+
+    #[tokio::test]
+    async fn user_index() {
+        let storage = MemoryStorage::new();
+        let entry = |attribution_type, user_name: &str, user_id| AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: str!(user_name),
+            user_id: NonZeroU32::new(user_id),
+            date: None,
+        };
+        let credit =
+            |site_slug: &str, page_slug: &str, attribution_type, user_name: &str| {
+                UserAttribution {
+                    site_slug: str!(site_slug),
+                    page_slug: str!(page_slug),
+                    attribution_type,
+                    user_name: str!(user_name),
+                    date: None,
+                }
+            };
+        let alice = NonZeroU32::new(1).unwrap();
+        let bob = NonZeroU32::new(2).unwrap();
+
+        let writes = [
+            (
+                SITE,
+                "scp-173",
+                vec![
+                    entry(AttributionType::Author, "Alice", 1),
+                    entry(AttributionType::Rewrite, "Bob", 2),
+                    entry(AttributionType::Maintainer, "Nobody", 0),
+                ],
+            ),
+            (
+                "scp-jp",
+                "scp-002",
+                vec![entry(AttributionType::Translator, "alice", 1)],
+            ),
+        ];
+
+        for (site_slug, page_slug, entries) in writes {
+            storage
+                .update_page_attribution(
+                    str!(site_slug),
+                    str!(page_slug),
+                    Attribution(entries),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            storage.get_user_attribution(alice).await.unwrap(),
+            [
+                credit("scp-jp", "scp-002", AttributionType::Translator, "alice"),
+                credit(SITE, "scp-173", AttributionType::Author, "Alice"),
+            ],
+        );
+        assert_eq!(
+            storage.get_user_attribution(bob).await.unwrap(),
+            [credit(SITE, "scp-173", AttributionType::Rewrite, "Bob")],
+        );
+
+        // Updating a page replaces all of its entries for each user
+        storage
+            .update_page_attribution(
+                str!(SITE),
+                str!("scp-173"),
+                Attribution(vec![
+                    entry(AttributionType::Author, "Alice", 1),
+                    entry(AttributionType::Maintainer, "Alice", 1),
+                ]),
+                &change(),
+            )
+            .await
+            .unwrap();
+
+        assert!(storage.get_user_attribution(bob).await.unwrap().is_empty());
+        assert_eq!(
+            storage.get_user_attribution(alice).await.unwrap(),
+            [
+                credit("scp-jp", "scp-002", AttributionType::Translator, "alice"),
+                credit(SITE, "scp-173", AttributionType::Author, "Alice"),
+                credit(SITE, "scp-173", AttributionType::Maintainer, "Alice"),
+            ],
+        );
+
+        // Deleting a page removes it from the index
+        storage
+            .delete_page_attribution(str!(SITE), str!("scp-173"), &change())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.get_user_attribution(alice).await.unwrap(),
+            [credit(
+                "scp-jp",
+                "scp-002",
+                AttributionType::Translator,
+                "alice"
+            )],
+        );
+    }
+
+    #[tokio::test]
+    async fn translations() {
+        let storage = MemoryStorage::new();
+        let page = |site_slug: &str, page_slug: &str| PageReference {
+            site_slug: str!(site_slug),
+            page_slug: str!(page_slug),
+        };
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, None);
+        assert!(translations.translations.is_empty());
+
+        for (site_slug, page_slug) in [("scp-ru", "scp-173"), ("scp-jp", "scp-173")] {
+            storage
+                .set_translation_of(
+                    str!(site_slug),
+                    str!(page_slug),
+                    Some(page(SITE, "scp-173")),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Translations are listed in order of site and page
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, None);
+        assert_eq!(
+            translations.translations,
+            [page("scp-jp", "scp-173"), page("scp-ru", "scp-173")],
+        );
+
+        let translations = storage
+            .get_page_translations("scp-jp", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page(SITE, "scp-173")));
+        assert!(translations.translations.is_empty());
+
+        // Setting the original again replaces it
+        storage
+            .set_translation_of(
+                str!("scp-jp"),
+                str!("scp-173"),
+                Some(page(SITE, "scp-173-j")),
+            )
+            .await
+            .unwrap();
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translations, [page("scp-ru", "scp-173")]);
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173-j")
+            .await
+            .unwrap();
+        assert_eq!(translations.translations, [page("scp-jp", "scp-173")]);
+
+        // Clearing the link removes it from both pages, and clearing again does nothing
+        for _ in 0..2 {
+            storage
+                .set_translation_of(str!("scp-ru"), str!("scp-173"), None)
+                .await
+                .unwrap();
+        }
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert!(translations.translations.is_empty());
+
+        let translations = storage
+            .get_page_translations("scp-ru", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, None);
+    }
+
+    #[tokio::test]
+    async fn rename() {
+        let storage = MemoryStorage::new();
+        let page = |site_slug: &str, page_slug: &str| PageReference {
+            site_slug: str!(site_slug),
+            page_slug: str!(page_slug),
+        };
+        let user_pages = |user_id| {
+            let storage = &storage;
+            async move {
+                storage
+                    .get_user_attribution(NonZeroU32::new(user_id).unwrap())
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|credit| credit.page_slug)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let mut credited = attribution("Alice");
+        credited.0[0].user_id = NonZeroU32::new(1);
+
+        for (page_slug, attribution) in
+            [("scp-173", &credited), ("scp-002", &attribution("Bob"))]
+        {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!(page_slug),
+                    attribution.clone(),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        storage
+            .set_translation_of(
+                str!(SITE),
+                str!("scp-173"),
+                Some(page("scp-ru", "scp-173")),
+            )
+            .await
+            .unwrap();
+        storage
+            .set_translation_of(
+                str!("scp-jp"),
+                str!("scp-173"),
+                Some(page(SITE, "scp-173")),
+            )
+            .await
+            .unwrap();
+
+        let result = storage
+            .rename_page(str!(SITE), str!("scp-173"), str!("scp-173-new"), &change())
+            .await
+            .unwrap();
+        assert_eq!(result, RenameResult::Renamed);
+
+        // The old slug is an alias for the new one
+        for page_slug in ["scp-173", "scp-173-new"] {
+            let stored = storage.get_page_attribution(SITE, page_slug).await.unwrap();
+            assert_eq!(stored, Some(credited.clone()));
+        }
+
+        // Translation links and the user index follow the page
+        let translations = storage
+            .get_page_translations(SITE, "scp-173-new")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page("scp-ru", "scp-173")));
+        assert_eq!(translations.translations, [page("scp-jp", "scp-173")]);
+
+        let translations = storage
+            .get_page_translations("scp-jp", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page(SITE, "scp-173-new")));
+        assert_eq!(user_pages(1).await, ["scp-173-new"]);
+
+        // The move is recorded in the history of both slugs
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        let last = history.last().unwrap();
+        assert_eq!(
+            (&last.previous, &last.attribution),
+            (&Some(credited.clone()), &None),
+        );
+
+        let history = storage.get_page_history(SITE, "scp-173-new").await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].attribution, Some(credited.clone()));
+
+        // Renaming again moves the aliases too
+        let result = storage
+            .rename_page(
+                str!(SITE),
+                str!("scp-173-new"),
+                str!("scp-173-final"),
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, RenameResult::Renamed);
+
+        let stored = storage.get_page_attribution(SITE, "scp-173").await.unwrap();
+        assert_eq!(stored, Some(credited.clone()));
+        assert_eq!(user_pages(1).await, ["scp-173-final"]);
+
+        // Neither a page nor another page's alias can be taken
+        for new_page_slug in ["scp-173-final", "scp-173", "scp-173-new"] {
+            let result = storage
+                .rename_page(str!(SITE), str!("scp-002"), str!(new_page_slug), &change())
+                .await
+                .unwrap();
+            assert_eq!(
+                result,
+                RenameResult::ExistingPage,
+                "Renamed onto {new_page_slug}"
+            );
+        }
+
+        // Only a page itself can be renamed, not one of its aliases
+        for page_slug in ["scp-999", "scp-173"] {
+            let result = storage
+                .rename_page(str!(SITE), str!(page_slug), str!("scp-999-new"), &change())
+                .await
+                .unwrap();
+            assert_eq!(result, RenameResult::MissingPage, "Renamed {page_slug}");
+        }
+
+        // Renaming back to an old slug makes it a page again
+        let result = storage
+            .rename_page(
+                str!(SITE),
+                str!("scp-173-final"),
+                str!("scp-173"),
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, RenameResult::Renamed);
+
+        for page_slug in ["scp-173", "scp-173-new", "scp-173-final"] {
+            let stored = storage.get_page_attribution(SITE, page_slug).await.unwrap();
+            assert_eq!(stored, Some(credited.clone()));
+        }
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page("scp-ru", "scp-173")));
+        assert_eq!(translations.translations, [page("scp-jp", "scp-173")]);
+        assert_eq!(user_pages(1).await, ["scp-173"]);
+
+        // The other page was never changed
+        let stored = storage.get_page_attribution(SITE, "scp-002").await.unwrap();
+        assert_eq!(stored, Some(attribution("Bob")));
+    }
+}
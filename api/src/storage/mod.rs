@@ -0,0 +1,349 @@
+/*
+ * storage/mod.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+mod dynamo;
+mod memory;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use self::dynamo::DynamoStorage;
+pub use self::memory::MemoryStorage;
+
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStorage;
+
+use crate::apikey::ApiKey;
+use crate::batch::PageWrite;
+use crate::history::{Change, Revision};
+use crate::object::{Attribution, FullAttribution};
+use crate::password::PasswordType;
+use crate::ratelimit::LoginAttempts;
+use crate::translation::{PageReference, PageTranslations};
+use crate::user::UserAttribution;
+use crate::wikidot::WikidotTemplate;
+use async_trait::async_trait;
+use lambda_http::Error;
+use std::collections::BTreeMap;
+use std::env;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+/// The persistence layer used by the service.
+///
+/// Handlers only interact with data through this trait, so that the
+/// same request pipeline can run against any backing store.
+///
+/// Any attributions passed in for storage have already been validated
+/// and sorted by the caller. Entries without an ID are given one when
+/// written, using `Attribution::assign_ids` with the page's stored attribution.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Gets the attribution list for a page, if it has any.
+    ///
+    /// If there is no page with this slug, but it is an alias left by renaming
+    /// a page, then the attribution of the page it now refers to is returned.
+    async fn get_page_attribution(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<Attribution>, Error>;
+
+    /// Gets the attribution lists for many pages in a site at once.
+    ///
+    /// Every slug is in the returned map, with `None` if the page has no attribution.
+    /// Aliases are resolved in the same way as for a single page.
+    async fn get_pages_attribution(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, Option<Attribution>>, Error>;
+
+    /// Gets the page a slug refers to, if it is the old slug of a renamed page.
+    ///
+    /// Returns `None` if the slug is not an alias, or if there is a page stored under it.
+    async fn get_alias_target(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<String>, Error>;
+
+    /// Gets the number of the latest revision of a page, or 0 if it has none.
+    ///
+    /// Unlike reading attribution, this does not resolve aliases.
+    async fn get_page_revision(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<u64, Error>;
+
+    /// Gets the latest revisions of many pages in a site at once.
+    ///
+    /// Every slug is in the returned map, with 0 if the page has no revisions.
+    /// As with a single page, aliases are not resolved.
+    async fn get_page_revisions(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, u64>, Error>;
+
+    /// Sets the attribution list for a page, replacing any existing one.
+    ///
+    /// This also appends a revision to the page's history, atomically with the write.
+    /// If the change has an expected revision which is no longer the latest,
+    /// nothing is written.
+    async fn update_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        attribution: Attribution,
+        change: &Change,
+    ) -> Result<WriteResult, Error>;
+
+    /// Removes all attribution for a page.
+    ///
+    /// This also appends a revision to the page's history, atomically with the write.
+    /// If the change has an expected revision which is no longer the latest,
+    /// nothing is written.
+    async fn delete_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        change: &Change,
+    ) -> Result<WriteResult, Error>;
+
+    /// Sets or removes the attribution of several pages in a site, all in one transaction.
+    ///
+    /// Each page has a revision appended to its history, as with a single write. If any
+    /// page is no longer at its expected revision, nothing is written. The change's own
+    /// expected revision is not used.
+    async fn write_pages(
+        &self,
+        site_slug: String,
+        writes: Vec<PageWrite>,
+        change: &Change,
+    ) -> Result<BatchResult, Error>;
+
+    /// Moves a page's attribution to a new slug, leaving the old slug as an alias of it.
+    ///
+    /// Any aliases of the old slug are changed to refer to the new one, as are its
+    /// translation links, both to its original and from its translations. This also
+    /// appends a revision to the history of both slugs, atomically with the move.
+    async fn rename_page(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        new_page_slug: String,
+        change: &Change,
+    ) -> Result<RenameResult, Error>;
+
+    /// Gets all the revisions made to a page, oldest first.
+    async fn get_page_history(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Vec<Revision>, Error>;
+
+    /// Gets the attribution lists for every page in a site.
+    async fn get_site_attribution(
+        &self,
+        site_slug: &str,
+    ) -> Result<Vec<FullAttribution>, Error>;
+
+    /// Gets the attribution lists for up to `limit` pages in a site, in order of slug.
+    ///
+    /// If `start_after` is given, only pages after that slug are returned.
+    /// Also returns the slug of the last page read if there may be more pages to fetch.
+    async fn get_site_attribution_page(
+        &self,
+        site_slug: &str,
+        limit: u32,
+        start_after: Option<&str>,
+    ) -> Result<(Vec<FullAttribution>, Option<String>), Error>;
+
+    /// Gets the page a page was translated from, and the pages translated from it.
+    async fn get_page_translations(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<PageTranslations, Error>;
+
+    /// Sets the page a page was translated from, or removes it if `None`.
+    async fn set_translation_of(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        translation_of: Option<PageReference>,
+    ) -> Result<(), Error>;
+
+    /// Gets every credit for a user across all sites, in order of site and page.
+    async fn get_user_attribution(
+        &self,
+        user_id: NonZeroU32,
+    ) -> Result<Vec<UserAttribution>, Error>;
+
+    /// Gets the stored password of the given type for a site, if it has one.
+    async fn get_password(
+        &self,
+        site_slug: &str,
+        password_type: PasswordType,
+    ) -> Result<Option<String>, Error>;
+
+    /// Sets the stored password of the given type for a site.
+    async fn set_password(
+        &self,
+        site_slug: String,
+        password_type: PasswordType,
+        password: String,
+    ) -> Result<(), Error>;
+
+    /// Gets the Wikidot template for a site, if it has set one.
+    async fn get_wikidot_template(
+        &self,
+        site_slug: &str,
+    ) -> Result<Option<WikidotTemplate>, Error>;
+
+    /// Sets the Wikidot template for a site, replacing any existing one.
+    async fn set_wikidot_template(
+        &self,
+        site_slug: String,
+        template: WikidotTemplate,
+    ) -> Result<(), Error>;
+
+    /// Stores a newly-created API key for a site.
+    async fn create_api_key(
+        &self,
+        site_slug: String,
+        api_key: ApiKey,
+    ) -> Result<(), Error>;
+
+    /// Gets an API key for a site by its ID, if it exists.
+    async fn get_api_key(
+        &self,
+        site_slug: &str,
+        key_id: &str,
+    ) -> Result<Option<ApiKey>, Error>;
+
+    /// Gets all the API keys for a site.
+    async fn list_api_keys(&self, site_slug: &str) -> Result<Vec<ApiKey>, Error>;
+
+    /// Removes an API key for a site.
+    ///
+    /// Returns whether the key existed.
+    async fn delete_api_key(
+        &self,
+        site_slug: String,
+        key_id: String,
+    ) -> Result<bool, Error>;
+
+    /// Gets the failed credential checks recorded under a rate limiting key.
+    async fn get_login_attempts(&self, key: &str)
+        -> Result<Option<LoginAttempts>, Error>;
+
+    /// Atomically counts a failure under a rate limiting key, returning the
+    /// attempts recorded before it, if any.
+    ///
+    /// If the previous failures are stale, counting starts afresh.
+    async fn record_login_failure(
+        &self,
+        key: String,
+        timestamp: u64,
+    ) -> Result<Option<LoginAttempts>, Error>;
+
+    /// Takes back a failure counted at the given timestamp, since the
+    /// attempt turned out not to have failed.
+    ///
+    /// The key is removed once no failures are left under it. Otherwise, if
+    /// the last failure is still at that timestamp, it is restored to
+    /// `previous_failure`, the time of the failure counted before it.
+    async fn release_login_attempt(
+        &self,
+        key: String,
+        timestamp: u64,
+        previous_failure: Option<u64>,
+    ) -> Result<(), Error>;
+
+    /// Removes the failure count under a rate limiting key.
+    async fn clear_login_attempts(&self, key: String) -> Result<(), Error>;
+}
+
+/// The outcome of a write to a page.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WriteResult {
+    /// The write was made, and the page is now at this revision.
+    ///
+    /// Deleting a page which has no attribution does not create a revision.
+    Written(u64),
+
+    /// The page has been changed since the revision the write expected.
+    Conflict,
+}
+
+/// The outcome of a write to several pages at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchResult {
+    /// Every write was made, and each page is now at these revisions, in the same order.
+    Written(Vec<u64>),
+
+    /// A page has been changed since the revision its write expected.
+    Conflict,
+
+    /// The writes cannot all be made in a single transaction.
+    TooLarge,
+}
+
+/// The outcome of attempting to rename a page.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenameResult {
+    Renamed,
+
+    /// There is no page with the old slug to move.
+    ///
+    /// This includes if the old slug is only an alias.
+    MissingPage,
+
+    /// There is already a page with the new slug, or it is an alias of another page.
+    ExistingPage,
+
+    /// The page has too many aliases or translations to move in a single transaction.
+    TooLarge,
+}
+
+/// Creates the storage backend to be used for this process.
+///
+/// The backend is selected with the `ATTRIBUTION_STORAGE` environment variable:
+/// * `dynamo` (default) &mdash; AWS DynamoDB, configured from the environment.
+/// * `memory` &mdash; In-process storage, optionally seeded from the JSON fixture
+///   file given in `ATTRIBUTION_STORAGE_FIXTURE`.
+/// * `sqlite` &mdash; A SQLite database at the path given in `ATTRIBUTION_STORAGE_PATH`.
+///   Requires the `sqlite` feature.
+pub async fn connect() -> Result<Box<dyn Storage>, Error> {
+    let backend = env::var("ATTRIBUTION_STORAGE").unwrap_or_else(|_| str!("dynamo"));
+    info!(backend, "Connecting to storage backend");
+
+    match backend.as_str() {
+        "dynamo" => Ok(Box::new(DynamoStorage::connect().await)),
+        "memory" => match env::var_os("ATTRIBUTION_STORAGE_FIXTURE") {
+            Some(path) => Ok(Box::new(MemoryStorage::load(Path::new(&path))?)),
+            None => Ok(Box::new(MemoryStorage::new())),
+        },
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = env::var_os("ATTRIBUTION_STORAGE_PATH")
+                .ok_or("ATTRIBUTION_STORAGE_PATH must be set for SQLite storage")?;
+
+            Ok(Box::new(SqliteStorage::open(Path::new(&path))?))
+        }
+        _ => Err(format!("Unknown storage backend '{backend}'").into()),
+    }
+}
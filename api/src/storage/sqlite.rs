@@ -0,0 +1,2169 @@
+/*
+ * storage/sqlite.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+use super::{BatchResult, RenameResult, Storage, WriteResult};
+use crate::apikey::ApiKey;
+use crate::batch::PageWrite;
+use crate::history::{Change, Revision};
+use crate::object::{Attribution, FullAttribution};
+use crate::password::PasswordType;
+use crate::ratelimit::LoginAttempts;
+use crate::translation::{PageReference, PageTranslations};
+use crate::user::UserAttribution;
+use crate::wikidot::WikidotTemplate;
+use async_trait::async_trait;
+use lambda_http::Error;
+use rusqlite::types::Type;
+use rusqlite::{
+    params, Connection, OptionalExtension, Row, Transaction, TransactionBehavior,
+};
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::task;
+
+/// Schema migrations, applied in order.
+///
+/// The index of the last applied migration (plus one) is kept in
+/// SQLite's `user_version` pragma. Never modify an existing entry,
+/// only append new ones.
+const MIGRATIONS: &[&str] = &[
+    // 1: Initial schema, mirroring the DynamoDB tables
+    "
+    CREATE TABLE attribution_metadata (
+        site_slug TEXT NOT NULL,
+        page_slug TEXT NOT NULL,
+        attribution TEXT NOT NULL,
+
+        PRIMARY KEY (site_slug, page_slug)
+    );
+
+    CREATE TABLE attribution_metadata_password (
+        site_slug TEXT NOT NULL PRIMARY KEY,
+        regular TEXT,
+        admin TEXT
+    );
+    ",
+    // 2: API keys, and recording the last editor of each page
+    "
+    ALTER TABLE attribution_metadata ADD COLUMN updated_by TEXT;
+
+    CREATE TABLE attribution_metadata_api_key (
+        site_slug TEXT NOT NULL,
+        key_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        scope TEXT NOT NULL,
+        secret_hash TEXT NOT NULL,
+        created INTEGER NOT NULL,
+        expires INTEGER,
+
+        PRIMARY KEY (site_slug, key_id)
+    );
+    ",
+    // 3: Failed credential checks, for rate limiting
+    "
+    CREATE TABLE attribution_metadata_login_attempt (
+        attempt_key TEXT NOT NULL PRIMARY KEY,
+        failures INTEGER NOT NULL,
+        last_failure INTEGER NOT NULL
+    );
+    ",
+    // 4: Page revision history
+    "
+    CREATE TABLE attribution_metadata_history (
+        site_slug TEXT NOT NULL,
+        page_slug TEXT NOT NULL,
+        revision INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        actor TEXT NOT NULL,
+        summary TEXT,
+        previous TEXT,
+        attribution TEXT,
+
+        PRIMARY KEY (site_slug, page_slug, revision)
+    );
+    ",
+    // 5: Wikidot templates
+    "
+    CREATE TABLE attribution_metadata_template (
+        site_slug TEXT PRIMARY KEY,
+        template TEXT NOT NULL
+    );
+    ",
+    // 6: Index of the pages each user is credited on, filled from existing pages
+    "
+    CREATE TABLE attribution_metadata_user (
+        user_id INTEGER NOT NULL,
+        site_slug TEXT NOT NULL,
+        page_slug TEXT NOT NULL,
+        attribution_type TEXT NOT NULL,
+        user_name TEXT NOT NULL,
+        date TEXT
+    );
+
+    CREATE INDEX attribution_metadata_user_by_id
+        ON attribution_metadata_user (user_id);
+
+    CREATE INDEX attribution_metadata_user_by_page
+        ON attribution_metadata_user (site_slug, page_slug);
+
+    INSERT INTO attribution_metadata_user
+        (user_id, site_slug, page_slug, attribution_type, user_name, date)
+    SELECT
+        json_extract(entry.value, '$.user_id'),
+        page.site_slug,
+        page.page_slug,
+        json_extract(entry.value, '$.type'),
+        json_extract(entry.value, '$.user_name'),
+        json_extract(entry.value, '$.date')
+    FROM attribution_metadata AS page, json_each(page.attribution) AS entry
+    WHERE json_extract(entry.value, '$.user_id') IS NOT NULL
+    ORDER BY page.site_slug, page.page_slug, entry.key;
+    ",
+    // 7: Links from translations to their original pages
+    "
+    CREATE TABLE attribution_metadata_translation (
+        site_slug TEXT NOT NULL,
+        page_slug TEXT NOT NULL,
+        original_site_slug TEXT NOT NULL,
+        original_page_slug TEXT NOT NULL,
+
+        PRIMARY KEY (site_slug, page_slug)
+    );
+
+    CREATE INDEX attribution_metadata_translation_by_original
+        ON attribution_metadata_translation (original_site_slug, original_page_slug);
+    ",
+    // 8: Old slugs of renamed pages
+    "
+    CREATE TABLE attribution_metadata_alias (
+        site_slug TEXT NOT NULL,
+        alias_slug TEXT NOT NULL,
+        page_slug TEXT NOT NULL,
+
+        PRIMARY KEY (site_slug, alias_slug)
+    );
+    ",
+    // 9: Stable IDs for the entries of existing pages
+    "
+    UPDATE attribution_metadata
+    SET attribution = (
+        SELECT json_group_array(
+            CASE
+                WHEN json_type(value, '$.id') = 'text' THEN json(value)
+                ELSE json_set(value, '$.id', lower(hex(randomblob(8))))
+            END
+        )
+        FROM (SELECT value FROM json_each(attribution) ORDER BY key)
+    );
+    ",
+];
+
+/// Storage backend which persists data in a local SQLite database.
+///
+/// Intended for self-hosted deployments which are not running on AWS.
+/// Attribution lists are stored as JSON text.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    /// Opens (or creates) the database at the given path, bringing its schema up-to-date.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        info!("Opening SQLite database at {}", path.display());
+
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+
+        Ok(SqliteStorage {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs the given database operation on the blocking thread pool.
+    async fn run<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        task::spawn_blocking(move || {
+            let mut conn = conn.lock().expect("SQLite connection lock poisoned");
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+/// Sets or removes a page's attribution, appending a revision to its history in the same transaction.
+fn write_with_revision(
+    conn: &mut Connection,
+    site_slug: &str,
+    page_slug: &str,
+    attribution: Option<Attribution>,
+    change: &Change,
+) -> Result<WriteResult, Error> {
+    let txn = conn.transaction()?;
+    let result = write_page(&txn, site_slug, page_slug, attribution, change)?;
+    txn.commit()?;
+    Ok(result)
+}
+
+/// Sets or removes a page's attribution within a transaction.
+///
+/// This also appends a revision to the page's history, and updates the user index.
+fn write_page(
+    txn: &Transaction,
+    site_slug: &str,
+    page_slug: &str,
+    mut attribution: Option<Attribution>,
+    change: &Change,
+) -> Result<WriteResult, Error> {
+    let latest = get_latest_revision(txn, site_slug, page_slug)?;
+    if change
+        .expected_revision
+        .is_some_and(|expected| expected != latest)
+    {
+        return Ok(WriteResult::Conflict);
+    }
+
+    let previous = get_stored_page(txn, site_slug, page_slug)?;
+
+    // Deleting a nonexistent page changes nothing
+    if previous.is_none() && attribution.is_none() {
+        return Ok(WriteResult::Written(latest));
+    }
+
+    match attribution {
+        Some(ref mut attribution) => {
+            attribution.assign_ids(previous.as_ref());
+            txn.execute(
+                "INSERT INTO attribution_metadata
+                    (site_slug, page_slug, attribution, updated_by)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (site_slug, page_slug)
+                DO UPDATE SET
+                    attribution = excluded.attribution,
+                    updated_by = excluded.updated_by",
+                params![
+                    site_slug,
+                    page_slug,
+                    serde_json::to_string(attribution)?,
+                    str!(change.actor),
+                ],
+            )?;
+        }
+        None => {
+            txn.execute(
+                "DELETE FROM attribution_metadata
+                WHERE site_slug = ?1 AND page_slug = ?2",
+                params![site_slug, page_slug],
+            )?;
+        }
+    }
+
+    write_user_index(txn, site_slug, page_slug, attribution.as_ref())?;
+
+    let revision = Revision::new(latest + 1, previous, attribution, change);
+    txn.execute(
+        "INSERT INTO attribution_metadata_history
+            (site_slug, page_slug, revision, timestamp, actor, summary, previous, attribution)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            site_slug,
+            page_slug,
+            revision.revision,
+            revision.timestamp,
+            revision.actor,
+            revision.summary,
+            revision.previous.as_ref().map(serde_json::to_string).transpose()?,
+            revision.attribution.as_ref().map(serde_json::to_string).transpose()?,
+        ],
+    )?;
+
+    Ok(WriteResult::Written(revision.revision))
+}
+
+/// Gets the number of the latest revision of a page, or 0 if it has none.
+fn get_latest_revision(
+    conn: &Connection,
+    site_slug: &str,
+    page_slug: &str,
+) -> rusqlite::Result<u64> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(revision), 0) FROM attribution_metadata_history
+        WHERE site_slug = ?1 AND page_slug = ?2",
+        params![site_slug, page_slug],
+        |row| row.get(0),
+    )
+}
+
+/// Gets the attribution stored under exactly this page slug, ignoring aliases.
+fn get_stored_page(
+    conn: &Connection,
+    site_slug: &str,
+    page_slug: &str,
+) -> Result<Option<Attribution>, Error> {
+    let attribution: Option<String> = conn
+        .query_row(
+            "SELECT attribution FROM attribution_metadata
+            WHERE site_slug = ?1 AND page_slug = ?2",
+            params![site_slug, page_slug],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    match attribution {
+        Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+        None => Ok(None),
+    }
+}
+
+/// Gets a page's attribution, resolving the slug if it is an alias.
+fn get_page(
+    conn: &Connection,
+    site_slug: &str,
+    page_slug: &str,
+) -> Result<Option<Attribution>, Error> {
+    if let Some(attribution) = get_stored_page(conn, site_slug, page_slug)? {
+        return Ok(Some(attribution));
+    }
+
+    match get_alias(conn, site_slug, page_slug)? {
+        None => Ok(None),
+        Some(page_slug) => get_stored_page(conn, site_slug, &page_slug),
+    }
+}
+
+/// Gets the page an alias refers to, if the slug is one.
+fn get_alias(
+    conn: &Connection,
+    site_slug: &str,
+    page_slug: &str,
+) -> Result<Option<String>, Error> {
+    let page_slug = conn
+        .query_row(
+            "SELECT page_slug FROM attribution_metadata_alias
+            WHERE site_slug = ?1 AND alias_slug = ?2",
+            params![site_slug, page_slug],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(page_slug)
+}
+
+/// Replaces the user index entries for a page with those from its new attribution.
+fn write_user_index(
+    txn: &Transaction,
+    site_slug: &str,
+    page_slug: &str,
+    attribution: Option<&Attribution>,
+) -> rusqlite::Result<()> {
+    txn.execute(
+        "DELETE FROM attribution_metadata_user
+        WHERE site_slug = ?1 AND page_slug = ?2",
+        params![site_slug, page_slug],
+    )?;
+
+    let entries = attribution.map(|attribution| attribution.0.as_slice());
+    for entry in entries.unwrap_or_default() {
+        if let Some(user_id) = entry.user_id {
+            txn.execute(
+                "INSERT INTO attribution_metadata_user
+                    (user_id, site_slug, page_slug, attribution_type, user_name, date)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    user_id.get(),
+                    site_slug,
+                    page_slug,
+                    entry.attribution_type.field_name(),
+                    entry.user_name,
+                    entry.date,
+                ],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Error> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let version = index + 1;
+        info!("Applying SQLite schema migration {version}");
+
+        let txn = conn.transaction()?;
+        txn.execute_batch(migration)?;
+        txn.pragma_update(None, "user_version", version)?;
+        txn.commit()?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn get_page_attribution(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<Attribution>, Error> {
+        let site_slug = str!(site_slug);
+        let page_slug = str!(page_slug);
+
+        self.run(move |conn| get_page(conn, &site_slug, &page_slug))
+            .await
+    }
+
+    async fn get_pages_attribution(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, Option<Attribution>>, Error> {
+        let site_slug = str!(site_slug);
+        let page_slugs = page_slugs.to_vec();
+
+        self.run(move |conn| {
+            page_slugs
+                .into_iter()
+                .map(|page_slug| {
+                    let attribution = get_page(conn, &site_slug, &page_slug)?;
+                    Ok((page_slug, attribution))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn get_alias_target(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Option<String>, Error> {
+        let site_slug = str!(site_slug);
+        let page_slug = str!(page_slug);
+
+        self.run(move |conn| {
+            if get_stored_page(conn, &site_slug, &page_slug)?.is_some() {
+                return Ok(None);
+            }
+
+            get_alias(conn, &site_slug, &page_slug)
+        })
+        .await
+    }
+
+    async fn get_page_revision(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<u64, Error> {
+        let site_slug = str!(site_slug);
+        let page_slug = str!(page_slug);
+
+        self.run(move |conn| Ok(get_latest_revision(conn, &site_slug, &page_slug)?))
+            .await
+    }
+
+    async fn get_page_revisions(
+        &self,
+        site_slug: &str,
+        page_slugs: &[String],
+    ) -> Result<BTreeMap<String, u64>, Error> {
+        let site_slug = str!(site_slug);
+        let page_slugs = page_slugs.to_vec();
+
+        self.run(move |conn| {
+            let mut revisions = BTreeMap::new();
+            for page_slug in page_slugs {
+                let revision = get_latest_revision(conn, &site_slug, &page_slug)?;
+                revisions.insert(page_slug, revision);
+            }
+
+            Ok(revisions)
+        })
+        .await
+    }
+
+    async fn update_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        attribution: Attribution,
+        change: &Change,
+    ) -> Result<WriteResult, Error> {
+        let change = change.clone();
+
+        self.run(move |conn| {
+            write_with_revision(conn, &site_slug, &page_slug, Some(attribution), &change)
+        })
+        .await
+    }
+
+    async fn delete_page_attribution(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        change: &Change,
+    ) -> Result<WriteResult, Error> {
+        info!(actor = %change.actor, "Deleting page attribution item");
+        let change = change.clone();
+
+        self.run(move |conn| {
+            write_with_revision(conn, &site_slug, &page_slug, None, &change)
+        })
+        .await
+    }
+
+    async fn rename_page(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        new_page_slug: String,
+        change: &Change,
+    ) -> Result<RenameResult, Error> {
+        let change = change.clone();
+
+        self.run(move |conn| {
+            let txn = conn.transaction()?;
+
+            let attribution = match get_stored_page(&txn, &site_slug, &page_slug)? {
+                Some(attribution) => attribution,
+                None => return Ok(RenameResult::MissingPage),
+            };
+
+            if get_stored_page(&txn, &site_slug, &new_page_slug)?.is_some() {
+                return Ok(RenameResult::ExistingPage);
+            }
+
+            // Another page's old slug can't be taken, since reads of it would change pages
+            let alias_target = get_alias(&txn, &site_slug, &new_page_slug)?;
+            if alias_target.is_some_and(|target| target != page_slug) {
+                return Ok(RenameResult::ExistingPage);
+            }
+
+            write_page(&txn, &site_slug, &page_slug, None, &change)?;
+            write_page(&txn, &site_slug, &new_page_slug, Some(attribution), &change)?;
+
+            // The new slug is now a page, and any aliases of the old one follow it
+            txn.execute(
+                "DELETE FROM attribution_metadata_alias
+                WHERE site_slug = ?1 AND alias_slug = ?2",
+                params![site_slug, new_page_slug],
+            )?;
+
+            txn.execute(
+                "UPDATE attribution_metadata_alias
+                SET page_slug = ?3
+                WHERE site_slug = ?1 AND page_slug = ?2",
+                params![site_slug, page_slug, new_page_slug],
+            )?;
+
+            txn.execute(
+                "INSERT INTO attribution_metadata_alias (site_slug, alias_slug, page_slug)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (site_slug, alias_slug)
+                DO UPDATE SET page_slug = excluded.page_slug",
+                params![site_slug, page_slug, new_page_slug],
+            )?;
+
+            // Move the page's own translation link, replacing any left at the new slug
+            txn.execute(
+                "DELETE FROM attribution_metadata_translation
+                WHERE site_slug = ?1 AND page_slug = ?2",
+                params![site_slug, new_page_slug],
+            )?;
+
+            txn.execute(
+                "UPDATE attribution_metadata_translation
+                SET page_slug = ?3
+                WHERE site_slug = ?1 AND page_slug = ?2",
+                params![site_slug, page_slug, new_page_slug],
+            )?;
+
+            // Point the translations of this page at its new slug
+            txn.execute(
+                "UPDATE attribution_metadata_translation
+                SET original_page_slug = ?3
+                WHERE original_site_slug = ?1 AND original_page_slug = ?2",
+                params![site_slug, page_slug, new_page_slug],
+            )?;
+
+            txn.commit()?;
+            Ok(RenameResult::Renamed)
+        })
+        .await
+    }
+
+    async fn write_pages(
+        &self,
+        site_slug: String,
+        writes: Vec<PageWrite>,
+        change: &Change,
+    ) -> Result<BatchResult, Error> {
+        let change = change.clone();
+
+        self.run(move |conn| {
+            let txn = conn.transaction()?;
+            let mut revisions = Vec::new();
+
+            for PageWrite {
+                page_slug,
+                attribution,
+                expected_revision,
+            } in writes
+            {
+                let change = Change {
+                    expected_revision,
+                    ..change.clone()
+                };
+
+                match write_page(&txn, &site_slug, &page_slug, attribution, &change)? {
+                    WriteResult::Written(revision) => revisions.push(revision),
+
+                    // Dropping the transaction rolls back the pages already written
+                    WriteResult::Conflict => return Ok(BatchResult::Conflict),
+                }
+            }
+
+            txn.commit()?;
+            Ok(BatchResult::Written(revisions))
+        })
+        .await
+    }
+
+    async fn get_page_history(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<Vec<Revision>, Error> {
+        let site_slug = str!(site_slug);
+        let page_slug = str!(page_slug);
+
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT revision, timestamp, actor, summary, previous, attribution
+                FROM attribution_metadata_history
+                WHERE site_slug = ?1 AND page_slug = ?2
+                ORDER BY revision",
+            )?;
+
+            let rows = stmt.query_map(params![site_slug, page_slug], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            })?;
+
+            let mut revisions = Vec::new();
+            for row in rows {
+                let (revision, timestamp, actor, summary, previous, attribution) = row?;
+                let parse = |json: Option<String>| -> Result<Option<Attribution>, Error> {
+                    match json {
+                        Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+                        None => Ok(None),
+                    }
+                };
+
+                revisions.push(Revision {
+                    revision,
+                    timestamp,
+                    actor,
+                    summary,
+                    previous: parse(previous)?,
+                    attribution: parse(attribution)?,
+                });
+            }
+
+            Ok(revisions)
+        })
+        .await
+    }
+
+    async fn get_site_attribution(
+        &self,
+        site_slug: &str,
+    ) -> Result<Vec<FullAttribution>, Error> {
+        let site_slug = str!(site_slug);
+
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT page_slug, attribution FROM attribution_metadata
+                WHERE site_slug = ?1
+                ORDER BY page_slug",
+            )?;
+
+            let mut rows = stmt.query(params![site_slug])?;
+            let mut attributions = Vec::new();
+            while let Some(row) = rows.next()? {
+                let page_slug = row.get(0)?;
+                let json: String = row.get(1)?;
+                let attribution = serde_json::from_str(&json)?;
+
+                attributions.push(FullAttribution {
+                    page_slug,
+                    attribution,
+                });
+            }
+
+            Ok(attributions)
+        })
+        .await
+    }
+
+    async fn get_site_attribution_page(
+        &self,
+        site_slug: &str,
+        limit: u32,
+        start_after: Option<&str>,
+    ) -> Result<(Vec<FullAttribution>, Option<String>), Error> {
+        let site_slug = str!(site_slug);
+        let start_after = start_after.map(String::from);
+
+        self.run(move |conn| {
+            // Fetch one extra row to find out if there are any more pages
+            let mut stmt = conn.prepare(
+                "SELECT page_slug, attribution FROM attribution_metadata
+                WHERE site_slug = ?1 AND (?2 IS NULL OR page_slug > ?2)
+                ORDER BY page_slug
+                LIMIT ?3",
+            )?;
+
+            let mut rows = stmt.query(params![site_slug, start_after, limit + 1])?;
+            let mut attributions = Vec::new();
+            let mut has_more = false;
+            while let Some(row) = rows.next()? {
+                if attributions.len() == limit as usize {
+                    has_more = true;
+                    break;
+                }
+
+                let page_slug = row.get(0)?;
+                let json: String = row.get(1)?;
+                let attribution = serde_json::from_str(&json)?;
+
+                attributions.push(FullAttribution {
+                    page_slug,
+                    attribution,
+                });
+            }
+
+            let last_page = match has_more {
+                true => attributions
+                    .last()
+                    .map(|attribution| attribution.page_slug.clone()),
+                false => None,
+            };
+
+            Ok((attributions, last_page))
+        })
+        .await
+    }
+
+    async fn get_page_translations(
+        &self,
+        site_slug: &str,
+        page_slug: &str,
+    ) -> Result<PageTranslations, Error> {
+        let site_slug = str!(site_slug);
+        let page_slug = str!(page_slug);
+
+        self.run(move |conn| {
+            let translation_of = conn
+                .query_row(
+                    "SELECT original_site_slug, original_page_slug
+                    FROM attribution_metadata_translation
+                    WHERE site_slug = ?1 AND page_slug = ?2",
+                    params![site_slug, page_slug],
+                    convert_page_reference,
+                )
+                .optional()?;
+
+            let mut stmt = conn.prepare(
+                "SELECT site_slug, page_slug
+                FROM attribution_metadata_translation
+                WHERE original_site_slug = ?1 AND original_page_slug = ?2
+                ORDER BY site_slug, page_slug",
+            )?;
+
+            let translations = stmt
+                .query_map(params![site_slug, page_slug], convert_page_reference)?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok(PageTranslations {
+                translation_of,
+                translations,
+            })
+        })
+        .await
+    }
+
+    async fn set_translation_of(
+        &self,
+        site_slug: String,
+        page_slug: String,
+        translation_of: Option<PageReference>,
+    ) -> Result<(), Error> {
+        self.run(move |conn| {
+            match translation_of {
+                Some(original) => {
+                    conn.execute(
+                        "INSERT INTO attribution_metadata_translation
+                        (site_slug, page_slug, original_site_slug, original_page_slug)
+                    VALUES (?1, ?2, ?3, ?4)
+                    ON CONFLICT (site_slug, page_slug)
+                    DO UPDATE SET
+                        original_site_slug = excluded.original_site_slug,
+                        original_page_slug = excluded.original_page_slug",
+                        params![
+                            site_slug,
+                            page_slug,
+                            original.site_slug,
+                            original.page_slug,
+                        ],
+                    )?
+                }
+                None => conn.execute(
+                    "DELETE FROM attribution_metadata_translation
+                    WHERE site_slug = ?1 AND page_slug = ?2",
+                    params![site_slug, page_slug],
+                )?,
+            };
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_attribution(
+        &self,
+        user_id: NonZeroU32,
+    ) -> Result<Vec<UserAttribution>, Error> {
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT site_slug, page_slug, attribution_type, user_name, date
+                FROM attribution_metadata_user
+                WHERE user_id = ?1
+                ORDER BY site_slug, page_slug, rowid",
+            )?;
+
+            let attributions = stmt
+                .query_map(params![user_id.get()], convert_user_attribution)?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok(attributions)
+        })
+        .await
+    }
+
+    async fn get_password(
+        &self,
+        site_slug: &str,
+        password_type: PasswordType,
+    ) -> Result<Option<String>, Error> {
+        let site_slug = str!(site_slug);
+        let field = password_type.field_name();
+
+        self.run(move |conn| {
+            let password = conn
+                .query_row(
+                    &format!(
+                        "SELECT {field} FROM attribution_metadata_password
+                        WHERE site_slug = ?1",
+                    ),
+                    params![site_slug],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()?;
+
+            Ok(password.flatten())
+        })
+        .await
+    }
+
+    async fn set_password(
+        &self,
+        site_slug: String,
+        password_type: PasswordType,
+        password: String,
+    ) -> Result<(), Error> {
+        let field = password_type.field_name();
+
+        self.run(move |conn| {
+            conn.execute(
+                &format!(
+                    "INSERT INTO attribution_metadata_password (site_slug, {field})
+                    VALUES (?1, ?2)
+                    ON CONFLICT (site_slug)
+                    DO UPDATE SET {field} = excluded.{field}",
+                ),
+                params![site_slug, password],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_wikidot_template(
+        &self,
+        site_slug: &str,
+    ) -> Result<Option<WikidotTemplate>, Error> {
+        let site_slug = str!(site_slug);
+
+        self.run(move |conn| {
+            let json: Option<String> = conn
+                .query_row(
+                    "SELECT template FROM attribution_metadata_template
+                    WHERE site_slug = ?1",
+                    params![site_slug],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            match json {
+                Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn set_wikidot_template(
+        &self,
+        site_slug: String,
+        template: WikidotTemplate,
+    ) -> Result<(), Error> {
+        let json = serde_json::to_string(&template)?;
+
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO attribution_metadata_template (site_slug, template)
+                VALUES (?1, ?2)
+                ON CONFLICT (site_slug)
+                DO UPDATE SET template = excluded.template",
+                params![site_slug, json],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_api_key(
+        &self,
+        site_slug: String,
+        api_key: ApiKey,
+    ) -> Result<(), Error> {
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO attribution_metadata_api_key
+                    (site_slug, key_id, name, scope, secret_hash, created, expires)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    site_slug,
+                    api_key.key_id,
+                    api_key.name,
+                    api_key.scope.field_name(),
+                    api_key.secret_hash,
+                    api_key.created,
+                    api_key.expires,
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_api_key(
+        &self,
+        site_slug: &str,
+        key_id: &str,
+    ) -> Result<Option<ApiKey>, Error> {
+        let site_slug = str!(site_slug);
+        let key_id = str!(key_id);
+
+        self.run(move |conn| {
+            let api_key = conn
+                .query_row(
+                    "SELECT key_id, name, scope, secret_hash, created, expires
+                    FROM attribution_metadata_api_key
+                    WHERE site_slug = ?1 AND key_id = ?2",
+                    params![site_slug, key_id],
+                    convert_api_key,
+                )
+                .optional()?;
+
+            Ok(api_key)
+        })
+        .await
+    }
+
+    async fn list_api_keys(&self, site_slug: &str) -> Result<Vec<ApiKey>, Error> {
+        let site_slug = str!(site_slug);
+
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT key_id, name, scope, secret_hash, created, expires
+                FROM attribution_metadata_api_key
+                WHERE site_slug = ?1
+                ORDER BY key_id",
+            )?;
+
+            let api_keys = stmt
+                .query_map(params![site_slug], convert_api_key)?
+                .collect::<Result<_, _>>()?;
+
+            Ok(api_keys)
+        })
+        .await
+    }
+
+    async fn delete_api_key(
+        &self,
+        site_slug: String,
+        key_id: String,
+    ) -> Result<bool, Error> {
+        self.run(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM attribution_metadata_api_key
+                WHERE site_slug = ?1 AND key_id = ?2",
+                params![site_slug, key_id],
+            )?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn get_login_attempts(
+        &self,
+        key: &str,
+    ) -> Result<Option<LoginAttempts>, Error> {
+        let key = str!(key);
+
+        self.run(move |conn| {
+            let attempts = conn
+                .query_row(
+                    "SELECT failures, last_failure FROM attribution_metadata_login_attempt
+                    WHERE attempt_key = ?1",
+                    params![key],
+                    convert_login_attempts,
+                )
+                .optional()?;
+
+            Ok(attempts)
+        })
+        .await
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: String,
+        timestamp: u64,
+    ) -> Result<Option<LoginAttempts>, Error> {
+        self.run(move |conn| {
+            // Lock the database first, so other processes cannot count in between
+            let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let previous = txn
+                .query_row(
+                    "SELECT failures, last_failure FROM attribution_metadata_login_attempt
+                    WHERE attempt_key = ?1",
+                    params![key],
+                    convert_login_attempts,
+                )
+                .optional()?;
+
+            let failures = match previous {
+                Some(attempts) if !attempts.is_stale(timestamp) => attempts.failures + 1,
+                _ => 1,
+            };
+
+            txn.execute(
+                "INSERT OR REPLACE INTO attribution_metadata_login_attempt
+                    (attempt_key, failures, last_failure)
+                VALUES (?1, ?2, ?3)",
+                params![key, failures, timestamp],
+            )?;
+
+            txn.commit()?;
+            Ok(previous)
+        })
+        .await
+    }
+
+    async fn release_login_attempt(
+        &self,
+        key: String,
+        timestamp: u64,
+        previous_failure: Option<u64>,
+    ) -> Result<(), Error> {
+        self.run(move |conn| {
+            let txn = conn.transaction()?;
+            txn.execute(
+                "UPDATE attribution_metadata_login_attempt
+                SET
+                    failures = failures - 1,
+                    last_failure = CASE
+                        WHEN last_failure = ?2 THEN coalesce(?3, last_failure)
+                        ELSE last_failure
+                    END
+                WHERE attempt_key = ?1 AND failures > 0",
+                params![key, timestamp, previous_failure],
+            )?;
+
+            txn.execute(
+                "DELETE FROM attribution_metadata_login_attempt
+                WHERE attempt_key = ?1 AND failures <= 0",
+                params![key],
+            )?;
+
+            txn.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clear_login_attempts(&self, key: String) -> Result<(), Error> {
+        self.run(move |conn| {
+            conn.execute(
+                "DELETE FROM attribution_metadata_login_attempt WHERE attempt_key = ?1",
+                params![key],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn convert_api_key(row: &Row) -> rusqlite::Result<ApiKey> {
+    let scope: String = row.get(2)?;
+    let scope = scope.as_str().try_into().map_err(|_| {
+        invalid_column(
+            2,
+            format!("Column 'scope' not valid ApiKeyScope enum value: {scope}"),
+        )
+    })?;
+
+    Ok(ApiKey {
+        key_id: row.get(0)?,
+        name: row.get(1)?,
+        scope,
+        secret_hash: row.get(3)?,
+        created: row.get(4)?,
+        expires: row.get(5)?,
+    })
+}
+
+fn convert_page_reference(row: &Row) -> rusqlite::Result<PageReference> {
+    Ok(PageReference {
+        site_slug: row.get(0)?,
+        page_slug: row.get(1)?,
+    })
+}
+
+fn convert_user_attribution(row: &Row) -> rusqlite::Result<UserAttribution> {
+    let attribution_type: String = row.get(2)?;
+    let attribution_type = attribution_type.as_str().try_into().map_err(|_| {
+        invalid_column(
+            2,
+            format!(
+                "Column 'attribution_type' not valid AttributionType enum value: {attribution_type}",
+            ),
+        )
+    })?;
+
+    Ok(UserAttribution {
+        site_slug: row.get(0)?,
+        page_slug: row.get(1)?,
+        attribution_type,
+        user_name: row.get(3)?,
+        date: row.get(4)?,
+    })
+}
+
+/// Builds the error for a text column which does not hold a valid value.
+fn invalid_column(index: usize, message: String) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, message.into())
+}
+
+fn convert_login_attempts(row: &Row) -> rusqlite::Result<LoginAttempts> {
+    Ok(LoginAttempts {
+        failures: row.get(0)?,
+        last_failure: row.get(1)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apikey::ApiKeyScope;
+    use crate::auth::Actor;
+    use crate::object::{AttributionEntry, AttributionType};
+    use crate::ratelimit::ATTEMPT_WINDOW;
+
+    const SITE: &str = "scp-wiki";
+
+    fn open() -> SqliteStorage {
+        SqliteStorage::open(Path::new(":memory:")).unwrap()
+    }
+
+    /// Creates an attribution list whose entry already has an ID, so it is stored as is.
+    fn attribution(user_name: &str) -> Attribution {
+        Attribution(vec![AttributionEntry {
+            id: Some(user_name.to_lowercase()),
+            attribution_type: AttributionType::Author,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }])
+    }
+
+    fn change() -> Change {
+        Change::new(
+            Actor::Password(PasswordType::Regular),
+            Some(str!("Testing")),
+        )
+    }
+
+    #[test]
+    fn migrations() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+
+        // Migrating again does nothing
+        migrate(&mut conn).unwrap();
+    }
+
+    #[test]
+    fn migrate_entry_ids() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..8] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 8).unwrap();
+
+        // A page stored before entries had IDs, with one given since
+        let mut attribution = attribution("Alice");
+        attribution.0[0].id = None;
+        attribution.0.push(AttributionEntry {
+            id: Some(str!("0123456789abcdef")),
+            attribution_type: AttributionType::Translator,
+            user_name: str!("Bob"),
+            user_id: None,
+            date: None,
+        });
+
+        conn.execute(
+            "INSERT INTO attribution_metadata (site_slug, page_slug, attribution)
+            VALUES (?, ?, ?)",
+            params![
+                SITE,
+                "scp-173",
+                serde_json::to_string(&attribution).unwrap()
+            ],
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let stored = get_stored_page(&conn, SITE, "scp-173").unwrap().unwrap();
+        assert!(attribution.same_entries(&stored));
+        assert_eq!(stored.0[0].id.as_ref().map(String::len), Some(16));
+        assert_eq!(stored.0[1].id.as_deref(), Some("0123456789abcdef"));
+    }
+
+    #[tokio::test]
+    async fn pages() {
+        let storage = open();
+        assert_eq!(
+            storage.get_page_attribution(SITE, "scp-173").await.unwrap(),
+            None
+        );
+
+        for user_name in ["Alice", "Bob"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!("scp-173"),
+                    attribution(user_name),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let stored = storage.get_page_attribution(SITE, "scp-173").await.unwrap();
+        assert_eq!(stored, Some(attribution("Bob")));
+
+        let stored = storage
+            .get_page_attribution("scp-jp", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(stored, None);
+
+        storage
+            .delete_page_attribution(str!(SITE), str!("scp-173"), &change())
+            .await
+            .unwrap();
+
+        let stored = storage.get_page_attribution(SITE, "scp-173").await.unwrap();
+        assert_eq!(stored, None);
+    }
+
+    #[tokio::test]
+    async fn entry_ids() {
+        let storage = open();
+        let write = |attribution: Attribution| {
+            let storage = &storage;
+            async move {
+                storage
+                    .update_page_attribution(
+                        str!(SITE),
+                        str!("scp-173"),
+                        attribution,
+                        &change(),
+                    )
+                    .await
+                    .unwrap();
+
+                storage
+                    .get_page_attribution(SITE, "scp-173")
+                    .await
+                    .unwrap()
+                    .unwrap()
+            }
+        };
+
+        // Entries are given IDs when first stored, and keep them when written without
+        let mut without_ids = attribution("Alice");
+        without_ids.0[0].id = None;
+        let stored = write(without_ids.clone()).await;
+        let id = stored.0[0].id.clone();
+        assert!(id.is_some());
+        assert_eq!(write(without_ids).await.0[0].id, id);
+
+        // The history records the IDs stored
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        assert_eq!(history[1].previous, Some(stored.clone()));
+        assert_eq!(history[1].attribution, Some(stored));
+
+        // Given IDs are kept as they are
+        assert_eq!(write(attribution("Bob")).await, attribution("Bob"));
+    }
+
+    #[tokio::test]
+    async fn site_in_order() {
+        let storage = open();
+        for page_slug in ["scp-173", "scp-002", "scp-1000"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!(page_slug),
+                    attribution("Alice"),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let page_slugs: Vec<_> = storage
+            .get_site_attribution(SITE)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|full| full.page_slug)
+            .collect();
+
+        assert_eq!(page_slugs, ["scp-002", "scp-1000", "scp-173"]);
+    }
+
+    #[tokio::test]
+    async fn pages_batch() {
+        let storage = open();
+        for page_slug in ["scp-173", "scp-096"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!(page_slug),
+                    attribution("Alice"),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Missing pages are included, but not pages which weren't asked for
+        let page_slugs = [str!("scp-173"), str!("scp-999")];
+        let stored = storage
+            .get_pages_attribution(SITE, &page_slugs)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored,
+            btreemap! {
+                str!("scp-173") => Some(attribution("Alice")),
+                str!("scp-999") => None,
+            },
+        );
+
+        let stored = storage
+            .get_pages_attribution("scp-jp", &page_slugs)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored,
+            btreemap! {
+                str!("scp-173") => None,
+                str!("scp-999") => None,
+            },
+        );
+
+        let stored = storage.get_pages_attribution(SITE, &[]).await.unwrap();
+        assert!(stored.is_empty());
+    }
+
+    #[tokio::test]
+    async fn passwords() {
+        let storage = open();
+        let get = |password_type| storage.get_password(SITE, password_type);
+
+        assert_eq!(get(PasswordType::Admin).await.unwrap(), None);
+        storage
+            .set_password(str!(SITE), PasswordType::Admin, str!("hunter2"))
+            .await
+            .unwrap();
+        storage
+            .set_password(str!(SITE), PasswordType::Regular, str!("letmein"))
+            .await
+            .unwrap();
+
+        // Setting one password leaves the other
+        let password = get(PasswordType::Admin).await.unwrap();
+        assert_eq!(password.as_deref(), Some("hunter2"));
+        let password = get(PasswordType::Regular).await.unwrap();
+        assert_eq!(password.as_deref(), Some("letmein"));
+    }
+
+    #[tokio::test]
+    async fn api_keys() {
+        let storage = open();
+        let api_key = ApiKey {
+            key_id: str!("0123abcd"),
+            name: str!("bot"),
+            scope: ApiKeyScope::ReadOnly,
+            secret_hash: str!("hash"),
+            created: 1000,
+            expires: None,
+        };
+
+        storage
+            .create_api_key(str!(SITE), api_key.clone())
+            .await
+            .unwrap();
+
+        let stored = storage
+            .get_api_key(SITE, "0123abcd")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.scope, ApiKeyScope::ReadOnly);
+        assert_eq!(stored.secret_hash, api_key.secret_hash);
+
+        // An unknown scope is an error, not a panic
+        storage
+            .run(|conn| {
+                conn.execute(
+                    "UPDATE attribution_metadata_api_key SET scope = 'superuser'",
+                    [],
+                )?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert!(storage.get_api_key(SITE, "0123abcd").await.is_err());
+        assert!(storage.list_api_keys(SITE).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn login_attempts() {
+        const KEY: &str = "client:scp-wiki:192.0.2.1";
+
+        let storage = open();
+        let record = |timestamp| storage.record_login_failure(str!(KEY), timestamp);
+        let get = || storage.get_login_attempts(KEY);
+
+        assert_eq!(record(1000).await.unwrap(), None);
+        assert_eq!(
+            record(1005).await.unwrap(),
+            Some(LoginAttempts {
+                failures: 1,
+                last_failure: 1000,
+            }),
+        );
+        assert_eq!(
+            get().await.unwrap(),
+            Some(LoginAttempts {
+                failures: 2,
+                last_failure: 1005,
+            }),
+        );
+
+        // Releasing restores the failure before it
+        storage
+            .release_login_attempt(str!(KEY), 1005, Some(1000))
+            .await
+            .unwrap();
+        assert_eq!(
+            get().await.unwrap(),
+            Some(LoginAttempts {
+                failures: 1,
+                last_failure: 1000,
+            }),
+        );
+
+        // Stale failures are counted afresh, but still returned
+        let later = 1000 + ATTEMPT_WINDOW + 1;
+        assert_eq!(
+            record(later).await.unwrap(),
+            Some(LoginAttempts {
+                failures: 1,
+                last_failure: 1000,
+            }),
+        );
+        assert_eq!(
+            get().await.unwrap(),
+            Some(LoginAttempts {
+                failures: 1,
+                last_failure: later,
+            }),
+        );
+
+        // Nothing left, so the key is removed
+        storage
+            .release_login_attempt(str!(KEY), later, None)
+            .await
+            .unwrap();
+        assert_eq!(get().await.unwrap(), None);
+
+        record(later).await.unwrap();
+        storage.clear_login_attempts(str!(KEY)).await.unwrap();
+        assert_eq!(get().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn history() {
+        let storage = open();
+        for user_name in ["Alice", "Bob"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!("scp-173"),
+                    attribution(user_name),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Deleting twice only records one revision
+        for _ in 0..2 {
+            storage
+                .delete_page_attribution(str!(SITE), str!("scp-173"), &change())
+                .await
+                .unwrap();
+        }
+
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        let revisions: Vec<_> =
+            history.iter().map(|revision| revision.revision).collect();
+        assert_eq!(revisions, vec![1, 2, 3]);
+
+        assert_eq!(history[0].previous, None);
+        assert_eq!(history[0].attribution, Some(attribution("Alice")));
+        assert_eq!(history[1].previous, Some(attribution("Alice")));
+        assert_eq!(history[1].attribution, Some(attribution("Bob")));
+        assert_eq!(history[2].previous, Some(attribution("Bob")));
+        assert_eq!(history[2].attribution, None);
+
+        assert_eq!(history[0].actor, "password:regular");
+        assert_eq!(history[0].summary.as_deref(), Some("Testing"));
+
+        let history = storage.get_page_history(SITE, "scp-001").await.unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn page_revisions() {
+        let storage = open();
+        for user_name in ["Alice", "Bob"] {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!("scp-173"),
+                    attribution(user_name),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        storage
+            .update_page_attribution(
+                str!("scp-jp"),
+                str!("scp-096"),
+                attribution("Alice"),
+                &change(),
+            )
+            .await
+            .unwrap();
+
+        let page_slugs = [str!("scp-173"), str!("scp-096")];
+        let revisions = storage.get_page_revisions(SITE, &page_slugs).await.unwrap();
+
+        assert_eq!(
+            revisions,
+            btreemap! {
+                str!("scp-173") => 2,
+                str!("scp-096") => 0,
+            },
+        );
+
+        let revisions = storage.get_page_revisions(SITE, &[]).await.unwrap();
+        assert!(revisions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expected_revision() {
+        let storage = open();
+        let write = |user_name: Option<&str>, expected_revision| {
+            let storage = &storage;
+            let mut change = change();
+            change.expected_revision = expected_revision;
+            let user_name = user_name.map(attribution);
+            async move {
+                match user_name {
+                    Some(attribution) => storage
+                        .update_page_attribution(
+                            str!(SITE),
+                            str!("scp-173"),
+                            attribution,
+                            &change,
+                        )
+                        .await
+                        .unwrap(),
+                    None => storage
+                        .delete_page_attribution(str!(SITE), str!("scp-173"), &change)
+                        .await
+                        .unwrap(),
+                }
+            }
+        };
+
+        // A page with no revisions is at revision 0
+        assert_eq!(write(Some("Alice"), Some(1)).await, WriteResult::Conflict);
+        assert_eq!(write(Some("Alice"), Some(0)).await, WriteResult::Written(1));
+        assert_eq!(write(Some("Bob"), None).await, WriteResult::Written(2));
+
+        // A stale revision writes nothing
+        assert_eq!(write(Some("Carol"), Some(1)).await, WriteResult::Conflict);
+        assert_eq!(write(None, Some(1)).await, WriteResult::Conflict);
+        assert_eq!(storage.get_page_revision(SITE, "scp-173").await.unwrap(), 2);
+        assert_eq!(
+            storage.get_page_attribution(SITE, "scp-173").await.unwrap(),
+            Some(attribution("Bob")),
+        );
+
+        assert_eq!(write(None, Some(2)).await, WriteResult::Written(3));
+        assert_eq!(
+            storage
+                .get_page_history(SITE, "scp-173")
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn write_pages() {
+        let storage = open();
+        let write =
+            |page_slug: &str, user_name: Option<&str>, expected_revision| PageWrite {
+                page_slug: str!(page_slug),
+                attribution: user_name.map(|user_name| {
+                    let mut attribution = attribution(user_name);
+                    attribution.0[0].user_id = NonZeroU32::new(1);
+                    attribution
+                }),
+                expected_revision,
+            };
+        let users = |storage| async move {
+            let storage: &dyn Storage = storage;
+            let user_id = NonZeroU32::new(1).unwrap();
+            let credits = storage.get_user_attribution(user_id).await.unwrap();
+            credits
+                .into_iter()
+                .map(|credit| (credit.page_slug, credit.user_name))
+                .collect::<Vec<_>>()
+        };
+
+        let result = storage
+            .write_pages(
+                str!(SITE),
+                vec![
+                    write("scp-173", Some("Alice"), Some(0)),
+                    write("scp-001", Some("Bob"), None),
+                    // Deleting a page with no attribution changes nothing
+                    write("scp-002", None, None),
+                ],
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, BatchResult::Written(vec![1, 1, 0]));
+        assert_eq!(
+            users(&storage).await,
+            [
+                (str!("scp-001"), str!("Bob")),
+                (str!("scp-173"), str!("Alice"))
+            ],
+        );
+
+        // If any page conflicts, nothing is written
+        let result = storage
+            .write_pages(
+                str!(SITE),
+                vec![
+                    write("scp-173", None, Some(1)),
+                    write("scp-002", Some("Carol"), None),
+                    write("scp-001", Some("Dave"), Some(0)),
+                ],
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, BatchResult::Conflict);
+
+        for (page_slug, revision) in [("scp-173", 1), ("scp-001", 1), ("scp-002", 0)] {
+            let latest = storage.get_page_revision(SITE, page_slug).await.unwrap();
+            assert_eq!(latest, revision, "{page_slug} was written");
+        }
+        assert_eq!(
+            users(&storage).await,
+            [
+                (str!("scp-001"), str!("Bob")),
+                (str!("scp-173"), str!("Alice"))
+            ],
+        );
+
+        let result = storage
+            .write_pages(
+                str!(SITE),
+                vec![
+                    write("scp-173", None, Some(1)),
+                    write("scp-002", Some("Carol"), None),
+                ],
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, BatchResult::Written(vec![2, 1]));
+        assert_eq!(
+            users(&storage).await,
+            [
+                (str!("scp-001"), str!("Bob")),
+                (str!("scp-002"), str!("Carol"))
+            ],
+        );
+
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        assert_eq!(
+            history[1].previous,
+            Some(history[0].attribution.clone().unwrap())
+        );
+        assert_eq!(history[1].attribution, None);
+    }
+
+    #[tokio::test]
+    async fn user_index() {
+        let storage = open();
+        let entry = |attribution_type, user_name: &str, user_id| AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: str!(user_name),
+            user_id: NonZeroU32::new(user_id),
+            date: None,
+        };
+        let credit =
+            |site_slug: &str, page_slug: &str, attribution_type, user_name: &str| {
+                UserAttribution {
+                    site_slug: str!(site_slug),
+                    page_slug: str!(page_slug),
+                    attribution_type,
+                    user_name: str!(user_name),
+                    date: None,
+                }
+            };
+        let alice = NonZeroU32::new(1).unwrap();
+        let bob = NonZeroU32::new(2).unwrap();
+
+        let writes = [
+            (
+                SITE,
+                "scp-173",
+                vec![
+                    entry(AttributionType::Author, "Alice", 1),
+                    entry(AttributionType::Rewrite, "Bob", 2),
+                    entry(AttributionType::Maintainer, "Nobody", 0),
+                ],
+            ),
+            (
+                "scp-jp",
+                "scp-002",
+                vec![entry(AttributionType::Translator, "alice", 1)],
+            ),
+        ];
+
+        for (site_slug, page_slug, entries) in writes {
+            storage
+                .update_page_attribution(
+                    str!(site_slug),
+                    str!(page_slug),
+                    Attribution(entries),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            storage.get_user_attribution(alice).await.unwrap(),
+            [
+                credit("scp-jp", "scp-002", AttributionType::Translator, "alice"),
+                credit(SITE, "scp-173", AttributionType::Author, "Alice"),
+            ],
+        );
+        assert_eq!(
+            storage.get_user_attribution(bob).await.unwrap(),
+            [credit(SITE, "scp-173", AttributionType::Rewrite, "Bob")],
+        );
+
+        // Updating a page replaces all of its entries for each user
+        storage
+            .update_page_attribution(
+                str!(SITE),
+                str!("scp-173"),
+                Attribution(vec![
+                    entry(AttributionType::Author, "Alice", 1),
+                    entry(AttributionType::Maintainer, "Alice", 1),
+                ]),
+                &change(),
+            )
+            .await
+            .unwrap();
+
+        assert!(storage.get_user_attribution(bob).await.unwrap().is_empty());
+        assert_eq!(
+            storage.get_user_attribution(alice).await.unwrap(),
+            [
+                credit("scp-jp", "scp-002", AttributionType::Translator, "alice"),
+                credit(SITE, "scp-173", AttributionType::Author, "Alice"),
+                credit(SITE, "scp-173", AttributionType::Maintainer, "Alice"),
+            ],
+        );
+
+        // Deleting a page removes it from the index
+        storage
+            .delete_page_attribution(str!(SITE), str!("scp-173"), &change())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.get_user_attribution(alice).await.unwrap(),
+            [credit(
+                "scp-jp",
+                "scp-002",
+                AttributionType::Translator,
+                "alice"
+            )],
+        );
+    }
+
+    #[tokio::test]
+    async fn translations() {
+        let storage = open();
+        let page = |site_slug: &str, page_slug: &str| PageReference {
+            site_slug: str!(site_slug),
+            page_slug: str!(page_slug),
+        };
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, None);
+        assert!(translations.translations.is_empty());
+
+        for (site_slug, page_slug) in [("scp-ru", "scp-173"), ("scp-jp", "scp-173")] {
+            storage
+                .set_translation_of(
+                    str!(site_slug),
+                    str!(page_slug),
+                    Some(page(SITE, "scp-173")),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Translations are listed in order of site and page
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, None);
+        assert_eq!(
+            translations.translations,
+            [page("scp-jp", "scp-173"), page("scp-ru", "scp-173")],
+        );
+
+        let translations = storage
+            .get_page_translations("scp-jp", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page(SITE, "scp-173")));
+        assert!(translations.translations.is_empty());
+
+        // Setting the original again replaces it
+        storage
+            .set_translation_of(
+                str!("scp-jp"),
+                str!("scp-173"),
+                Some(page(SITE, "scp-173-j")),
+            )
+            .await
+            .unwrap();
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translations, [page("scp-ru", "scp-173")]);
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173-j")
+            .await
+            .unwrap();
+        assert_eq!(translations.translations, [page("scp-jp", "scp-173")]);
+
+        // Clearing the link removes it from both pages, and clearing again does nothing
+        for _ in 0..2 {
+            storage
+                .set_translation_of(str!("scp-ru"), str!("scp-173"), None)
+                .await
+                .unwrap();
+        }
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert!(translations.translations.is_empty());
+
+        let translations = storage
+            .get_page_translations("scp-ru", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, None);
+    }
+
+    #[tokio::test]
+    async fn rename() {
+        let storage = open();
+        let page = |site_slug: &str, page_slug: &str| PageReference {
+            site_slug: str!(site_slug),
+            page_slug: str!(page_slug),
+        };
+        let user_pages = |user_id| {
+            let storage = &storage;
+            async move {
+                storage
+                    .get_user_attribution(NonZeroU32::new(user_id).unwrap())
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|credit| credit.page_slug)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let mut credited = attribution("Alice");
+        credited.0[0].user_id = NonZeroU32::new(1);
+
+        for (page_slug, attribution) in
+            [("scp-173", &credited), ("scp-002", &attribution("Bob"))]
+        {
+            storage
+                .update_page_attribution(
+                    str!(SITE),
+                    str!(page_slug),
+                    attribution.clone(),
+                    &change(),
+                )
+                .await
+                .unwrap();
+        }
+
+        storage
+            .set_translation_of(
+                str!(SITE),
+                str!("scp-173"),
+                Some(page("scp-ru", "scp-173")),
+            )
+            .await
+            .unwrap();
+        storage
+            .set_translation_of(
+                str!("scp-jp"),
+                str!("scp-173"),
+                Some(page(SITE, "scp-173")),
+            )
+            .await
+            .unwrap();
+
+        let result = storage
+            .rename_page(str!(SITE), str!("scp-173"), str!("scp-173-new"), &change())
+            .await
+            .unwrap();
+        assert_eq!(result, RenameResult::Renamed);
+
+        // The old slug is an alias for the new one
+        for page_slug in ["scp-173", "scp-173-new"] {
+            let stored = storage.get_page_attribution(SITE, page_slug).await.unwrap();
+            assert_eq!(stored, Some(credited.clone()));
+        }
+
+        // Translation links and the user index follow the page
+        let translations = storage
+            .get_page_translations(SITE, "scp-173-new")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page("scp-ru", "scp-173")));
+        assert_eq!(translations.translations, [page("scp-jp", "scp-173")]);
+
+        let translations = storage
+            .get_page_translations("scp-jp", "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page(SITE, "scp-173-new")));
+        assert_eq!(user_pages(1).await, ["scp-173-new"]);
+
+        // The move is recorded in the history of both slugs
+        let history = storage.get_page_history(SITE, "scp-173").await.unwrap();
+        let last = history.last().unwrap();
+        assert_eq!(
+            (&last.previous, &last.attribution),
+            (&Some(credited.clone()), &None),
+        );
+
+        let history = storage.get_page_history(SITE, "scp-173-new").await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].attribution, Some(credited.clone()));
+
+        // Renaming again moves the aliases too
+        let result = storage
+            .rename_page(
+                str!(SITE),
+                str!("scp-173-new"),
+                str!("scp-173-final"),
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, RenameResult::Renamed);
+
+        let stored = storage.get_page_attribution(SITE, "scp-173").await.unwrap();
+        assert_eq!(stored, Some(credited.clone()));
+        assert_eq!(user_pages(1).await, ["scp-173-final"]);
+
+        // Neither a page nor another page's alias can be taken
+        for new_page_slug in ["scp-173-final", "scp-173", "scp-173-new"] {
+            let result = storage
+                .rename_page(str!(SITE), str!("scp-002"), str!(new_page_slug), &change())
+                .await
+                .unwrap();
+            assert_eq!(
+                result,
+                RenameResult::ExistingPage,
+                "Renamed onto {new_page_slug}"
+            );
+        }
+
+        // Only a page itself can be renamed, not one of its aliases
+        for page_slug in ["scp-999", "scp-173"] {
+            let result = storage
+                .rename_page(str!(SITE), str!(page_slug), str!("scp-999-new"), &change())
+                .await
+                .unwrap();
+            assert_eq!(result, RenameResult::MissingPage, "Renamed {page_slug}");
+        }
+
+        // Renaming back to an old slug makes it a page again
+        let result = storage
+            .rename_page(
+                str!(SITE),
+                str!("scp-173-final"),
+                str!("scp-173"),
+                &change(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, RenameResult::Renamed);
+
+        for page_slug in ["scp-173", "scp-173-new", "scp-173-final"] {
+            let stored = storage.get_page_attribution(SITE, page_slug).await.unwrap();
+            assert_eq!(stored, Some(credited.clone()));
+        }
+
+        let translations = storage
+            .get_page_translations(SITE, "scp-173")
+            .await
+            .unwrap();
+        assert_eq!(translations.translation_of, Some(page("scp-ru", "scp-173")));
+        assert_eq!(translations.translations, [page("scp-jp", "scp-173")]);
+        assert_eq!(user_pages(1).await, ["scp-173"]);
+
+        // The other page was never changed
+        let stored = storage.get_page_attribution(SITE, "scp-002").await.unwrap();
+        assert_eq!(stored, Some(attribution("Bob")));
+    }
+}
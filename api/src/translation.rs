@@ -0,0 +1,77 @@
+/*
+ * translation.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Links between translated pages and the originals they were translated from.
+//!
+//! Only the translation stores its original. The reverse list of translations
+//! of a page is found by looking up every translation which points to it.
+
+use crate::object::Attribution;
+
+/// Refers to a page on a particular site.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PageReference {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    #[serde(rename = "page")]
+    pub page_slug: String,
+}
+
+/// The translation links of a single page.
+#[derive(Serialize, Debug, Default)]
+pub struct PageTranslations {
+    /// The page this is a translation of, if any.
+    pub translation_of: Option<PageReference>,
+
+    /// All the translations of this page, in order of site and page.
+    pub translations: Vec<PageReference>,
+}
+
+/// The translation links of a page, along with the attribution of its original.
+///
+/// This lets a translation credit the original authors without a second request.
+#[derive(Serialize, Debug)]
+pub struct PageTranslationsOutput {
+    #[serde(flatten)]
+    pub translations: PageTranslations,
+
+    /// The attribution of the original page, if it has any.
+    pub original_attribution: Option<Attribution>,
+}
+
+/// The response for a page's attribution when its translations are also requested.
+#[derive(Serialize, Debug)]
+pub struct PageAttributionOutput {
+    pub attribution: Option<Attribution>,
+
+    #[serde(flatten)]
+    pub translations: PageTranslations,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateTranslationInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    #[serde(rename = "page")]
+    pub page_slug: String,
+
+    /// The site's regular password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub password: Option<String>,
+
+    /// The page this is a translation of, or `None` to remove the link.
+    pub translation_of: Option<PageReference>,
+}
@@ -0,0 +1,127 @@
+/*
+ * user.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Lookup of the pages a user is credited on, across all sites.
+//!
+//! Storage backends keep an index from each user ID to the pages it appears on,
+//! which is updated along with every write to a page. Entries without a user ID
+//! cannot be looked up this way, since user names are not unique or stable.
+
+use crate::object::{Attribution, AttributionType};
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+
+/// A single credit for a user on a page.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UserAttribution {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    #[serde(rename = "page")]
+    pub page_slug: String,
+
+    #[serde(rename = "type")]
+    pub attribution_type: AttributionType,
+
+    /// The user's name as given in this entry, which may differ between pages.
+    pub user_name: String,
+
+    pub date: Option<String>,
+}
+
+impl UserAttribution {
+    /// Gets the credits for a page from the entries of a single user.
+    pub fn from_page(
+        site_slug: &str,
+        page_slug: &str,
+        attribution: &Attribution,
+    ) -> Vec<UserAttribution> {
+        attribution
+            .0
+            .iter()
+            .map(|entry| UserAttribution {
+                site_slug: str!(site_slug),
+                page_slug: str!(page_slug),
+                attribution_type: entry.attribution_type,
+                user_name: entry.user_name.clone(),
+                date: entry.date.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Splits a page's attribution into the entries for each user ID.
+///
+/// Entries without a user ID are not included.
+pub fn entries_by_user(attribution: &Attribution) -> BTreeMap<NonZeroU32, Attribution> {
+    let mut users = BTreeMap::<_, Attribution>::new();
+
+    for entry in &attribution.0 {
+        if let Some(user_id) = entry.user_id {
+            users
+                .entry(user_id)
+                .or_insert_with(|| Attribution(Vec::new()))
+                .0
+                .push(entry.clone());
+        }
+    }
+
+    users
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::AttributionEntry;
+
+    fn entry(attribution_type: AttributionType, user_id: u32) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: format!("user-{user_id}"),
+            user_id: NonZeroU32::new(user_id),
+            date: None,
+        }
+    }
+
+    #[test]
+    fn split_by_user() {
+        let attribution = Attribution(vec![
+            entry(AttributionType::Author, 2),
+            entry(AttributionType::Rewrite, 0),
+            entry(AttributionType::Translator, 1),
+            entry(AttributionType::Maintainer, 2),
+        ]);
+
+        let users = entries_by_user(&attribution);
+        let user_ids: Vec<_> = users.keys().map(|id| id.get()).collect();
+        assert_eq!(user_ids, [1, 2]);
+
+        let entries = &users[&NonZeroU32::new(2).unwrap()];
+        assert_eq!(
+            entries.0,
+            [
+                entry(AttributionType::Author, 2),
+                entry(AttributionType::Maintainer, 2),
+            ],
+        );
+
+        let credits = UserAttribution::from_page("scp-wiki", "scp-173", entries);
+        assert_eq!(credits.len(), 2);
+        assert_eq!(credits[1].attribution_type, AttributionType::Maintainer);
+        assert_eq!(credits[1].user_name, "user-2");
+        assert!(credits.iter().all(
+            |credit| credit.site_slug == "scp-wiki" && credit.page_slug == "scp-173"
+        ));
+    }
+}
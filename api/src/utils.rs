@@ -13,18 +13,11 @@
 
 use crate::password::PasswordType;
 use crate::result::ServiceResult;
-use aws_sdk_dynamodb::Client as DynamoClient;
 use lambda_http::Error;
 use regex::Regex;
 use std::error::Error as StdError;
 use std::fmt::Display;
-
-// Setup
-
-pub async fn connect_dynamo_db() -> DynamoClient {
-    let config = aws_config::load_from_env().await;
-    DynamoClient::new(&config)
-}
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // ServiceResult output helpers
 
@@ -52,6 +45,30 @@ pub fn invalid_password(password_type: PasswordType) -> Result<String, Error> {
     Ok(body)
 }
 
+pub fn insufficient_scope(password_type: PasswordType) -> Result<String, Error> {
+    error!(
+        "Error, API key does not grant {} permissions",
+        password_type.field_name(),
+    );
+
+    let body = ServiceResult::error(
+        "insufficient-scope",
+        format!(
+            "The passed API key does not grant {} permissions",
+            password_type.field_name(),
+        ),
+    )
+    .to_json()?;
+
+    Ok(body)
+}
+
+pub fn rate_limited(retry_after: u64) -> Result<String, Error> {
+    error!("Error, too many invalid attempts, retry after {retry_after} seconds");
+    let body = ServiceResult::rate_limited(retry_after).to_json()?;
+    Ok(body)
+}
+
 pub fn input_error(error: &dyn Display) -> Result<String, Error> {
     error!("Error processing input: {error}");
     let body = ServiceResult::error("input-invalid", str!(error)).to_json()?;
@@ -73,3 +90,11 @@ pub fn replace_in_place(string: &mut String, regex: &Regex, replacement: &str) {
         string.replace_range(start..end, replacement);
     }
 }
+
+/// Gets the current time, in seconds since the UNIX epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before UNIX epoch")
+        .as_secs()
+}
@@ -0,0 +1,367 @@
+/*
+ * wikidot.rs
+ *
+ * attribution-metadata
+ * Copyright (C) 2023-2023 SCP-EN Technical Team
+ *
+ * attribution-metadata is available free of charge under the terms of the MIT
+ * License. You are free to redistribute and/or modify it under those
+ * terms. It is distributed in the hopes that it will be useful, but
+ * WITHOUT ANY WARRANTY. See the LICENSE file for more details.
+ *
+ */
+
+//! Rendering of attribution as Wikidot markup, for display on the wiki itself.
+//!
+//! Output is produced from per-site templates, in which placeholders such as
+//! `{user_name}` are substituted. Any template not set by a site uses the default.
+
+use crate::object::{Attribution, AttributionType, FullAttribution};
+
+/// The order attribution types are listed in.
+const TYPE_ORDER: [AttributionType; 4] = [
+    AttributionType::Author,
+    AttributionType::Rewrite,
+    AttributionType::Translator,
+    AttributionType::Maintainer,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct WikidotTemplate {
+    /// The attribution for a page, with `{groups}`.
+    pub page: String,
+
+    /// The users with one type of attribution, with `{label}` and `{users}`.
+    pub group: String,
+
+    /// A single user, with `{user_name}`, `{user_id}`, and `{date}`.
+    pub user: String,
+
+    /// The date of an entry, with `{date}`. Entries without a date use an empty string.
+    pub date: String,
+
+    /// Placed between users in the same group.
+    pub separator: String,
+
+    /// The name shown for each type of attribution.
+    pub labels: TypeLabels,
+
+    /// The attribution hub for a whole site, with `{pages}`.
+    pub hub: String,
+
+    /// A single page in the hub, with `{page_slug}` and `{attribution}`,
+    /// which is the page rendered with the `page` template.
+    pub hub_page: String,
+}
+
+impl Default for WikidotTemplate {
+    fn default() -> Self {
+        WikidotTemplate {
+            page: str!("[[div class=\"attribution\"]]\n{groups}[[/div]]\n"),
+            group: str!("**{label}:** {users}\n"),
+            user: str!("[[*user {user_name}]]{date}"),
+            date: str!(" ({date})"),
+            separator: str!(", "),
+            labels: TypeLabels::default(),
+            hub: str!("+ Attribution Metadata\n\n{pages}"),
+            hub_page: str!("++ [[[{page_slug}]]]\n{attribution}\n"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct TypeLabels {
+    pub author: String,
+    pub rewrite: String,
+    pub translator: String,
+    pub maintainer: String,
+}
+
+impl TypeLabels {
+    pub fn get(&self, attribution_type: AttributionType) -> &str {
+        match attribution_type {
+            AttributionType::Author => &self.author,
+            AttributionType::Rewrite => &self.rewrite,
+            AttributionType::Translator => &self.translator,
+            AttributionType::Maintainer => &self.maintainer,
+        }
+    }
+}
+
+impl Default for TypeLabels {
+    fn default() -> Self {
+        TypeLabels {
+            author: str!("Author"),
+            rewrite: str!("Rewrite"),
+            translator: str!("Translator"),
+            maintainer: str!("Maintainer"),
+        }
+    }
+}
+
+impl WikidotTemplate {
+    /// Renders a page's attribution, grouping users by the type of attribution.
+    pub fn render_page(&self, attribution: &Attribution) -> String {
+        let mut groups = String::new();
+
+        for attribution_type in TYPE_ORDER {
+            let users = attribution
+                .0
+                .iter()
+                .filter(|entry| entry.attribution_type == attribution_type)
+                .map(|entry| {
+                    let date = match entry.date {
+                        Some(ref date) => fill(&self.date, &[("date", date)]),
+                        None => String::new(),
+                    };
+
+                    let user_id =
+                        entry.user_id.map(|id| id.to_string()).unwrap_or_default();
+
+                    let user_name = escape_user_name(&entry.user_name);
+
+                    fill(
+                        &self.user,
+                        &[
+                            ("user_name", &user_name),
+                            ("user_id", &user_id),
+                            ("date", &date),
+                        ],
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            if !users.is_empty() {
+                let users = users.join(&self.separator);
+                let label = self.labels.get(attribution_type);
+                groups
+                    .push_str(&fill(&self.group, &[("label", label), ("users", &users)]));
+            }
+        }
+
+        fill(&self.page, &[("groups", &groups)])
+    }
+
+    /// Renders a single page's entry in the site hub.
+    pub fn render_hub_page(&self, attribution: &FullAttribution) -> String {
+        let rendered = self.render_page(&attribution.attribution);
+
+        fill(
+            &self.hub_page,
+            &[
+                ("page_slug", &attribution.page_slug),
+                ("attribution", &rendered),
+            ],
+        )
+    }
+
+    /// Gets the parts of the hub which go before and after the list of pages.
+    pub fn hub_parts(&self) -> (&str, &str) {
+        self.hub.split_once("{pages}").unwrap_or((&self.hub, ""))
+    }
+}
+
+/// Makes a user name safe to place inside a Wikidot module, such as `[[*user]]`.
+///
+/// Brackets and line breaks would end the module early, so they are replaced with
+/// `-`. Wikidot looks users up by their unix name, which already treats any
+/// punctuation as `-`, so the same user is still found.
+pub fn escape_user_name(user_name: &str) -> String {
+    user_name.replace(['[', ']', '\r', '\n'], "-")
+}
+
+/// Replaces each `{name}` placeholder in a template with its value.
+///
+/// Substituted values are not themselves searched for placeholders,
+/// and any unknown placeholders are left as-is.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value, end))
+        });
+
+        match value {
+            Some((value, end)) => {
+                output.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateWikidotTemplateInput {
+    #[serde(rename = "site")]
+    pub site_slug: String,
+
+    /// The site's admin password.
+    ///
+    /// May be omitted if a session token is passed instead.
+    pub admin_password: Option<String>,
+
+    /// The new template. Any fields not given use the default.
+    pub template: WikidotTemplate,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::AttributionEntry;
+    use std::num::NonZeroU32;
+
+    fn entry(attribution_type: AttributionType, user_name: &str) -> AttributionEntry {
+        AttributionEntry {
+            id: None,
+            attribution_type,
+            user_name: str!(user_name),
+            user_id: None,
+            date: None,
+        }
+    }
+
+    #[test]
+    fn each_type() {
+        let template = WikidotTemplate::default();
+
+        for attribution_type in TYPE_ORDER {
+            let attribution = Attribution(vec![entry(attribution_type, "Moto42")]);
+            let label = template.labels.get(attribution_type);
+
+            assert_eq!(
+                template.render_page(&attribution),
+                format!(
+                    "[[div class=\"attribution\"]]\n**{label}:** [[*user Moto42]]\n[[/div]]\n",
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn grouped_in_order() {
+        let template = WikidotTemplate {
+            user: str!("{user_name}#{user_id}{date}"),
+            ..WikidotTemplate::default()
+        };
+
+        let mut dated = entry(AttributionType::Author, "Moto42");
+        dated.date = Some(str!("2008-07-25"));
+        dated.user_id = NonZeroU32::new(42);
+
+        let attribution = Attribution(vec![
+            entry(AttributionType::Maintainer, "Carol"),
+            dated,
+            entry(AttributionType::Translator, "Dave"),
+            entry(AttributionType::Rewrite, "Bob"),
+            entry(AttributionType::Author, "Alice"),
+        ]);
+
+        assert_eq!(
+            template.render_page(&attribution),
+            "[[div class=\"attribution\"]]\n\
+             **Author:** Moto42#42 (2008-07-25), Alice#\n\
+             **Rewrite:** Bob#\n\
+             **Translator:** Dave#\n\
+             **Maintainer:** Carol#\n\
+             [[/div]]\n",
+        );
+    }
+
+    #[test]
+    fn custom_labels() {
+        let template = WikidotTemplate {
+            page: str!("{groups}"),
+            group: str!("{label}={users};"),
+            labels: TypeLabels {
+                author: str!("Auteur"),
+                translator: str!("Traducteur"),
+                ..TypeLabels::default()
+            },
+            ..WikidotTemplate::default()
+        };
+
+        let attribution = Attribution(vec![
+            entry(AttributionType::Author, "Alice"),
+            entry(AttributionType::Translator, "Bob"),
+        ]);
+
+        assert_eq!(
+            template.render_page(&attribution),
+            "Auteur=[[*user Alice]];Traducteur=[[*user Bob]];",
+        );
+    }
+
+    #[test]
+    fn markup_in_user_name() {
+        let template = WikidotTemplate {
+            page: str!("{groups}"),
+            group: str!("{users}"),
+            ..WikidotTemplate::default()
+        };
+
+        let check = |user_name, expected| {
+            let attribution =
+                Attribution(vec![entry(AttributionType::Author, user_name)]);
+            assert_eq!(template.render_page(&attribution), expected);
+        };
+
+        check("]] [[module Css]]", "[[*user -- --module Css--]]");
+        check("line\nbreak", "[[*user line-break]]");
+
+        // Placeholders and other markup in a name are not interpreted
+        check("{date}", "[[*user {date}]]");
+        check("**bold** //name//", "[[*user **bold** //name//]]");
+        check("@@raw@@ \"quoted\"", "[[*user @@raw@@ \"quoted\"]]");
+    }
+
+    #[test]
+    fn hub() {
+        let template = WikidotTemplate::default();
+        assert_eq!(template.hub_parts(), ("+ Attribution Metadata\n\n", ""));
+
+        let attribution = FullAttribution {
+            page_slug: str!("scp-173"),
+            attribution: Attribution(vec![entry(AttributionType::Author, "Moto42")]),
+        };
+
+        assert_eq!(
+            template.render_hub_page(&attribution),
+            "++ [[[scp-173]]]\n\
+             [[div class=\"attribution\"]]\n**Author:** [[*user Moto42]]\n[[/div]]\n\n",
+        );
+
+        // Without a placeholder, all of the hub goes before the pages
+        let template = WikidotTemplate {
+            hub: str!("Header"),
+            ..WikidotTemplate::default()
+        };
+        assert_eq!(template.hub_parts(), ("Header", ""));
+    }
+
+    #[test]
+    fn fill_placeholders() {
+        let values = [("a", "1"), ("b", "{a}")];
+        assert_eq!(fill("{a}{b}", &values), "1{a}");
+        assert_eq!(fill("{unknown} {a", &values), "{unknown} {a");
+        assert_eq!(fill("{{a}}", &values), "{1}");
+        assert_eq!(fill("", &values), "");
+    }
+}
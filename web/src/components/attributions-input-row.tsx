@@ -16,6 +16,7 @@ export default function AttributionsInputRow({ attribution, onRemove }: Attribut
   return (
     <tr class="attributions-input-row">
       <td>
+        <input type="hidden" name="attributions.id" value={attribution.id ?? ""} />
         <select name="attributions.type" defaultValue={attribution.type} required>
           <option value="author">{message("attribution-type-author")}</option>
           <option value="rewrite">{message("attribution-type-rewrite")}</option>
@@ -3,6 +3,7 @@ import { useState } from "preact/hooks";
 
 import { PageInfo, getPageInfo } from "../util/crom-api";
 import {
+  ConflictError,
   PageAttribution,
   SITES,
   getPageAttributions,
@@ -28,6 +29,8 @@ export default function AttributionMetadataPage({ initialSiteName }: Attribution
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string>();
   const [page, setPage] = useState<Page>();
+  // Kept separately from the page, so that saving doesn't reset the form
+  const [etag, setEtag] = useState<string | null>(null);
 
   const onGetPageFormSubmit = async (event: JSX.TargetedEvent<HTMLFormElement>) => {
     event.preventDefault();
@@ -39,11 +42,12 @@ export default function AttributionMetadataPage({ initialSiteName }: Attribution
     setLoading(true);
     setPage(undefined);
     try {
-      const [pageInfo, pageAttributions] = await Promise.all([
+      const [pageInfo, { attributions: pageAttributions, etag: pageEtag }] = await Promise.all([
         getPageInfo(site, slug),
         getPageAttributions(site, slug),
       ]);
       setPage({ site, slug, pageInfo, pageAttributions });
+      setEtag(pageEtag);
     } finally {
       setLoading(false);
     }
@@ -56,6 +60,7 @@ export default function AttributionMetadataPage({ initialSiteName }: Attribution
     const site = formData.get("site") as string;
     const slug = formData.get("slug") as string;
     const password = formData.get("password") as string;
+    const ids = formData.getAll("attributions.id") as string[];
     const types = formData.getAll("attributions.type") as string[];
     const user_names = formData.getAll("attributions.user_name") as string[];
     const user_ids = formData.getAll("attributions.user_id") as string[];
@@ -63,6 +68,7 @@ export default function AttributionMetadataPage({ initialSiteName }: Attribution
 
     const attributions: PageAttribution[] = Array.from({ length: types.length }, (_v, i) => {
       return {
+        id: ids[i] || null,
         type: types[i]!,
         user_name: user_names[i]!,
         user_id: user_ids[i] ? parseInt(user_ids[i]!) : null,
@@ -72,10 +78,14 @@ export default function AttributionMetadataPage({ initialSiteName }: Attribution
 
     setLoading(true);
     try {
-      await updatePageAttributions(site, slug, password, attributions);
+      setEtag(await updatePageAttributions(site, slug, password, attributions, etag));
       setError(undefined);
     } catch (err) {
-      setError((err as Error).message);
+      if (err instanceof ConflictError) {
+        setError(message("attribution-conflict"));
+      } else {
+        setError((err as Error).message);
+      }
     } finally {
       setLoading(false);
     }
@@ -22,25 +22,42 @@ export const SITES = [
 ];
 
 export interface PageAttribution {
+  id?: string | null;
   type: string;
   user_name: string;
   user_id: number | null;
   date: string | null;
 }
 
-async function request(method: string, path: string, data?: any): Promise<Response> {
+export interface PageAttributionsResult {
+  attributions: PageAttribution[] | null;
+  etag: string | null;
+}
+
+/** Thrown when a page was changed by someone else since it was loaded. */
+export class ConflictError extends Error {}
+
+async function request(
+  method: string,
+  path: string,
+  data?: any,
+  headers: Record<string, string> = {},
+): Promise<Response> {
   let response: Response;
   const url = new URL(path, ATTRIBUTIONS_ENDPOINT);
   if (method === "GET") {
     url.search = new URLSearchParams(data).toString();
-    response = await fetch(url, { method });
+    response = await fetch(url, { method, headers });
   } else {
     response = await fetch(url, {
       method,
-      headers: { "content-type": "application/json" },
+      headers: { ...headers, "content-type": "application/json" },
       body: JSON.stringify(data),
     });
   }
+  if (response.status === 412) {
+    throw new ConflictError(await response.text());
+  }
   if (!response.ok) {
     throw new Error(await response.text());
   }
@@ -70,21 +87,29 @@ export async function updatePassword(
 export async function getPageAttributions(
   site: string,
   page: string,
-): Promise<PageAttribution[] | null> {
+): Promise<PageAttributionsResult> {
   const response = await request("GET", "/attribution/page", { site, page });
-  return response.json();
+  return { attributions: await response.json(), etag: response.headers.get("etag") };
 }
 
+/**
+ * Saves a page's attributions, returning its new ETag.
+ *
+ * If `etag` is given, this throws a `ConflictError` if the page has changed since.
+ */
 export async function updatePageAttributions(
   site: string,
   page: string,
   password: string,
   attributions: PageAttribution[],
-) {
-  await request("PUT", "/attribution/page", {
-    site,
-    page,
-    password,
-    attributions,
-  });
+  etag: string | null,
+): Promise<string | null> {
+  const headers: Record<string, string> = etag ? { "if-match": etag } : {};
+  const response = await request(
+    "PUT",
+    "/attribution/page",
+    { site, page, password, attributions },
+    headers,
+  );
+  return response.headers.get("etag");
 }
@@ -16,6 +16,8 @@ const DEFAULT_TRANSLATIONS = {
   "password-label": "Password",
   "save-button": "Save",
   error: "Error:",
+  "attribution-conflict":
+    "Someone else has changed this page's attributions since you opened it. Open the page again to see their changes before saving.",
 
   "attribution-type-label": "Type",
   "attribution-name-label": "Name",